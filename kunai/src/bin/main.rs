@@ -24,6 +24,8 @@ use kunai::events::{
 };
 use kunai::info::{AdditionalInfo, ProcKey, StdEventInfo, TaskAdditionalInfo};
 use kunai::ioc::IoC;
+use kunai::util::caps::{check_required_any_capabilities, Capability};
+use kunai::util::meminfo;
 use kunai::util::uname::Utsname;
 
 use kunai::yara::{Scanner, SourceCode};
@@ -586,7 +588,7 @@ impl EventConsumer<'_> {
             let line = line?;
             let ioc: IoC = serde_json::from_str(&line)?;
             self.iocs
-                .entry(ioc.value)
+                .entry(ioc.normalized_value())
                 .and_modify(|e| *e = max(*e, ioc.severity))
                 .or_insert(ioc.severity);
         }
@@ -2289,6 +2291,25 @@ const fn optimal_page_count(page_size: usize, max_event_size: usize, n_events: u
     ((max_event_size * n_events) / page_size).next_power_of_two()
 }
 
+// fraction of available memory perf buffers of all CPUs can use
+const PERF_BUFFERS_MEM_FRACTION: f64 = 0.1;
+
+/// Returns the page count to use for the perf buffer of each CPU, divided
+/// by two as long as all the buffers do not fit in a fraction of the
+/// available memory. It is kept a power of two and never goes below one page.
+fn mem_capped_page_count(page_count: usize, ncpus: usize) -> usize {
+    let Ok(budget) = meminfo::fraction_of_available(PERF_BUFFERS_MEM_FRACTION, 0, u64::MAX) else {
+        return page_count;
+    };
+    let max_pages = budget as usize / PAGE_SIZE / ncpus.max(1);
+
+    let mut page_count = page_count;
+    while page_count > max_pages.max(1) {
+        page_count /= 2;
+    }
+    page_count
+}
+
 impl EventProducer {
     pub fn with_params(
         bpf: &mut Ebpf,
@@ -2491,6 +2512,14 @@ impl EventProducer {
         // we choose what task will handle the reduce process (handle piped events)
         let leader_cpu_id = online_cpus[0];
         let config = self.config.clone();
+        let page_count = mem_capped_page_count(
+            optimal_page_count(
+                PAGE_SIZE,
+                MAX_BPF_EVENT_SIZE,
+                config.max_buffered_events as usize,
+            ),
+            online_cpus.len(),
+        );
 
         let shared = Arc::new(Mutex::new(self));
 
@@ -2526,14 +2555,7 @@ impl EventProducer {
                 .lock()
                 .await
                 .ebpf_perf_array
-                .open(
-                    cpu_id,
-                    Some(optimal_page_count(
-                        PAGE_SIZE,
-                        MAX_BPF_EVENT_SIZE,
-                        config.max_buffered_events as usize,
-                    )),
-                )
+                .open(cpu_id, Some(page_count))
                 .expect("cannot open perf event buffer");
             let event_producer = shared.clone();
             let bar = barrier.clone();
@@ -3424,6 +3446,19 @@ impl Command {
             None => Config::default(),
         };
 
+        // being root is not enough (ex: in containers) so we check capabilities
+        // early rather than failing with EPERM while loading eBPF programs.
+        // CAP_SYS_ADMIN is still accepted by kernels knowing CAP_BPF and CAP_PERFMON
+        let required_caps: &[&[Capability]] = if current_kernel >= kernel!(5, 8, 0) {
+            &[
+                &[Capability::Bpf, Capability::Perfmon],
+                &[Capability::SysAdmin],
+            ]
+        } else {
+            &[&[Capability::SysAdmin]]
+        };
+        check_required_any_capabilities(required_caps)?;
+
         // we set RLIMIT_MEMLOCK programmatically otherwise kunai fails at starting
         // as a service on old kernels, even though securityfs has been set properly.
         // This is very likely because securityfs isn't mounted when kunai starts
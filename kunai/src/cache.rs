@@ -11,8 +11,10 @@ use std::{
     borrow::Cow,
     fs::File,
     io::{self, BufReader, Read},
-    os::unix::prelude::MetadataExt,
+    os::fd::{AsFd, AsRawFd, BorrowedFd},
     path::PathBuf,
+    sync::{mpsc, Arc, Mutex},
+    thread,
     time::SystemTime,
 };
 use thiserror::Error;
@@ -20,7 +22,11 @@ use thiserror::Error;
 use crate::{
     util::{
         account::{Group, Groups, User, Users},
-        namespace::{self, Mnt, Switcher},
+        is_regular_file,
+        namespace::{self, Mnt, Namespace, Switcher},
+        normalize_hash, num_online_cpus, open_and_stat, read_chunks,
+        walk::{TreeWalker, DEFAULT_MAX_DEPTH},
+        HashAlgo,
     },
     yara::Scanner,
 };
@@ -141,6 +147,66 @@ impl Hashes {
         h
     }
 
+    /// Hashes all the data read from `r` with the selected algorithms,
+    /// digests of algorithms not selected are left empty.
+    pub fn from_reader<R: Read>(path: PathBuf, r: R, algos: &[HashAlgo]) -> io::Result<Self> {
+        let mut h = Hashes {
+            path,
+            ..Hashes::default()
+        };
+        let sel = |a| algos.contains(&a);
+        let mut md5 = sel(HashAlgo::Md5).then(Md5::new);
+        let mut sha1 = sel(HashAlgo::Sha1).then(Sha1::new);
+        let mut sha256 = sel(HashAlgo::Sha256).then(Sha256::new);
+        let mut sha512 = sel(HashAlgo::Sha512).then(Sha512::new);
+
+        read_chunks(r, |chunk| {
+            md5.iter_mut().for_each(|d| d.update(chunk));
+            sha1.iter_mut().for_each(|d| d.update(chunk));
+            sha256.iter_mut().for_each(|d| d.update(chunk));
+            sha512.iter_mut().for_each(|d| d.update(chunk));
+            h.size += chunk.len();
+        })?;
+
+        h.md5 = md5.map(|d| hex::encode(d.finalize())).unwrap_or_default();
+        h.sha1 = sha1.map(|d| hex::encode(d.finalize())).unwrap_or_default();
+        h.sha256 = sha256
+            .map(|d| hex::encode(d.finalize()))
+            .unwrap_or_default();
+        h.sha512 = sha512
+            .map(|d| hex::encode(d.finalize()))
+            .unwrap_or_default();
+
+        Ok(h)
+    }
+
+    /// Returns the hex digest computed with `algo`, empty if not computed
+    #[inline]
+    pub fn digest(&self, algo: HashAlgo) -> &str {
+        match algo {
+            HashAlgo::Md5 => &self.md5,
+            HashAlgo::Sha1 => &self.sha1,
+            HashAlgo::Sha256 => &self.sha256,
+            HashAlgo::Sha512 => &self.sha512,
+        }
+    }
+
+    #[inline]
+    fn digest_mut(&mut self, algo: HashAlgo) -> &mut String {
+        match algo {
+            HashAlgo::Md5 => &mut self.md5,
+            HashAlgo::Sha1 => &mut self.sha1,
+            HashAlgo::Sha256 => &mut self.sha256,
+            HashAlgo::Sha512 => &mut self.sha512,
+        }
+    }
+
+    /// Returns true if all digests are available or if hashing failed
+    #[inline]
+    fn is_complete(&self) -> bool {
+        self.error.is_some() || HashAlgo::ALL.iter().all(|a| !self.digest(*a).is_empty())
+    }
+
     #[inline(always)]
     pub(crate) fn iocs(&self) -> Vec<Cow<'_, str>> {
         vec![
@@ -153,6 +219,143 @@ impl Hashes {
     }
 }
 
+/// Hashes all the data read from `fd` until EOF with `algos`, results are
+/// the same as the ones of a file with identical content. The descriptor is
+/// duplicated so `fd` is left open, its offset is shared though. The path of
+/// the returned [Hashes] is /dev/fd/<fd>.
+pub fn hash_fd(fd: BorrowedFd<'_>, algos: &[HashAlgo]) -> io::Result<Hashes> {
+    let path = PathBuf::from(format!("/dev/fd/{}", fd.as_raw_fd()));
+    let f = File::from(fd.try_clone_to_owned()?);
+    Hashes::from_reader(path, BufReader::new(f), algos)
+}
+
+/// Hashes the data read from the standard input until EOF (see [hash_fd])
+#[inline]
+pub fn hash_stdin(algos: &[HashAlgo]) -> io::Result<Hashes> {
+    hash_fd(io::stdin().as_fd(), algos)
+}
+
+/// Outcome of the verification of a manifest entry
+#[derive(Debug)]
+pub enum VerifyResult {
+    Ok(PathBuf),
+    Mismatch {
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+    Missing(PathBuf),
+    Error(PathBuf, io::Error),
+}
+
+impl VerifyResult {
+    #[inline]
+    pub fn path(&self) -> &std::path::Path {
+        match self {
+            Self::Ok(p) | Self::Missing(p) | Self::Error(p, _) => p,
+            Self::Mismatch { path, .. } => path,
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_ok(&self) -> bool {
+        matches!(self, Self::Ok(_))
+    }
+}
+
+/// Compares `a` and `b` in a time independent of their content
+#[inline]
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Options controlling [hash_tree]
+#[derive(Debug, Clone)]
+pub struct HashTreeOptions {
+    /// maximum number of directories to descend below a root, deeper
+    /// directories are yielded as errors
+    pub max_depth: usize,
+    /// maximum number of files hashed concurrently, the number
+    /// of online CPUs is used if `None`
+    pub concurrency: Option<usize>,
+}
+
+impl Default for HashTreeOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_MAX_DEPTH,
+            concurrency: None,
+        }
+    }
+}
+
+/// Walks `roots` and hashes all the regular files found with `algos` in
+/// a pool of worker threads. Results are yielded in no particular order
+/// as soon as they are available. Errors (unreadable directory or file)
+/// are yielded along with the path they relate to without stopping the
+/// walk. Dropping the iterator stops the workers.
+pub fn hash_tree(
+    roots: &[PathBuf],
+    algos: &[HashAlgo],
+    opts: HashTreeOptions,
+) -> impl Iterator<Item = (PathBuf, io::Result<Hashes>)> {
+    let workers = opts
+        .concurrency
+        .unwrap_or_else(|| num_online_cpus().unwrap_or(1))
+        .max(1);
+
+    // bounded channels so that neither the walk nor the workers
+    // run too far ahead of the consumer
+    let (path_tx, path_rx) = mpsc::sync_channel::<PathBuf>(workers);
+    let (res_tx, res_rx) = mpsc::sync_channel(workers);
+    let path_rx = Arc::new(Mutex::new(path_rx));
+
+    let walker = TreeWalker::new(roots.to_vec(), opts.max_depth);
+    let walk_tx = res_tx.clone();
+    thread::spawn(move || {
+        for (path, meta) in walker {
+            let sent = match meta {
+                Ok(m) if m.is_file() => path_tx.send(path).is_ok(),
+                // only regular files are hashed
+                Ok(_) => true,
+                Err(e) => walk_tx.send((path, Err(e))).is_ok(),
+            };
+            // the consumer has gone away
+            if !sent {
+                break;
+            }
+        }
+    });
+
+    for _ in 0..workers {
+        let rx = Arc::clone(&path_rx);
+        let tx = res_tx.clone();
+        let algos = algos.to_vec();
+        thread::spawn(move || loop {
+            // lock is released as soon as a path is received
+            let next = rx.lock().ok().and_then(|rx| rx.recv().ok());
+            let Some(path) = next else {
+                break;
+            };
+            // the file may have been replaced since it has been walked
+            let h = open_and_stat(&path).and_then(|(f, st)| {
+                if !is_regular_file(&st) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "not a regular file",
+                    ));
+                }
+                Hashes::from_reader(path.clone(), BufReader::new(f), &algos)
+            });
+            if tx.send((path, h)).is_err() {
+                break;
+            }
+        });
+    }
+
+    res_rx.into_iter()
+}
+
 /// Path enum used as a generic interface to handle both
 /// path comming from eBPF and path comming from std lib
 #[derive(Debug)]
@@ -199,10 +402,14 @@ impl Path {
 struct Key {
     mnt_namespace: Mnt,
     path: PathBuf,
+    ino: u64,
     size: u64,
     modified: SystemTime,
     created: SystemTime,
-    accessed: SystemTime,
+    // unlike mtime, ctime cannot be restored by users (ex: touch -r) so it
+    // reliably changes whenever the file is. The access time is not part of
+    // the key as reading the file to hash it may update it.
+    changed: SystemTime,
 }
 
 impl Default for Key {
@@ -210,33 +417,31 @@ impl Default for Key {
         Key {
             mnt_namespace: Mnt::default(),
             path: PathBuf::default(),
+            ino: 0,
             size: 0,
             modified: SystemTime::UNIX_EPOCH,
             created: SystemTime::UNIX_EPOCH,
-            accessed: SystemTime::UNIX_EPOCH,
+            changed: SystemTime::UNIX_EPOCH,
         }
     }
 }
 
 impl Key {
+    /// Opens `path` and builds its key from the opened file (see [open_and_stat])
+    /// so that the key always describes the content read from the returned file.
     #[inline(always)]
-    fn from_path_in_ns(ns: Mnt, path: &Path) -> Result<Self, Error> {
+    fn open_in_ns(ns: Mnt, path: &Path) -> Result<(File, Self), Error> {
         let pb = path.to_path_buf();
 
-        // checking if the file still exists
-        if !pb.exists() {
-            // return a io::Error instead of custom error
-            return Err(io::Error::new(io::ErrorKind::NotFound, "file not found").into());
-        }
-
-        let meta = pb.metadata()?;
+        let (f, st) = open_and_stat(pb)?;
 
         let k = Key {
             mnt_namespace: ns,
             path: pb.clone(),
-            size: meta.size(),
-            modified: SystemTime::from(&Time::new(meta.mtime(), meta.mtime_nsec())),
-            accessed: SystemTime::from(&Time::new(meta.atime(), meta.atime_nsec())),
+            ino: st.st_ino,
+            size: st.st_size as u64,
+            modified: SystemTime::from(&Time::new(st.st_mtime, st.st_mtime_nsec)),
+            changed: SystemTime::from(&Time::new(st.st_ctime, st.st_ctime_nsec)),
             ..Default::default()
         };
 
@@ -246,22 +451,25 @@ impl Key {
             // we don't have to switch to ns here as it is done in caller
             let ebpf_meta = ebpf_meta.ok_or(Error::MetadataRequired)?;
 
-            if k.size != meta.size() {
+            if k.size != st.st_size as u64 {
                 return Err(Error::FileModSinceKernelEvent("size changed"));
             }
 
-            if ebpf_meta.ino != meta.ino() {
+            if ebpf_meta.ino != st.st_ino {
                 return Err(Error::FileModSinceKernelEvent("inode changed"));
             }
 
-            if let Ok(mtime) = meta.modified() {
-                if mtime != k.modified {
-                    return Err(Error::FileModSinceKernelEvent("mtime changed"));
-                }
+            if SystemTime::from(&Time::new(st.st_mtime, st.st_mtime_nsec)) != k.modified {
+                return Err(Error::FileModSinceKernelEvent("mtime changed"));
             }
         }
 
-        Ok(k)
+        Ok((f, k))
+    }
+
+    #[inline(always)]
+    fn from_path_in_ns(ns: Mnt, path: &Path) -> Result<Self, Error> {
+        Self::open_in_ns(ns, path).map(|(_, k)| k)
     }
 }
 
@@ -278,6 +486,8 @@ pub struct Cache {
     // to separate into two HashMaps to prevent
     // any race
     signatures: LruHashMap<Key, Vec<String>>,
+    #[cfg(test)]
+    verify_hash_count: usize,
 }
 
 const NS_CACHE_SIZE: usize = 256;
@@ -291,6 +501,8 @@ impl Cache {
             groups: LruHashMap::with_max_entries(NS_CACHE_SIZE),
             hashes: LruHashMap::with_max_entries(cap),
             signatures: LruHashMap::with_max_entries(cap),
+            #[cfg(test)]
+            verify_hash_count: 0,
         }
     }
 
@@ -426,10 +638,17 @@ impl Cache {
         let res = mnt_ns.do_in_namespace(|| {
             let pb = path.to_path_buf();
 
-            let key = Key::from_path_in_ns(ns, path).map_err(namespace::Error::other)?;
-
-            if !self.hashes.contains_key(&key) {
-                let h = Hashes::from_path_ref(pb);
+            let (f, key) = Key::open_in_ns(ns, path).map_err(namespace::Error::other)?;
+
+            // entries cached by a verification may lack some digests
+            if !self.hashes.get(&key).is_some_and(|h| h.is_complete()) {
+                // errors do not prevent caching, as it has always been
+                let h = Hashes::from_reader(pb.clone(), BufReader::new(f), &HashAlgo::ALL)
+                    .unwrap_or_else(|e| Hashes {
+                        path: pb.clone(),
+                        error: Some(e.to_string()),
+                        ..Default::default()
+                    });
                 self.hashes.insert(key.clone(), h);
             }
 
@@ -445,3 +664,283 @@ impl Cache {
         res.map_err(Error::from)
     }
 }
+
+impl Cache {
+    /// Gets the hashes of `path` in the mount namespace of the current process
+    /// making sure the ones of `algo` are available. Only `algo` is computed when
+    /// missing, and merged into the cached entry. Unlike [Cache::get_hashes_in_ns]
+    /// hashing errors are returned and not cached.
+    fn get_hashes_for_verify(
+        &mut self,
+        ns: Mnt,
+        path: &Path,
+        algo: HashAlgo,
+    ) -> Result<Hashes, Error> {
+        let (f, key) = Key::open_in_ns(ns, path)?;
+
+        let cached = self.hashes.get(&key).filter(|h| h.error.is_none()).cloned();
+        if let Some(h) = cached.as_ref() {
+            if !h.digest(algo).is_empty() {
+                return Ok(h.clone());
+            }
+        }
+
+        let fresh = Hashes::from_reader(key.path.clone(), BufReader::new(f), &[algo])?;
+        #[cfg(test)]
+        {
+            self.verify_hash_count += 1;
+        }
+
+        let h = match cached {
+            Some(mut h) => {
+                *h.digest_mut(algo) = fresh.digest(algo).into();
+                h
+            }
+            None => fresh,
+        };
+        self.hashes.insert(key, h.clone());
+        Ok(h)
+    }
+
+    /// Verifies the files of a manifest made of (path, algorithm, expected hex
+    /// digest) entries, in the mount namespace of the current process. Expected
+    /// digests are normalized (see [normalize_hash]) and compared in constant
+    /// time, entries with an invalid digest are reported as errors. Hashes are
+    /// kept in the cache so that files unchanged (same inode, size, mtime and
+    /// ctime) since a previous verification are not hashed again. The only
+    /// error returned is the failure to identify the current mount namespace,
+    /// errors related to an entry are reported in its [VerifyResult].
+    pub fn verify_manifest(
+        &mut self,
+        entries: &[(PathBuf, HashAlgo, String)],
+    ) -> io::Result<Vec<VerifyResult>> {
+        let ns = Mnt::from_pid::<Mnt>(std::process::id()).map_err(io::Error::other)?;
+
+        Ok(entries
+            .iter()
+            .map(|(path, algo, expected)| {
+                let Some(normalized) = normalize_hash(expected) else {
+                    return VerifyResult::Error(
+                        path.clone(),
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("invalid expected hash: {expected}"),
+                        ),
+                    );
+                };
+
+                let p = Path::from(path.clone());
+                match self.get_hashes_for_verify(ns, &p, *algo) {
+                    Ok(h) => {
+                        let actual = h.digest(*algo);
+                        if constant_time_eq(actual.as_bytes(), normalized.as_bytes()) {
+                            VerifyResult::Ok(path.clone())
+                        } else {
+                            VerifyResult::Mismatch {
+                                path: path.clone(),
+                                expected: expected.clone(),
+                                actual: actual.into(),
+                            }
+                        }
+                    }
+                    Err(Error::IoError(e)) if e.kind() == io::ErrorKind::NotFound => {
+                        VerifyResult::Missing(path.clone())
+                    }
+                    Err(Error::IoError(e)) => VerifyResult::Error(path.clone(), e),
+                    Err(e) => VerifyResult::Error(path.clone(), io::Error::other(e)),
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        fs,
+        io::Write,
+        os::{fd::FromRawFd, fd::OwnedFd, unix::fs::symlink},
+    };
+
+    use crate::util::{md5_data, sha256_data};
+
+    use super::*;
+
+    #[test]
+    fn test_verify_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let good = dir.path().join("good");
+        let tampered = dir.path().join("tampered");
+        let missing = dir.path().join("missing");
+        fs::write(&good, "trusted").unwrap();
+        fs::write(&tampered, "trusted").unwrap();
+
+        let manifest = vec![
+            (good.clone(), HashAlgo::Sha256, sha256_data("trusted")),
+            (
+                tampered.clone(),
+                HashAlgo::Md5,
+                md5_data("trusted").to_uppercase(),
+            ),
+            (missing.clone(), HashAlgo::Sha256, sha256_data("trusted")),
+        ];
+
+        let mut cache = Cache::with_max_entries(16);
+
+        // expected digests coming from external sources
+        let res = cache
+            .verify_manifest(&[
+                (
+                    good.clone(),
+                    HashAlgo::Sha256,
+                    format!("0x{}\n", sha256_data("trusted")),
+                ),
+                (good.clone(), HashAlgo::Sha256, "not a hash".into()),
+            ])
+            .unwrap();
+        assert!(res[0].is_ok());
+        assert!(
+            matches!(&res[1], VerifyResult::Error(_, e) if e.kind() == io::ErrorKind::InvalidInput)
+        );
+
+        let mut cache = Cache::with_max_entries(16);
+        let res = cache.verify_manifest(&manifest).unwrap();
+        assert!(res.iter().take(2).all(VerifyResult::is_ok));
+        assert!(matches!(&res[2], VerifyResult::Missing(p) if p == &missing));
+        assert_eq!(cache.verify_hash_count, 2);
+
+        // unchanged files are not hashed again
+        let res = cache.verify_manifest(&manifest).unwrap();
+        assert!(res.iter().take(2).all(VerifyResult::is_ok));
+        assert_eq!(cache.verify_hash_count, 2);
+
+        // tampering at the same size while restoring times, ctime
+        // granularity may be coarse so we make sure it changes
+        let meta = fs::metadata(&good).unwrap();
+        thread::sleep(std::time::Duration::from_millis(20));
+        fs::write(&good, "trustee").unwrap();
+        File::options()
+            .write(true)
+            .open(&good)
+            .unwrap()
+            .set_times(
+                fs::FileTimes::new()
+                    .set_accessed(meta.accessed().unwrap())
+                    .set_modified(meta.modified().unwrap()),
+            )
+            .unwrap();
+        let res = cache.verify_manifest(&manifest).unwrap();
+        assert!(matches!(&res[0], VerifyResult::Mismatch { .. }));
+        assert_eq!(cache.verify_hash_count, 3);
+        fs::write(&good, "trusted").unwrap();
+
+        // only the requested algorithm is computed, others are merged
+        let ns = Mnt::from_pid::<Mnt>(std::process::id()).unwrap();
+        let h = cache
+            .get_hashes_for_verify(ns, &Path::from(good.clone()), HashAlgo::Sha256)
+            .unwrap();
+        assert_eq!(h.sha256, sha256_data("trusted"));
+        assert!(h.md5.is_empty() && h.sha1.is_empty() && h.sha512.is_empty());
+        let h = cache
+            .get_hashes_for_verify(ns, &Path::from(good.clone()), HashAlgo::Md5)
+            .unwrap();
+        assert_eq!(h.md5, md5_data("trusted"));
+        assert_eq!(h.sha256, sha256_data("trusted"));
+        assert!(!h.is_complete());
+
+        fs::write(&tampered, "malicious").unwrap();
+        let res = cache.verify_manifest(&manifest).unwrap();
+        assert!(res[0].is_ok());
+        match &res[1] {
+            VerifyResult::Mismatch { path, actual, .. } => {
+                assert_eq!(path, &tampered);
+                assert_eq!(actual, &md5_data("malicious"));
+            }
+            r => panic!("unexpected result: {r:?}"),
+        }
+        assert_eq!(res[2].path(), missing);
+
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+    }
+
+    #[test]
+    fn test_hash_fd() {
+        let data = (0..100_000u32)
+            .flat_map(|i| i.to_le_bytes())
+            .collect::<Vec<u8>>();
+
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (rx, tx) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+
+        // data is larger than the pipe buffer so we must write concurrently
+        let writer = {
+            let data = data.clone();
+            thread::spawn(move || File::from(tx).write_all(&data).unwrap())
+        };
+        let h = hash_fd(rx.as_fd(), &HashAlgo::ALL).unwrap();
+        writer.join().unwrap();
+
+        assert_eq!(h.sha256, sha256_data(&data));
+        assert_eq!(h.md5, md5_data(&data));
+        assert_eq!(h.size, data.len());
+
+        // same results as for a file
+        let dir = tempfile::tempdir().unwrap();
+        let p = dir.path().join("data");
+        fs::write(&p, &data).unwrap();
+        let fh = Hashes::from_path_ref(&p);
+        assert_eq!(
+            (h.md5, h.sha1, h.sha256, h.sha512),
+            (fh.md5, fh.sha1, fh.sha256, fh.sha512)
+        );
+    }
+
+    #[test]
+    fn test_hash_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("bin/sub")).unwrap();
+        for i in 0..16 {
+            fs::write(root.join(format!("bin/f{i}")), format!("content {i}")).unwrap();
+        }
+        fs::write(root.join("bin/sub/deep"), b"deep").unwrap();
+        symlink(root, root.join("bin/loop")).unwrap();
+
+        let opts = HashTreeOptions {
+            max_depth: DEFAULT_MAX_DEPTH,
+            concurrency: Some(4),
+        };
+        let roots = [root.to_path_buf(), root.join("missing")];
+        let algos = [HashAlgo::Md5, HashAlgo::Sha256];
+        let res = hash_tree(&roots, &algos, opts).collect::<HashMap<_, _>>();
+
+        // 17 files and the missing root, symlinks are skipped
+        assert_eq!(res.len(), 18);
+        assert!(res[&root.join("missing")].is_err());
+
+        let h = res[&root.join("bin/f3")].as_ref().unwrap();
+        assert_eq!(h.md5, md5_data("content 3"));
+        assert_eq!(h.sha256, sha256_data("content 3"));
+        assert_eq!(h.size, 9);
+        // not selected
+        assert!(h.sha1.is_empty());
+        assert!(h.sha512.is_empty());
+
+        // depth is bounded
+        let shallow = hash_tree(
+            &[root.to_path_buf()],
+            &algos,
+            HashTreeOptions {
+                max_depth: 1,
+                concurrency: Some(2),
+            },
+        )
+        .collect::<HashMap<_, _>>();
+        assert_eq!(shallow.len(), 17);
+        assert!(shallow[&root.join("bin/sub")].is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use std::io;
+use std::{collections::HashSet, fmt, io};
 
 use chrono::{DateTime, Utc};
 use kunai_common::{
@@ -12,9 +12,13 @@ use crate::{
     util::{
         account::{Group, User},
         get_clk_tck,
+        proc::{read_proc_stat, ProcStat},
     },
 };
 
+/// Key identifying a process instance. As PIDs get recycled, the
+/// start time is part of the key so that a new process re-using
+/// a PID does not get mistaken for the previous one.
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct ProcKey {
     start_time_sec: u64,
@@ -35,6 +39,75 @@ impl From<ProcUuid> for ProcKey {
     }
 }
 
+impl fmt::Display for ProcKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pid={} start_time_sec={}", self.pid, self.start_time_sec)
+    }
+}
+
+impl ProcKey {
+    /// Builds the key of the live process `pid` from procfs
+    #[inline]
+    pub fn for_pid(pid: i32) -> io::Result<Self> {
+        Ok(Self::from_stat(
+            &read_proc_stat(pid)?,
+            get_clk_tck()? as u64,
+        ))
+    }
+
+    #[inline(always)]
+    fn from_stat(stat: &ProcStat, clk_tck: u64) -> Self {
+        Self {
+            start_time_sec: stat.starttime / clk_tck,
+            pid: stat.pid as u32,
+        }
+    }
+
+    #[inline(always)]
+    pub fn pid(&self) -> i32 {
+        self.pid as i32
+    }
+
+    /// Start time of the process in nanoseconds since boot. Precision is
+    /// limited to the second so that keys built from eBPF and procfs
+    /// information compare equal.
+    #[inline(always)]
+    pub fn start_time_ns(&self) -> u64 {
+        self.start_time_sec * 1_000_000_000
+    }
+}
+
+/// Returns the ancestors of `pid`, from its parent up to PID 1 or until
+/// `max_depth` ancestors have been collected. The chain stops early if an
+/// ancestor exited in between or if its PID got reused (an ancestor cannot
+/// start after its child), so that only genuine ancestors are returned.
+pub fn ancestry(pid: i32, max_depth: usize) -> io::Result<Vec<ProcKey>> {
+    let clk_tck = get_clk_tck()? as u64;
+    let mut out = vec![];
+    let mut seen = HashSet::from([pid]);
+    let mut child = read_proc_stat(pid)?;
+
+    while out.len() < max_depth && child.ppid > 0 {
+        // a cycle should never happen but we must not loop forever
+        if !seen.insert(child.ppid) {
+            break;
+        }
+
+        let Ok(parent) = read_proc_stat(child.ppid) else {
+            break;
+        };
+
+        if parent.starttime > child.starttime {
+            break;
+        }
+
+        out.push(ProcKey::from_stat(&parent, clk_tck));
+        child = parent;
+    }
+
+    Ok(out)
+}
+
 #[derive(Debug, Error)]
 pub enum KeyError {
     #[error("procfs: {0}")]
@@ -139,3 +212,48 @@ impl StdEventInfo {
         self
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_proc_key_for_pid() {
+        let pid = std::process::id() as i32;
+        let k1 = ProcKey::for_pid(pid).unwrap();
+        let k2 = ProcKey::for_pid(pid).unwrap();
+
+        assert_eq!(k1, k2);
+        assert_eq!(k1.pid(), pid);
+        assert_eq!(
+            k1,
+            ProcKey::try_from(&procfs::process::Process::myself().unwrap()).unwrap()
+        );
+        assert_eq!(
+            k1.to_string(),
+            format!(
+                "pid={pid} start_time_sec={}",
+                k1.start_time_ns() / 1_000_000_000
+            )
+        );
+    }
+
+    #[test]
+    fn test_ancestry() {
+        let pid = std::process::id() as i32;
+        let chain = ancestry(pid, usize::MAX).unwrap();
+
+        assert_eq!(chain.last().map(|k| k.pid()), Some(1));
+        assert_eq!(
+            chain.first().map(|k| k.pid()),
+            Some(read_proc_stat(pid).unwrap().ppid)
+        );
+        assert!(!chain.iter().any(|k| k.pid() == pid));
+        // keys must match the ones built from procfs
+        assert_eq!(chain[0], ProcKey::for_pid(chain[0].pid()).unwrap());
+
+        assert_eq!(ancestry(pid, 1).unwrap(), chain[..1]);
+        assert!(ancestry(pid, 0).unwrap().is_empty());
+        assert!(ancestry(1, 10).unwrap().is_empty());
+    }
+}
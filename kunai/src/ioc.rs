@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::util::normalize_hash;
+
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct IoC {
     pub source: String,
@@ -7,3 +9,34 @@ pub struct IoC {
     pub value: String,
     pub severity: u8,
 }
+
+impl IoC {
+    /// Returns the value to use for matching. Values looking like hashes
+    /// (hex strings of md5, sha1, sha256 or sha512 digest size) are
+    /// normalized so that they compare equal with the hashes we compute.
+    pub fn normalized_value(&self) -> String {
+        normalize_hash(&self.value)
+            .filter(|h| matches!(h.len(), 32 | 40 | 64 | 128))
+            .unwrap_or_else(|| self.value.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_normalized_value() {
+        let mut ioc = IoC {
+            value: "0xD41D8CD98F00B204E9800998ECF8427E".into(),
+            ..Default::default()
+        };
+        assert_eq!(ioc.normalized_value(), "d41d8cd98f00b204e9800998ecf8427e");
+
+        // anything else is left untouched
+        ioc.value = "Evil.Com".into();
+        assert_eq!(ioc.normalized_value(), "Evil.Com");
+        ioc.value = "CAFE".into();
+        assert_eq!(ioc.normalized_value(), "CAFE");
+    }
+}
@@ -1,18 +1,52 @@
 use core::mem::{size_of, MaybeUninit};
 use ip_network::IpNetwork;
-use libc::{clock_gettime, rlimit, timespec, CLOCK_MONOTONIC};
+use libc::{clock_gettime, rlimit, timespec, CLOCK_BOOTTIME, CLOCK_MONOTONIC};
 use md5::{Digest, Md5};
 use sha1::Sha1;
 use sha2::{Sha256, Sha512};
-use std::{fs, io, net::IpAddr};
+use std::{
+    ffi::CString,
+    fs::{self, File},
+    io,
+    net::IpAddr,
+    os::{
+        fd::AsRawFd,
+        unix::{
+            ffi::OsStrExt,
+            fs::{FileExt, OpenOptionsExt},
+        },
+    },
+    path::{Component, Path, PathBuf},
+};
 use thiserror::Error;
 
 pub mod account;
+pub mod bloom;
 pub mod bpf;
+pub mod caps;
+pub mod cgroup;
+pub mod checksum;
 pub mod elf;
+pub mod entropy;
+pub mod inotify;
+pub mod ksyms;
+pub mod lsm;
+pub mod meminfo;
+pub mod mount;
 pub mod namespace;
+pub mod net;
+pub mod perm;
+pub mod persistence;
+pub mod posture;
+pub mod proc;
+pub mod spawn;
+pub mod sysctl;
+pub mod tracefs;
 pub mod uname;
 pub mod uptime;
+pub mod utmp;
+pub mod walk;
+pub mod watchdog;
 
 #[inline]
 pub fn is_public_ip(ip: IpAddr) -> bool {
@@ -40,6 +74,41 @@ pub fn ktime_get_ns() -> Result<u64, io::Error> {
     }
 }
 
+/// Function getting time since boot, including suspended
+/// time. This is the clock procfs start times are based on.
+pub fn ktime_get_boot_ns() -> Result<u64, io::Error> {
+    let mut ts: timespec = unsafe { std::mem::zeroed() };
+
+    let result = unsafe { clock_gettime(CLOCK_BOOTTIME, &mut ts) };
+
+    if result == 0 {
+        Ok(ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64)
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[inline]
+fn skew_at(now_ns: u64, event_ns: u64) -> i64 {
+    (now_ns as i128 - event_ns as i128).clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+/// Returns the signed difference, in nanoseconds, between the current
+/// boot time (see [ktime_get_boot_ns]) and an event timestamp taken with the
+/// same clock. A positive value is the age of the event, a negative one
+/// means the event comes from the future.
+#[inline]
+pub fn clock_skew(event_boottime_ns: u64) -> Result<i64, io::Error> {
+    Ok(skew_at(ktime_get_boot_ns()?, event_boottime_ns))
+}
+
+/// Returns true if a skew returned by [clock_skew] exceeds `tolerance`,
+/// either in the past (lagging processing) or in the future (clock tampering).
+#[inline]
+pub fn flag_skew(skew_ns: i64, tolerance: std::time::Duration) -> bool {
+    skew_ns.unsigned_abs() as u128 > tolerance.as_nanos()
+}
+
 fn sysconf<T: From<i64>>(var: libc::c_int) -> Result<T, io::Error> {
     let v = unsafe { libc::sysconf(var) };
     if v == -1 {
@@ -58,6 +127,76 @@ pub fn page_size() -> Result<i64, io::Error> {
     sysconf(libc::_SC_PAGESIZE)
 }
 
+/// Number of CPUs currently online
+#[inline]
+pub fn num_online_cpus() -> Result<usize, io::Error> {
+    sysconf::<i64>(libc::_SC_NPROCESSORS_ONLN).map(|n| n.max(1) as usize)
+}
+
+/// Parses a CPU list as found in sysfs (ex: 0-3,8,10-11)
+fn parse_cpu_list(s: &str) -> Option<Vec<usize>> {
+    let mut out = vec![];
+    for r in s.trim().split(',').filter(|r| !r.is_empty()) {
+        match r.split_once('-') {
+            Some((start, end)) => out.extend(start.parse::<usize>().ok()?..=end.parse().ok()?),
+            None => out.push(r.parse().ok()?),
+        }
+    }
+    Some(out)
+}
+
+/// Number of CPUs the system can have, including offline
+/// and hot-pluggable ones
+pub fn num_possible_cpus() -> Result<usize, io::Error> {
+    let possible = fs::read_to_string("/sys/devices/system/cpu/possible")?;
+    parse_cpu_list(&possible)
+        .and_then(|l| l.into_iter().max())
+        .map(|max| max + 1)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid possible cpu list"))
+}
+
+/// Pins the calling thread to `cpus`. An error is returned if the
+/// list is empty or if a CPU index is not a possible CPU.
+pub fn set_thread_affinity(cpus: &[usize]) -> Result<(), io::Error> {
+    let possible = num_possible_cpus()?.min(libc::CPU_SETSIZE as usize);
+    if cpus.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "empty cpu list",
+        ));
+    }
+
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for &cpu in cpus {
+        if cpu >= possible {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cpu {cpu} is not a possible cpu (max={})", possible - 1),
+            ));
+        }
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+
+    // pid 0 is the calling thread
+    if unsafe { libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &set) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Returns the CPUs the calling thread is allowed to run on
+pub fn get_thread_affinity() -> Result<Vec<usize>, io::Error> {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::sched_getaffinity(0, size_of::<libc::cpu_set_t>(), &mut set) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok((0..libc::CPU_SETSIZE as usize)
+        .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) })
+        .collect())
+}
+
 #[inline]
 pub fn page_shift() -> Result<u64, io::Error> {
     let page_size = page_size()?;
@@ -94,6 +233,74 @@ pub fn getrandom<T: Sized>() -> Result<T, RandError> {
     Ok(unsafe { t.assume_init() })
 }
 
+/// Real and effective ids of the calling process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Ids {
+    uid: libc::uid_t,
+    euid: libc::uid_t,
+    gid: libc::gid_t,
+    egid: libc::gid_t,
+}
+
+impl Ids {
+    #[inline]
+    fn current() -> Self {
+        unsafe {
+            Self {
+                uid: libc::getuid(),
+                euid: libc::geteuid(),
+                gid: libc::getgid(),
+                egid: libc::getegid(),
+            }
+        }
+    }
+
+    /// Checks that ids are exactly the target ones
+    fn check_dropped_to(&self, uid: libc::uid_t, gid: libc::gid_t) -> Result<(), io::Error> {
+        if self.uid != uid || self.euid != uid || self.gid != gid || self.egid != gid {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "privileges not dropped: uid={} euid={} gid={} egid={}",
+                    self.uid, self.euid, self.gid, self.egid
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Drops privileges of the calling process to `uid` and `gid`. Supplementary
+/// groups are set to `gid` only, then gid is changed before uid (we would no
+/// longer be allowed to change gid otherwise). Any failure, including the
+/// ability to regain root afterwards, is reported as an error and the caller
+/// must not go on running as it may still be privileged.
+pub fn drop_privileges(uid: libc::uid_t, gid: libc::gid_t) -> Result<(), io::Error> {
+    if unsafe { libc::setgroups(1, &gid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if unsafe { libc::setgid(gid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if unsafe { libc::setuid(uid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ids::current().check_dropped_to(uid, gid)?;
+
+    // setuid(0) must fail if we really dropped root
+    if uid != 0 && unsafe { libc::setuid(0) } == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "privileges can be regained after drop",
+        ));
+    }
+
+    Ok(())
+}
+
 pub fn kill(pid: i32, sig: i32) -> Result<(), io::Error> {
     if unsafe { libc::kill(pid, sig) } == -1 {
         return Err(io::Error::last_os_error());
@@ -125,6 +332,79 @@ pub fn setrlimit(resource: u32, rlimit: rlimit) -> Result<(), io::Error> {
     Ok(())
 }
 
+#[inline]
+fn prlimit_permission_error(pid: i32) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!(
+            "accessing limits of pid={pid} requires matching credentials or {}",
+            caps::Capability::SysResource
+        ),
+    )
+}
+
+#[inline]
+fn prlimit(pid: i32, resource: u32, new: Option<&rlimit>) -> Result<rlimit, io::Error> {
+    let mut old = rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    let new = new.map(|n| n as *const rlimit).unwrap_or(core::ptr::null());
+
+    if unsafe { libc::prlimit(pid, resource, new, &mut old) } != 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::EPERM) {
+            return Err(prlimit_permission_error(pid));
+        }
+        return Err(err);
+    }
+
+    Ok(old)
+}
+
+/// Same as [getrlimit] but for any process `pid`. An error of kind
+/// [io::ErrorKind::PermissionDenied] is returned if the caller does not
+/// have the credentials of the target process nor CAP_SYS_RESOURCE.
+#[inline]
+pub fn getrlimit_pid(pid: i32, resource: u32) -> Result<rlimit, io::Error> {
+    prlimit(pid, resource, None)
+}
+
+/// Same as [setrlimit] but for any process `pid`. Raising a hard limit
+/// requires CAP_SYS_RESOURCE, this is checked before attempting the change
+/// so that no partial update happens.
+pub fn setrlimit_pid(pid: i32, resource: u32, rlimit: rlimit) -> Result<(), io::Error> {
+    let current = getrlimit_pid(pid, resource)?;
+    if rlimit.rlim_max > current.rlim_max
+        && !caps::current_capabilities()?.has_effective(caps::Capability::SysResource)
+    {
+        return Err(prlimit_permission_error(pid));
+    }
+    prlimit(pid, resource, Some(&rlimit)).map(|_| ())
+}
+
+/// Locks all current and future pages of the process in memory so that
+/// they never get swapped. Locked memory is accounted against
+/// RLIMIT_MEMLOCK for unprivileged processes (without CAP_IPC_LOCK) and
+/// with MCL_FUTURE any allocation going over the limit fails. It is
+/// thus advised to raise RLIMIT_MEMLOCK (see [setrlimit]) before calling
+/// this function. Failures with ENOMEM (limit too low) or EPERM are
+/// returned as is.
+pub fn lock_all_memory() -> Result<(), io::Error> {
+    if unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Unlocks all the pages locked by [lock_all_memory]
+pub fn unlock_all_memory() -> Result<(), io::Error> {
+    if unsafe { libc::munlockall() } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
 #[inline]
 pub fn md5_data<T: AsRef<[u8]>>(data: T) -> String {
     let mut h = Md5::new();
@@ -153,6 +433,271 @@ pub fn sha512_data<T: AsRef<[u8]>>(data: T) -> String {
     hex::encode(h.finalize())
 }
 
+/// Hash algorithms supported when hashing files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashAlgo {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgo {
+    pub const ALL: [HashAlgo; 4] = [Self::Md5, Self::Sha1, Self::Sha256, Self::Sha512];
+
+    /// Returns the hex encoded digest of `data`
+    #[inline]
+    pub fn hash_data<T: AsRef<[u8]>>(&self, data: T) -> String {
+        match self {
+            Self::Md5 => md5_data(data),
+            Self::Sha1 => sha1_data(data),
+            Self::Sha256 => sha256_data(data),
+            Self::Sha512 => sha512_data(data),
+        }
+    }
+
+    /// Returns the hex encoded digest of all the data read from `r`
+    pub fn hash_reader<R: io::Read>(&self, r: R) -> Result<String, io::Error> {
+        match self {
+            Self::Md5 => digest_reader::<Md5, R>(r),
+            Self::Sha1 => digest_reader::<Sha1, R>(r),
+            Self::Sha256 => digest_reader::<Sha256, R>(r),
+            Self::Sha512 => digest_reader::<Sha512, R>(r),
+        }
+    }
+}
+
+fn digest_reader<D: Digest, R: io::Read>(r: R) -> Result<String, io::Error> {
+    let mut h = D::new();
+    read_chunks(r, |chunk| h.update(chunk))?;
+    Ok(hex::encode(h.finalize()))
+}
+
+/// Reads `r` until EOF, passing each chunk of data read to `f`.
+/// Interrupted reads are retried, any other error is returned.
+pub fn read_chunks<R: io::Read, F: FnMut(&[u8])>(mut r: R, mut f: F) -> Result<(), io::Error> {
+    let mut buf = [0; 4096];
+    loop {
+        match r.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => f(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Computes a cheap fingerprint of `path` by hashing its size along with its
+/// first and last `edge_bytes` bytes, files smaller than twice `edge_bytes`
+/// being hashed entirely. This is a heuristic meant to spot likely identical
+/// large files: files differing only in their middle share a fingerprint, so
+/// it is not an identity of the content and must not be used for integrity
+/// checks or IoC matching.
+pub fn quick_fingerprint(path: &Path, edge_bytes: usize, algo: HashAlgo) -> io::Result<String> {
+    let f = File::open(path)?;
+    let size = f.metadata()?.len();
+
+    let mut data = size.to_le_bytes().to_vec();
+    let edges = if size <= 2 * edge_bytes as u64 {
+        vec![(0, size as usize)]
+    } else {
+        vec![(0, edge_bytes), (size - edge_bytes as u64, edge_bytes)]
+    };
+
+    for (off, len) in edges {
+        let start = data.len();
+        data.resize(start + len, 0);
+        f.read_exact_at(&mut data[start..], off)?;
+    }
+
+    Ok(algo.hash_data(data))
+}
+
+/// Reads the target of symlink `path`, growing the read buffer until the
+/// target fits or `max` bytes is reached. The returned boolean is true if
+/// the target got truncated to `max` bytes (a target of exactly `max` bytes
+/// cannot be told apart and is reported as truncated). Non UTF-8 targets are
+/// decoded lossily.
+pub fn read_link_capped(path: &Path, max: usize) -> Result<(PathBuf, bool), io::Error> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let mut buf: Vec<u8> = vec![0; max.clamp(1, 256)];
+
+    loop {
+        let n = unsafe {
+            libc::readlink(
+                c_path.as_ptr(),
+                buf.as_mut_ptr() as *mut libc::c_char,
+                buf.len(),
+            )
+        };
+
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let n = n as usize;
+        // readlink fills the whole buffer when target is too big
+        // so we cannot know if target fits exactly or if it got truncated
+        if n < buf.len() || buf.len() >= max {
+            let truncated = n == buf.len() && n >= max;
+            buf.truncate(n.min(max));
+            return Ok((
+                PathBuf::from(String::from_utf8_lossy(&buf).into_owned()),
+                truncated,
+            ));
+        }
+
+        buf.resize(buf.len().saturating_mul(2).min(max), 0);
+    }
+}
+
+/// Lexically normalizes `p`: duplicate separators, `.` components and
+/// trailing separators are removed and, if `collapse_parent` is true, `..`
+/// components are collapsed with the component preceding them. The filesystem
+/// is never accessed so symlinks are not resolved, collapsing `..` may thus
+/// return a different file than `p` if a symlinked directory precedes it.
+pub fn normalize_path_with(p: &Path, collapse_parent: bool) -> PathBuf {
+    let mut out = PathBuf::new();
+    for c in p.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir if collapse_parent => match out.components().next_back() {
+                // .. of the root is the root
+                Some(Component::RootDir) => {}
+                Some(Component::Normal(_)) => {
+                    out.pop();
+                }
+                // relative path going above its start
+                _ => out.push(c),
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Lexically normalizes `p`, collapsing `..` components (see [normalize_path_with])
+#[inline]
+pub fn normalize_path(p: &Path) -> PathBuf {
+    normalize_path_with(p, true)
+}
+
+/// Opens file at `path` and stats the opened file descriptor so that
+/// metadata and content are guaranteed to come from the same inode, even
+/// if `path` is replaced in between. This must be preferred over a
+/// separate stat and open when enriching files. Opening never blocks (ex:
+/// on a FIFO without writer) so callers must check the file type (see
+/// [is_regular_file]) before reading.
+pub fn open_and_stat(path: &Path) -> Result<(File, libc::stat), io::Error> {
+    let f = File::options()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK | libc::O_NOCTTY)
+        .open(path)?;
+    let mut st: libc::stat = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::fstat(f.as_raw_fd(), &mut st) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok((f, st))
+}
+
+/// Returns true if `st` is the one of a regular file
+#[inline(always)]
+pub fn is_regular_file(st: &libc::stat) -> bool {
+    st.st_mode & libc::S_IFMT == libc::S_IFREG
+}
+
+/// Reads extended attribute `name` of `path` without following symlinks.
+/// `None` is returned if the attribute does not exist.
+pub fn get_xattr(path: &Path, name: &str) -> Result<Option<Vec<u8>>, io::Error> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let c_name = CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    loop {
+        // we first query the size of the attribute
+        let size =
+            unsafe { libc::lgetxattr(c_path.as_ptr(), c_name.as_ptr(), core::ptr::null_mut(), 0) };
+        if size < 0 {
+            let err = io::Error::last_os_error();
+            return match err.raw_os_error() {
+                Some(libc::ENODATA) => Ok(None),
+                _ => Err(err),
+            };
+        }
+
+        let mut buf = vec![0u8; size as usize];
+        let n = unsafe {
+            libc::lgetxattr(
+                c_path.as_ptr(),
+                c_name.as_ptr(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+            )
+        };
+
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                // attribute grew in between, we retry
+                Some(libc::ERANGE) => continue,
+                Some(libc::ENODATA) => return Ok(None),
+                _ => return Err(err),
+            }
+        }
+
+        buf.truncate(n as usize);
+        return Ok(Some(buf));
+    }
+}
+
+#[inline(always)]
+const fn hex_nibble(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decodes hex string `s` into a fixed size array without any heap
+/// allocation. `None` is returned if `s` is not exactly `2 * N` hex
+/// characters long.
+#[inline]
+pub fn hex_to_array<const N: usize>(s: &str) -> Option<[u8; N]> {
+    let b = s.as_bytes();
+    if b.len() != N * 2 {
+        return None;
+    }
+
+    let mut out = [0u8; N];
+    for (i, o) in out.iter_mut().enumerate() {
+        *o = (hex_nibble(b[i * 2])? << 4) | hex_nibble(b[i * 2 + 1])?;
+    }
+    Some(out)
+}
+
+/// Normalizes a hash coming from an external source to the format used
+/// across the crate (lowercase hex). Surrounding whitespaces and `0x`
+/// prefix are stripped. `None` is returned if `s` is not a valid hex string.
+pub fn normalize_hash(s: &str) -> Option<String> {
+    let s = s.trim();
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+
+    if s.is_empty() || s.len() % 2 != 0 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    Some(s.to_ascii_lowercase())
+}
+
 #[inline]
 pub fn is_bpf_lsm_enabled() -> Result<bool, io::Error> {
     Ok(fs::read_to_string("/sys/kernel/security/lsm")?
@@ -164,9 +709,280 @@ pub fn is_bpf_lsm_enabled() -> Result<bool, io::Error> {
 mod test {
     use crate::util::*;
 
+    #[test]
+    fn test_read_chunks() {
+        // reader interrupted before each successful read
+        struct Interrupting<R>(R, bool);
+        impl<R: io::Read> io::Read for Interrupting<R> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.1 = !self.1;
+                if self.1 {
+                    return Err(io::ErrorKind::Interrupted.into());
+                }
+                self.0.read(buf)
+            }
+        }
+
+        let data = vec![0x42u8; 10_000];
+        let mut out = vec![];
+        read_chunks(Interrupting(data.as_slice(), false), |c| {
+            out.extend_from_slice(c)
+        })
+        .unwrap();
+        assert_eq!(out, data);
+        assert_eq!(
+            HashAlgo::Sha256
+                .hash_reader(Interrupting(data.as_slice(), false))
+                .unwrap(),
+            sha256_data(&data)
+        );
+    }
+
+    #[test]
+    fn test_quick_fingerprint() {
+        let dir = tempfile::tempdir().unwrap();
+        let p = |n: &str| dir.path().join(n);
+        let data = vec![0x41u8; 4096];
+
+        let mut middle = data.clone();
+        middle[2048] = 0x42;
+        let mut tail = data.clone();
+        tail[4095] = 0x42;
+        fs::write(p("a"), &data).unwrap();
+        fs::write(p("middle"), &middle).unwrap();
+        fs::write(p("tail"), &tail).unwrap();
+        fs::write(p("longer"), [data.as_slice(), b"A"].concat()).unwrap();
+
+        let fp = |n: &str| quick_fingerprint(&p(n), 512, HashAlgo::Sha256).unwrap();
+        assert_eq!(fp("a"), fp("middle"));
+        assert_ne!(fp("a"), fp("tail"));
+        assert_ne!(fp("a"), fp("longer"));
+
+        // small files are entirely hashed
+        assert_ne!(
+            quick_fingerprint(&p("a"), 4096, HashAlgo::Md5).unwrap(),
+            quick_fingerprint(&p("middle"), 4096, HashAlgo::Md5).unwrap()
+        );
+        assert!(quick_fingerprint(&p("missing"), 512, HashAlgo::Md5).is_err());
+    }
+
     #[test]
     fn test_page_size() {
         println!("PAGE_SIZE: {}", page_size().unwrap());
         println!("PAGE_SHIFT: {}", page_shift().unwrap());
     }
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11\n"),
+            Some(vec![0, 1, 2, 3, 8, 10, 11])
+        );
+        assert_eq!(parse_cpu_list("0"), Some(vec![0]));
+        assert_eq!(parse_cpu_list("0-a"), None);
+        assert!(num_possible_cpus().unwrap() >= num_online_cpus().unwrap());
+    }
+
+    #[test]
+    fn test_thread_affinity() {
+        // we run in a dedicated thread not to alter other tests
+        std::thread::spawn(|| {
+            let initial = get_thread_affinity().unwrap();
+            assert!(!initial.is_empty());
+
+            assert!(set_thread_affinity(&[]).is_err());
+            assert!(set_thread_affinity(&[num_possible_cpus().unwrap()]).is_err());
+
+            match set_thread_affinity(&[0]) {
+                Ok(()) => assert_eq!(get_thread_affinity().unwrap(), vec![0]),
+                // cpu 0 may not be allowed (ex: restricted cpuset)
+                Err(e) => assert!(
+                    matches!(e.raw_os_error(), Some(libc::EPERM | libc::EINVAL)),
+                    "unexpected error: {e}"
+                ),
+            }
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_check_dropped() {
+        let ids = Ids {
+            uid: 1000,
+            euid: 1000,
+            gid: 100,
+            egid: 100,
+        };
+        assert!(ids.check_dropped_to(1000, 100).is_ok());
+        assert!(ids.check_dropped_to(1000, 0).is_err());
+
+        // effective ids must be dropped too
+        assert!(Ids { euid: 0, ..ids }.check_dropped_to(1000, 100).is_err());
+        assert!(Ids { egid: 0, ..ids }.check_dropped_to(1000, 100).is_err());
+
+        let cur = Ids::current();
+        assert!(cur.check_dropped_to(cur.uid, cur.gid).is_ok());
+    }
+
+    #[test]
+    fn test_lock_all_memory() {
+        match lock_all_memory() {
+            Ok(()) => unlock_all_memory().unwrap(),
+            Err(e) => assert!(
+                matches!(e.raw_os_error(), Some(libc::ENOMEM) | Some(libc::EPERM)),
+                "unexpected error: {e}"
+            ),
+        }
+    }
+
+    #[test]
+    fn test_hex_to_array() {
+        let h = sha256_data(b"kunai");
+        let a = hex_to_array::<32>(&h).unwrap();
+        assert_eq!(hex::encode(a), h);
+        assert_eq!(hex_to_array::<32>(&h.to_uppercase()), Some(a));
+
+        // wrong length
+        assert_eq!(hex_to_array::<32>(&h[..62]), None);
+        assert_eq!(hex_to_array::<16>(&h), None);
+        // invalid characters
+        assert_eq!(hex_to_array::<2>("zz00"), None);
+        assert_eq!(hex_to_array::<2>("00 0"), None);
+        // multi-byte utf8 must not panic
+        assert_eq!(hex_to_array::<2>("é00"), None);
+    }
+
+    #[test]
+    fn test_normalize_hash() {
+        let h = md5_data(b"kunai");
+        assert_eq!(normalize_hash(&h.to_uppercase()), Some(h.clone()));
+        assert_eq!(normalize_hash(&format!("0x{h}")), Some(h.clone()));
+        assert_eq!(
+            normalize_hash(&format!(" 0X{}\n", h.to_uppercase())),
+            Some(h.clone())
+        );
+        assert_eq!(normalize_hash(&h), Some(h));
+
+        assert_eq!(normalize_hash(""), None);
+        assert_eq!(normalize_hash("0x"), None);
+        assert_eq!(normalize_hash("abc"), None);
+        assert_eq!(normalize_hash("deadbeefzz"), None);
+        assert_eq!(normalize_hash("dead beef"), None);
+    }
+
+    #[test]
+    fn test_open_and_stat() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, b"original").unwrap();
+
+        let (f, st) = open_and_stat(&path).unwrap();
+        let meta = f.metadata().unwrap();
+        assert_eq!(st.st_ino, meta.ino());
+        assert_eq!(st.st_dev, meta.dev());
+        assert_eq!(st.st_size, 8);
+
+        // replacing the path does not affect what we opened
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, b"replaced file").unwrap();
+        assert_eq!(f.metadata().unwrap().ino(), st.st_ino);
+        assert_eq!(f.metadata().unwrap().len(), 8);
+
+        assert!(open_and_stat(&dir.path().join("missing")).is_err());
+        assert!(is_regular_file(&st));
+
+        // must not block on a FIFO
+        let fifo = dir.path().join("fifo");
+        let c_fifo = CString::new(fifo.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_fifo.as_ptr(), 0o600) }, 0);
+        let (_, st) = open_and_stat(&fifo).unwrap();
+        assert!(!is_regular_file(&st));
+    }
+
+    #[test]
+    fn test_read_link_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        let link = dir.path().join("link");
+        let target = PathBuf::from(format!("/{}", "very_long_dir/".repeat(250)));
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let (p, truncated) = read_link_capped(&link, 8192).unwrap();
+        assert_eq!(p, target);
+        assert!(!truncated);
+
+        let (p, truncated) = read_link_capped(&link, 100).unwrap();
+        assert_eq!(p.as_os_str().len(), 100);
+        assert!(target
+            .to_string_lossy()
+            .starts_with(p.to_string_lossy().as_ref()));
+        assert!(truncated);
+
+        assert!(read_link_capped(&dir.path().join("missing"), 128).is_err());
+    }
+
+    #[test]
+    fn test_getrlimit_pid() {
+        let pid = std::process::id() as i32;
+        for res in [
+            libc::RLIMIT_NOFILE,
+            libc::RLIMIT_MEMLOCK,
+            libc::RLIMIT_STACK,
+        ] {
+            let l = getrlimit_pid(pid, res).unwrap();
+            let e = getrlimit(res).unwrap();
+            assert_eq!((l.rlim_cur, l.rlim_max), (e.rlim_cur, e.rlim_max));
+        }
+
+        // setting current limits is always allowed
+        let l = getrlimit(libc::RLIMIT_NOFILE).unwrap();
+        setrlimit_pid(pid, libc::RLIMIT_NOFILE, l).unwrap();
+
+        assert_eq!(
+            getrlimit_pid(i32::MAX, libc::RLIMIT_NOFILE)
+                .map(|_| ())
+                .unwrap_err()
+                .raw_os_error(),
+            Some(libc::ESRCH)
+        );
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        let n = |p: &str| normalize_path(Path::new(p));
+        assert_eq!(n("//a/./b/../c"), PathBuf::from("/a/c"));
+        assert_eq!(n("/usr/bin/"), PathBuf::from("/usr/bin"));
+        assert_eq!(n("/usr//bin///ls"), PathBuf::from("/usr/bin/ls"));
+        assert_eq!(n("/../../etc/passwd"), PathBuf::from("/etc/passwd"));
+        assert_eq!(n("a/../../b"), PathBuf::from("../b"));
+        assert_eq!(n("./a/."), PathBuf::from("a"));
+        assert_eq!(n("/"), PathBuf::from("/"));
+
+        assert_eq!(
+            normalize_path_with(Path::new("//a/./b/../c/"), false),
+            PathBuf::from("/a/b/../c")
+        );
+    }
+
+    #[test]
+    fn test_clock_skew() {
+        use std::time::Duration;
+
+        assert_eq!(skew_at(1_000, 400), 600);
+        assert_eq!(skew_at(400, 1_000), -600);
+        assert_eq!(skew_at(u64::MAX, 0), i64::MAX);
+        assert_eq!(skew_at(0, u64::MAX), i64::MIN);
+
+        let tolerance = Duration::from_nanos(500);
+        assert!(flag_skew(600, tolerance));
+        assert!(flag_skew(-600, tolerance));
+        assert!(!flag_skew(500, tolerance));
+        assert!(!flag_skew(-42, tolerance));
+        assert!(flag_skew(i64::MIN, Duration::from_secs(1)));
+
+        let now = ktime_get_boot_ns().unwrap();
+        assert!(clock_skew(now).unwrap() >= 0);
+    }
 }
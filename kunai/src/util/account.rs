@@ -2,19 +2,24 @@
 /// and /etc/group files and provide API to query user and group information.
 use std::{
     collections::HashMap,
+    ffi::CStr,
     fs::File,
     io::{self, BufRead, BufReader},
     num::ParseIntError,
     path::Path,
     str::FromStr,
+    sync::Mutex,
 };
 
+use lru_st::collections::LruHashMap;
 use thiserror::Error;
 
 #[derive(Debug, Clone)]
 pub struct User {
     pub name: String,
     pub uid: u32,
+    pub home: String,
+    pub shell: String,
 }
 
 impl FromStr for User {
@@ -36,6 +41,8 @@ impl TryFrom<Vec<&str>> for User {
             uid: value[2]
                 .parse::<u32>()
                 .map_err(|e| ParseError::ParseInt("uid", e))?,
+            home: value.get(5).map(|s| s.to_string()).unwrap_or_default(),
+            shell: value.get(6).map(|s| s.to_string()).unwrap_or_default(),
         })
     }
 }
@@ -85,6 +92,193 @@ pub enum Error {
     Parse(#[from] ParseError),
 }
 
+// getpwent family of functions share a global state
+static PWENT_LOCK: Mutex<()> = Mutex::new(());
+
+/// Enumerates all the users known by the system through `getpwent`, so
+/// that any user database configured in nsswitch.conf is taken into account.
+///
+/// # Thread safety
+///
+/// `setpwent`/`getpwent`/`endpwent` are not thread safe, calls made through
+/// this function are serialized but any other direct use of these functions
+/// in the process may interfere with the enumeration.
+pub fn all_users() -> io::Result<Vec<User>> {
+    // lock cannot be poisoned by the code below
+    let _guard = PWENT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut out = vec![];
+
+    let cstr = |p: *const libc::c_char| {
+        if p.is_null() {
+            return String::new();
+        }
+        unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    };
+
+    unsafe { libc::setpwent() };
+
+    let res = loop {
+        // errno is the only way to distinguish end of enumeration from errors
+        unsafe { *libc::__errno_location() = 0 };
+        let pw = unsafe { libc::getpwent() };
+
+        if pw.is_null() {
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(0) | Some(libc::ENOENT) => break Ok(()),
+                _ => break Err(err),
+            }
+        }
+
+        let pw = unsafe { &*pw };
+        out.push(User {
+            name: cstr(pw.pw_name),
+            uid: pw.pw_uid,
+            home: cstr(pw.pw_dir),
+            shell: cstr(pw.pw_shell),
+        });
+    };
+
+    unsafe { libc::endpwent() };
+
+    res.map(|_| out)
+}
+
+#[inline]
+fn filter_extra_root_users<'a, I>(users: I) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a User>,
+{
+    users
+        .into_iter()
+        .filter(|u| u.uid == 0 && u.name != "root")
+        .map(|u| u.name.clone())
+        .collect()
+}
+
+/// Returns the names of all the users having uid 0 apart from root.
+/// Such accounts are often used as backdoors.
+pub fn extra_root_users() -> io::Result<Vec<String>> {
+    Ok(filter_extra_root_users(&all_users()?))
+}
+
+/// Source of uid and gid to name translations used by [CredentialResolver].
+/// Lookups return `None` when the id is unknown.
+pub trait CredentialBackend {
+    fn user_name(&self, uid: u32) -> io::Result<Option<String>>;
+    fn group_name(&self, gid: u32) -> io::Result<Option<String>>;
+}
+
+/// Backend querying the databases configured in nsswitch.conf
+/// through `getpwuid_r` and `getgrgid_r`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NssBackend;
+
+impl NssBackend {
+    /// Runs a reentrant NSS lookup, growing the buffer until results fit
+    fn lookup<T, F, N>(f: F, name: N) -> io::Result<Option<String>>
+    where
+        F: Fn(*mut T, &mut [libc::c_char], *mut *mut T) -> libc::c_int,
+        N: Fn(&T) -> *const libc::c_char,
+    {
+        let mut buf = vec![0 as libc::c_char; 1024];
+        let mut entry = std::mem::MaybeUninit::<T>::uninit();
+        let mut result = std::ptr::null_mut();
+
+        loop {
+            match f(entry.as_mut_ptr(), &mut buf, &mut result) {
+                0 if result.is_null() => return Ok(None),
+                0 => {
+                    let entry = unsafe { &*result };
+                    return Ok(Some(
+                        unsafe { CStr::from_ptr(name(entry)) }
+                            .to_string_lossy()
+                            .into_owned(),
+                    ));
+                }
+                libc::ERANGE => buf.resize(buf.len() * 2, 0),
+                // not found may also be reported as an error
+                libc::ENOENT | libc::ESRCH | libc::EBADF | libc::EPERM => return Ok(None),
+                e => return Err(io::Error::from_raw_os_error(e)),
+            }
+        }
+    }
+}
+
+impl CredentialBackend for NssBackend {
+    fn user_name(&self, uid: u32) -> io::Result<Option<String>> {
+        Self::lookup(
+            |pw, buf: &mut [libc::c_char], res| unsafe {
+                libc::getpwuid_r(uid, pw, buf.as_mut_ptr(), buf.len(), res)
+            },
+            |pw: &libc::passwd| pw.pw_name,
+        )
+    }
+
+    fn group_name(&self, gid: u32) -> io::Result<Option<String>> {
+        Self::lookup(
+            |gr, buf: &mut [libc::c_char], res| unsafe {
+                libc::getgrgid_r(gid, gr, buf.as_mut_ptr(), buf.len(), res)
+            },
+            |gr: &libc::group| gr.gr_name,
+        )
+    }
+}
+
+/// Bounded cache of uid and gid to name translations, sparing a NSS lookup
+/// per event. Unknown ids are cached as well, lookup errors are not.
+pub struct CredentialResolver<B: CredentialBackend = NssBackend> {
+    backend: B,
+    cap: usize,
+    users: LruHashMap<u32, Option<String>>,
+    groups: LruHashMap<u32, Option<String>>,
+}
+
+impl CredentialResolver<NssBackend> {
+    /// Creates a resolver caching at most `cap` users and `cap` groups
+    pub fn new(cap: usize) -> Self {
+        Self::with_backend(NssBackend, cap)
+    }
+}
+
+impl<B: CredentialBackend> CredentialResolver<B> {
+    pub fn with_backend(backend: B, cap: usize) -> Self {
+        Self {
+            backend,
+            cap,
+            users: LruHashMap::with_max_entries(cap),
+            groups: LruHashMap::with_max_entries(cap),
+        }
+    }
+
+    /// Returns the name of user `uid`, `None` if the user is unknown
+    pub fn resolve_uid(&mut self, uid: u32) -> io::Result<Option<String>> {
+        if let Some(name) = self.users.get(&uid) {
+            return Ok(name.clone());
+        }
+        let name = self.backend.user_name(uid)?;
+        self.users.insert(uid, name.clone());
+        Ok(name)
+    }
+
+    /// Returns the name of group `gid`, `None` if the group is unknown
+    pub fn resolve_gid(&mut self, gid: u32) -> io::Result<Option<String>> {
+        if let Some(name) = self.groups.get(&gid) {
+            return Ok(name.clone());
+        }
+        let name = self.backend.group_name(gid)?;
+        self.groups.insert(gid, name.clone());
+        Ok(name)
+    }
+
+    /// Drops all cached entries, it must be called when
+    /// user or group databases change (ex: /etc/passwd modified)
+    pub fn invalidate(&mut self) {
+        self.users = LruHashMap::with_max_entries(self.cap);
+        self.groups = LruHashMap::with_max_entries(self.cap);
+    }
+}
+
 // Structure holding data parsed from /etc/passwd
 #[derive(Debug, Default, Clone)]
 pub struct Users {
@@ -283,10 +477,39 @@ diana:x:1622:1718:Charles White:/home/charles:/bin/bash"#,
             )
             .unwrap();
 
-        assert_eq!(users.get_by_uid(&1386).unwrap().name.as_str(), "john");
+        assert_eq!(users.get_by_uid(1386).unwrap().name.as_str(), "john");
         assert_eq!(users.get_by_name("john").unwrap().name.as_str(), "john");
-        assert_eq!(users.get_by_uid(&1622).unwrap().name.as_str(), "diana");
+        assert_eq!(users.get_by_uid(1622).unwrap().name.as_str(), "diana");
         assert_eq!(users.get_by_name("diana").unwrap().name.as_str(), "diana");
+        assert_eq!(
+            users.get_by_name("bob").unwrap().home.as_str(),
+            "/home/rachel"
+        );
+        assert_eq!(
+            users.get_by_name("bob").unwrap().shell.as_str(),
+            "/sbin/nologin"
+        );
+    }
+
+    #[test]
+    fn test_all_users() {
+        let users = all_users().unwrap();
+        assert!(users.iter().any(|u| u.uid == 0));
+    }
+
+    #[test]
+    fn test_extra_root_users() {
+        let users = r#"root:x:0:0:root:/root:/bin/bash
+daemon:x:1:1:daemon:/usr/sbin:/usr/sbin/nologin
+toor:x:0:0::/tmp:/bin/sh
+alice:x:1798:1890:John Doe:/home/alice:/bin/zsh"#
+            .lines()
+            .map(|l| User::from_str(l).unwrap())
+            .collect::<Vec<User>>();
+
+        assert_eq!(filter_extra_root_users(&users), vec!["toor".to_string()]);
+        assert!(filter_extra_root_users(&users[..1]).is_empty());
+        extra_root_users().unwrap();
     }
 
     #[test]
@@ -306,12 +529,60 @@ operations:x:612:chris,kate,adam"#,
         )
         .unwrap();
 
-        assert_eq!(db.get_by_gid(&371).unwrap().name.as_str(), "wheel");
+        assert_eq!(db.get_by_gid(371).unwrap().name.as_str(), "wheel");
         assert_eq!(db.get_by_name("wheel").unwrap().name.as_str(), "wheel");
-        assert_eq!(db.get_by_gid(&612).unwrap().name.as_str(), "operations");
+        assert_eq!(db.get_by_gid(612).unwrap().name.as_str(), "operations");
         assert_eq!(
             db.get_by_name("operations").unwrap().name.as_str(),
             "operations"
         );
     }
+
+    #[derive(Default)]
+    struct SpyBackend {
+        calls: std::cell::Cell<usize>,
+    }
+
+    impl CredentialBackend for SpyBackend {
+        fn user_name(&self, uid: u32) -> io::Result<Option<String>> {
+            self.calls.set(self.calls.get() + 1);
+            Ok((uid < 1000).then(|| format!("user{uid}")))
+        }
+
+        fn group_name(&self, gid: u32) -> io::Result<Option<String>> {
+            self.calls.set(self.calls.get() + 1);
+            Ok(Some(format!("group{gid}")))
+        }
+    }
+
+    #[test]
+    fn test_credential_resolver() {
+        let mut r = CredentialResolver::with_backend(SpyBackend::default(), 2);
+
+        assert_eq!(r.resolve_uid(42).unwrap(), Some("user42".into()));
+        assert_eq!(r.resolve_uid(42).unwrap(), Some("user42".into()));
+        assert_eq!(r.backend.calls.get(), 1);
+
+        // unknown users are cached too
+        assert_eq!(r.resolve_uid(4242).unwrap(), None);
+        assert_eq!(r.resolve_uid(4242).unwrap(), None);
+        assert_eq!(r.backend.calls.get(), 2);
+
+        // groups are cached separately
+        assert_eq!(r.resolve_gid(42).unwrap(), Some("group42".into()));
+        assert_eq!(r.backend.calls.get(), 3);
+
+        // cache is bounded, 42 gets evicted
+        r.resolve_uid(1).unwrap();
+        r.resolve_uid(42).unwrap();
+        assert_eq!(r.backend.calls.get(), 5);
+
+        r.invalidate();
+        r.resolve_gid(42).unwrap();
+        assert_eq!(r.backend.calls.get(), 6);
+
+        let mut nss = CredentialResolver::new(8);
+        assert_eq!(nss.resolve_uid(0).unwrap(), Some("root".into()));
+        assert!(nss.resolve_gid(0).unwrap().is_some());
+    }
 }
@@ -0,0 +1,113 @@
+//! This module implements a Bloom filter, which can be used as a cheap
+//! first stage filter in front of large IoC sets.
+use sha2::{Digest, Sha256};
+
+/// Bloom filter using double hashing over a SHA-256 digest. A Bloom
+/// filter can only produce false positives: if [BloomFilter::contains]
+/// returns false the item has never been inserted, but if it returns true
+/// the item must still be confirmed against the real set.
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    nbits: u64,
+    nhashes: u32,
+}
+
+impl BloomFilter {
+    /// Creates a filter sized to hold `expected_items` with a false
+    /// positive rate of `fpr` (ex: 0.01 for 1%).
+    pub fn new(expected_items: usize, fpr: f64) -> Self {
+        let n = expected_items.max(1) as f64;
+        let p = fpr.clamp(f64::MIN_POSITIVE, 0.5);
+        let ln2 = core::f64::consts::LN_2;
+
+        let nbits = (-(n * p.ln()) / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let nhashes = ((nbits as f64 / n) * ln2).round().clamp(1.0, 32.0) as u32;
+
+        Self {
+            bits: vec![0; nbits.div_ceil(64) as usize],
+            nbits,
+            nhashes,
+        }
+    }
+
+    #[inline(always)]
+    fn hashes(data: &[u8]) -> (u64, u64) {
+        let d = Sha256::digest(data);
+        let h1 = u64::from_le_bytes(d[0..8].try_into().unwrap());
+        // h2 must not be null otherwise all the k hashes would be equal
+        let h2 = u64::from_le_bytes(d[8..16].try_into().unwrap()) | 1;
+        (h1, h2)
+    }
+
+    #[inline(always)]
+    fn indexes(&self, data: &[u8]) -> impl Iterator<Item = u64> {
+        let (h1, h2) = Self::hashes(data);
+        let nbits = self.nbits;
+        (0..self.nhashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % nbits)
+    }
+
+    pub fn insert(&mut self, data: &[u8]) {
+        for i in self.indexes(data) {
+            self.bits[(i / 64) as usize] |= 1 << (i % 64);
+        }
+    }
+
+    /// Returns false if `data` has never been inserted, true if it
+    /// has probably been inserted.
+    #[inline]
+    pub fn contains(&self, data: &[u8]) -> bool {
+        self.indexes(data)
+            .all(|i| self.bits[(i / 64) as usize] & (1 << (i % 64)) != 0)
+    }
+
+    #[inline(always)]
+    pub fn clear(&mut self) {
+        self.bits.iter_mut().for_each(|b| *b = 0);
+    }
+
+    /// Size of the filter in bits
+    #[inline(always)]
+    pub fn len_bits(&self) -> u64 {
+        self.nbits
+    }
+
+    #[inline(always)]
+    pub fn hash_count(&self) -> u32 {
+        self.nhashes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_no_false_negative() {
+        let mut b = BloomFilter::new(1000, 0.01);
+        for i in 0..1000u32 {
+            b.insert(&i.to_le_bytes());
+        }
+        assert!((0..1000u32).all(|i| b.contains(&i.to_le_bytes())));
+
+        b.clear();
+        assert!(!b.contains(&0u32.to_le_bytes()));
+    }
+
+    #[test]
+    fn test_fpr() {
+        let fpr = 0.01;
+        let mut b = BloomFilter::new(10_000, fpr);
+        for i in 0..10_000u32 {
+            b.insert(&i.to_le_bytes());
+        }
+
+        let probes = 100_000u32;
+        let fp = (10_000..10_000 + probes)
+            .filter(|i| b.contains(&i.to_le_bytes()))
+            .count();
+
+        let rate = fp as f64 / probes as f64;
+        assert!(rate < fpr * 2.0, "false positive rate too high: {rate}");
+    }
+}
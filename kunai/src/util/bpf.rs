@@ -1,10 +1,19 @@
-use aya_obj::generated::{bpf_attr, bpf_cmd, bpf_prog_info, bpf_prog_type};
+use aya::maps::perf::Events;
+use aya_obj::generated::{
+    bpf_attr, bpf_cmd, bpf_map_info, bpf_map_type, bpf_prog_info, bpf_prog_type,
+};
 use core::ffi::c_long;
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io;
-use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, RawFd};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
 use thiserror::Error;
 
+use super::page_size;
+
 pub(crate) type SysResult = Result<c_long, io::Error>;
 
 #[inline]
@@ -50,6 +59,120 @@ pub(crate) fn bpf_prog_get_info(prog: &File) -> Result<bpf_prog_info, io::Error>
     Ok(info)
 }
 
+#[inline]
+fn bpf_prog_get_next_id(start_id: u32) -> Result<u32, io::Error> {
+    let mut attr = unsafe { core::mem::zeroed::<bpf_attr>() };
+
+    attr.__bindgen_anon_6.__bindgen_anon_1.start_id = start_id;
+
+    sys_bpf(bpf_cmd::BPF_PROG_GET_NEXT_ID, &attr)?;
+    // next_id is populated by the kernel
+    Ok(unsafe { core::ptr::read_volatile(&attr.__bindgen_anon_6.next_id) })
+}
+
+/// Identity of a loaded BPF program. The tag is a hash of the program
+/// instructions computed by the kernel, so it changes when a program
+/// gets replaced by another one with the same name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgFingerprint {
+    /// program name as known by the kernel (truncated to 15 bytes)
+    pub name: String,
+    pub prog_type: u32,
+    pub tag: [u8; 8],
+}
+
+impl ProgFingerprint {
+    // BPF_OBJ_NAME_LEN includes the NUL terminator
+    const MAX_NAME_LEN: usize = 15;
+
+    pub fn new<S: AsRef<str>>(name: S, prog_type: u32, tag: [u8; 8]) -> Self {
+        let name = name.as_ref();
+        // kernel truncates names so we do it as well to compare them
+        let mut end = name.len().min(Self::MAX_NAME_LEN);
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        Self {
+            name: name[..end].to_string(),
+            prog_type,
+            tag,
+        }
+    }
+
+    fn from_info(info: &bpf_prog_info) -> Self {
+        let name = info
+            .name
+            .iter()
+            .map(|&c| c as u8)
+            .take_while(|&b| b != 0)
+            .collect::<Vec<u8>>();
+        Self::new(String::from_utf8_lossy(&name), info.type_, info.tag)
+    }
+}
+
+/// Returns the fingerprints of all the BPF programs currently loaded
+pub fn loaded_programs() -> Result<Vec<ProgFingerprint>, io::Error> {
+    let mut out = vec![];
+    let mut id = 0;
+
+    loop {
+        id = match bpf_prog_get_next_id(id) {
+            Ok(id) => id,
+            // no more programs
+            Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+            Err(e) => return Err(e),
+        };
+
+        let fd = match bpf_prog_get_fd_by_id(id) {
+            Ok(fd) => fd,
+            // program got unloaded in the meantime
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+
+        out.push(ProgFingerprint::from_info(&bpf_prog_get_info(&fd)?));
+    }
+
+    Ok(out)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Discrepancy {
+    /// an expected program is not loaded
+    Missing(ProgFingerprint),
+    /// a loaded program has the name of an expected one but matches
+    /// none of the expected fingerprints with that name (i.e. it got
+    /// replaced or a twin got loaded next to it)
+    Unexpected(ProgFingerprint),
+}
+
+fn compare_programs(loaded: &[ProgFingerprint], expected: &[ProgFingerprint]) -> Vec<Discrepancy> {
+    let mut out = expected
+        .iter()
+        .filter(|exp| !loaded.iter().any(|l| l.name == exp.name))
+        .map(|exp| Discrepancy::Missing(exp.clone()))
+        .collect::<Vec<_>>();
+
+    // a genuine program being loaded must not hide a same-named twin
+    out.extend(
+        loaded
+            .iter()
+            .filter(|l| expected.iter().any(|exp| exp.name == l.name))
+            .filter(|l| !expected.contains(l))
+            .map(|l| Discrepancy::Unexpected(l.clone())),
+    );
+
+    out
+}
+
+/// Verifies that all the `expected` programs are loaded, reporting the ones
+/// missing and the ones looking like a replacement of an expected program.
+#[inline]
+pub fn verify_loaded_programs(expected: &[ProgFingerprint]) -> Result<Vec<Discrepancy>, io::Error> {
+    Ok(compare_programs(&loaded_programs()?, expected))
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("wrong tag")]
@@ -104,3 +227,507 @@ pub fn bpf_type_to_string(t: u32) -> String {
         .to_ascii_lowercase()
         .replace("bpf_prog_type_", "")
 }
+
+#[inline]
+fn bpf_map_get_info(map: BorrowedFd<'_>) -> Result<bpf_map_info, io::Error> {
+    let mut attr = unsafe { core::mem::zeroed::<bpf_attr>() };
+    // info gets entirely populated by the kernel
+    let info = unsafe { core::mem::zeroed::<bpf_map_info>() };
+
+    attr.info.bpf_fd = map.as_raw_fd() as u32;
+    attr.info.info = &info as *const _ as u64;
+    attr.info.info_len = core::mem::size_of::<bpf_map_info>() as u32;
+
+    sys_bpf(bpf_cmd::BPF_OBJ_GET_INFO_BY_FD, &attr)?;
+    Ok(info)
+}
+
+// see include/uapi/linux/bpf.h
+const BPF_RINGBUF_BUSY_BIT: u32 = 1 << 31;
+const BPF_RINGBUF_DISCARD_BIT: u32 = 1 << 30;
+const BPF_RINGBUF_HDR_SZ: u64 = 8;
+
+/// Fill ratio above which a ring buffer is considered saturated
+pub const RING_SATURATION_RATIO: f64 = 0.9;
+
+/// Ring buffer consumption metrics
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct RingStats {
+    /// records passed to the callback
+    pub records: u64,
+    /// bytes of record data passed to the callback
+    pub bytes: u64,
+    /// records discarded by the producer
+    pub discarded: u64,
+    /// number of polls which found pending data above [RING_SATURATION_RATIO]
+    /// of the buffer size. This is not a drop count: a producer failing to
+    /// reserve space leaves the positions untouched, so drops cannot be
+    /// observed from the consumer side and must be counted by the producers
+    /// (ex: on bpf_ringbuf_reserve failures). It only tells the consumer is
+    /// lagging and drops may happen.
+    pub saturated_polls: u64,
+    /// highest fill ratio observed
+    pub max_fill: f64,
+}
+
+impl RingStats {
+    fn observe_fill(&mut self, producer_pos: u64, consumer_pos: u64, size: u64) {
+        let fill = fill_ratio(producer_pos, consumer_pos, size);
+        if fill >= RING_SATURATION_RATIO {
+            self.saturated_polls += 1;
+        }
+        self.max_fill = self.max_fill.max(fill);
+    }
+}
+
+/// Ratio of the ring buffer holding data not consumed yet
+#[inline]
+fn fill_ratio(producer_pos: u64, consumer_pos: u64, size: u64) -> f64 {
+    if size == 0 {
+        return 0.0;
+    }
+    (producer_pos.saturating_sub(consumer_pos) as f64 / size as f64).min(1.0)
+}
+
+/// Raw view of a ring buffer memory layout. Data area is mapped twice
+/// in a row by the kernel so that records wrapping around the end of
+/// the buffer can be read contiguously.
+struct RingLayout {
+    consumer_pos: *const AtomicU64,
+    producer_pos: *const AtomicU64,
+    data: *const u8,
+    size: u64,
+}
+
+impl RingLayout {
+    /// Consumes all the records committed by the producers
+    fn consume<F: FnMut(&[u8])>(&self, stats: &mut RingStats, mut f: F) -> usize {
+        let (cons_pos, prod_pos) = unsafe { (&*self.consumer_pos, &*self.producer_pos) };
+        let mask = self.size - 1;
+        let mut count = 0;
+
+        let mut cons = cons_pos.load(Ordering::Acquire);
+        let prod = prod_pos.load(Ordering::Acquire);
+        stats.observe_fill(prod, cons, self.size);
+
+        while cons < prod {
+            let rec = unsafe { self.data.add((cons & mask) as usize) };
+            let hdr = unsafe { &*(rec as *const AtomicU32) }.load(Ordering::Acquire);
+
+            // record reserved but not committed yet
+            if hdr & BPF_RINGBUF_BUSY_BIT != 0 {
+                break;
+            }
+
+            let len = (hdr & !(BPF_RINGBUF_BUSY_BIT | BPF_RINGBUF_DISCARD_BIT)) as u64;
+            if hdr & BPF_RINGBUF_DISCARD_BIT == 0 {
+                let data = unsafe {
+                    core::slice::from_raw_parts(rec.add(BPF_RINGBUF_HDR_SZ as usize), len as usize)
+                };
+                f(data);
+                stats.records += 1;
+                stats.bytes += len;
+                count += 1;
+            } else {
+                stats.discarded += 1;
+            }
+
+            // records are 8 bytes aligned
+            cons += (len + BPF_RINGBUF_HDR_SZ + 7) & !7;
+            cons_pos.store(cons, Ordering::Release);
+        }
+
+        count
+    }
+}
+
+/// Memory mappings of a ring buffer map
+struct RingMmap {
+    consumer: *mut libc::c_void,
+    consumer_len: usize,
+    producer: *mut libc::c_void,
+    producer_len: usize,
+}
+
+impl Drop for RingMmap {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.consumer, self.consumer_len);
+            libc::munmap(self.producer, self.producer_len);
+        }
+    }
+}
+
+/// Consumer of a BPF ring buffer map (BPF_MAP_TYPE_RINGBUF) calling
+/// a callback for every record and keeping track of [RingStats].
+pub struct RingConsumer<T: AsFd, F: FnMut(&[u8])> {
+    map: T,
+    layout: RingLayout,
+    _mmap: RingMmap,
+    callback: F,
+    stats: RingStats,
+}
+
+impl<T: AsFd, F: FnMut(&[u8])> RingConsumer<T, F> {
+    pub fn new(map: T, callback: F) -> Result<Self, io::Error> {
+        let info = bpf_map_get_info(map.as_fd())?;
+        if info.type_ != bpf_map_type::BPF_MAP_TYPE_RINGBUF as u32 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "map is not a ring buffer",
+            ));
+        }
+
+        let page_size = page_size()? as usize;
+        let size = info.max_entries as usize;
+        let fd = map.as_fd().as_raw_fd();
+
+        // consumer position is the only page we write to
+        let consumer = unsafe {
+            libc::mmap(
+                core::ptr::null_mut(),
+                page_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            )
+        };
+        if consumer == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        // producer position page followed by the data mapped twice
+        let producer_len = page_size + 2 * size;
+        let producer = unsafe {
+            libc::mmap(
+                core::ptr::null_mut(),
+                producer_len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                fd,
+                page_size as libc::off_t,
+            )
+        };
+        if producer == libc::MAP_FAILED {
+            let err = io::Error::last_os_error();
+            unsafe { libc::munmap(consumer, page_size) };
+            return Err(err);
+        }
+
+        Ok(Self {
+            layout: RingLayout {
+                consumer_pos: consumer as *const AtomicU64,
+                producer_pos: producer as *const AtomicU64,
+                data: unsafe { (producer as *const u8).add(page_size) },
+                size: size as u64,
+            },
+            _mmap: RingMmap {
+                consumer,
+                consumer_len: page_size,
+                producer,
+                producer_len,
+            },
+            map,
+            callback,
+            stats: RingStats::default(),
+        })
+    }
+
+    /// Consumes available records, waiting for at most `timeout` (forever
+    /// if `None`) if there is none. Returns the number of records consumed.
+    pub fn poll(&mut self, timeout: Option<Duration>) -> Result<usize, io::Error> {
+        let n = self.layout.consume(&mut self.stats, &mut self.callback);
+        if n > 0 {
+            return Ok(n);
+        }
+
+        let mut pfd = libc::pollfd {
+            fd: self.map.as_fd().as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout
+            .map(|t| t.as_millis().min(i32::MAX as u128) as i32)
+            .unwrap_or(-1);
+
+        if unsafe { libc::poll(&mut pfd, 1, timeout) } < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(0);
+            }
+            return Err(err);
+        }
+
+        Ok(self.layout.consume(&mut self.stats, &mut self.callback))
+    }
+
+    /// Returns a snapshot of consumption metrics
+    #[inline(always)]
+    pub fn stats(&self) -> RingStats {
+        self.stats
+    }
+}
+
+/// Perf buffer counters of a single CPU
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PerfCpuStats {
+    pub consumed: u64,
+    pub lost: u64,
+}
+
+/// Perf buffers consumption metrics
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PerfStats {
+    pub per_cpu: BTreeMap<u32, PerfCpuStats>,
+    pub consumed: u64,
+    pub lost: u64,
+}
+
+impl PerfStats {
+    /// Percentage of the events produced that got lost
+    #[inline]
+    pub fn loss_percent(&self) -> f64 {
+        let total = self.consumed + self.lost;
+        if total == 0 {
+            return 0.0;
+        }
+        self.lost as f64 * 100.0 / total as f64
+    }
+}
+
+/// Accounting of events consumed and lost out of per-CPU perf buffers.
+/// It is meant to be shared (ex: in an Arc) by the tasks reading the
+/// buffers, each one reporting the [Events] returned by its reads.
+#[derive(Debug, Default)]
+pub struct PerfConsumer {
+    cpus: Mutex<BTreeMap<u32, PerfCpuStats>>,
+}
+
+impl PerfConsumer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accounts the result of a read of the perf buffer of `cpu`
+    pub fn on_events(&self, cpu: u32, events: &Events) {
+        // a poisoned lock only means another reader panicked while
+        // updating counters so it is safe to go on using them
+        let mut cpus = self.cpus.lock().unwrap_or_else(|e| e.into_inner());
+        let c = cpus.entry(cpu).or_default();
+        c.consumed += events.read as u64;
+        c.lost += events.lost as u64;
+    }
+
+    /// Returns a snapshot of the counters
+    pub fn stats(&self) -> PerfStats {
+        let cpus = self.cpus.lock().unwrap_or_else(|e| e.into_inner());
+        PerfStats {
+            consumed: cpus.values().map(|c| c.consumed).sum(),
+            lost: cpus.values().map(|c| c.lost).sum(),
+            per_cpu: cpus.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prog_fingerprint() {
+        let f = ProgFingerprint::new("kunai_execve_security_bprm_check", 2, [0; 8]);
+        assert_eq!(f.name, "kunai_execve_se");
+        assert_eq!(ProgFingerprint::new("short", 2, [0; 8]).name, "short");
+    }
+
+    #[test]
+    fn test_compare_programs() {
+        let kprobe = bpf_prog_type::BPF_PROG_TYPE_KPROBE as u32;
+        let expected = vec![
+            ProgFingerprint::new("execve", kprobe, [1; 8]),
+            ProgFingerprint::new("mmap_exec", kprobe, [2; 8]),
+            ProgFingerprint::new("connect", kprobe, [3; 8]),
+        ];
+
+        let replaced = ProgFingerprint::new("mmap_exec", kprobe, [0xff; 8]);
+        let loaded = vec![
+            ProgFingerprint::new("execve", kprobe, [1; 8]),
+            replaced.clone(),
+            // programs unrelated to ours are ignored
+            ProgFingerprint::new("sd_fw_ingress", 8, [4; 8]),
+        ];
+
+        assert_eq!(
+            compare_programs(&loaded, &expected),
+            vec![
+                Discrepancy::Missing(expected[2].clone()),
+                Discrepancy::Unexpected(replaced.clone()),
+            ]
+        );
+        assert!(compare_programs(&expected, &expected).is_empty());
+
+        // a rogue twin loaded next to the genuine program is reported
+        let mut loaded = expected.clone();
+        loaded.push(replaced.clone());
+        assert_eq!(
+            compare_programs(&loaded, &expected),
+            vec![Discrepancy::Unexpected(replaced)]
+        );
+    }
+
+    #[test]
+    fn test_verify_loaded_programs() {
+        // we may not be allowed to enumerate programs
+        if let Ok(loaded) = loaded_programs() {
+            assert!(verify_loaded_programs(&loaded).unwrap().is_empty());
+        }
+    }
+
+    /// In memory ring buffer mimicking the kernel layout
+    struct MockRing {
+        consumer: Box<AtomicU64>,
+        producer: Box<AtomicU64>,
+        // u64 for alignment, data is duplicated on the second half
+        data: Vec<u64>,
+        size: u64,
+    }
+
+    impl MockRing {
+        fn new(size: u64) -> Self {
+            Self {
+                consumer: Box::new(AtomicU64::new(0)),
+                producer: Box::new(AtomicU64::new(0)),
+                data: vec![0; (2 * size / 8) as usize],
+                size,
+            }
+        }
+
+        fn write(&mut self, hdr: u32, payload: &[u8]) {
+            let prod = self.producer.load(Ordering::Relaxed);
+            let mut rec = hdr.to_ne_bytes().to_vec();
+            rec.extend_from_slice(&[0; 4]);
+            rec.extend_from_slice(payload);
+
+            let size = self.size;
+            let bytes = unsafe {
+                core::slice::from_raw_parts_mut(
+                    self.data.as_mut_ptr() as *mut u8,
+                    2 * size as usize,
+                )
+            };
+            for (i, b) in rec.iter().enumerate() {
+                let off = (prod + i as u64) % size;
+                bytes[off as usize] = *b;
+                bytes[(off + size) as usize] = *b;
+            }
+            let len = (payload.len() as u64 + BPF_RINGBUF_HDR_SZ + 7) & !7;
+            self.producer.store(prod + len, Ordering::Release);
+        }
+
+        fn push(&mut self, payload: &[u8]) {
+            self.write(payload.len() as u32, payload)
+        }
+
+        fn layout(&self) -> RingLayout {
+            RingLayout {
+                consumer_pos: &*self.consumer,
+                producer_pos: &*self.producer,
+                data: self.data.as_ptr() as *const u8,
+                size: self.size,
+            }
+        }
+    }
+
+    #[test]
+    fn test_fill_ratio() {
+        assert_eq!(fill_ratio(0, 0, 4096), 0.0);
+        assert_eq!(fill_ratio(1024, 0, 4096), 0.25);
+        // positions only grow, they are not wrapped
+        assert_eq!(fill_ratio(8192 + 2048, 8192, 4096), 0.5);
+        assert_eq!(fill_ratio(4096, 0, 4096), 1.0);
+        assert_eq!(fill_ratio(0, 8, 4096), 0.0);
+
+        let mut stats = RingStats::default();
+        stats.observe_fill(1024, 0, 4096);
+        assert_eq!(stats.saturated_polls, 0);
+        stats.observe_fill(4000, 0, 4096);
+        stats.observe_fill(2048, 0, 4096);
+        assert_eq!(stats.saturated_polls, 1);
+        assert_eq!(stats.max_fill, 4000.0 / 4096.0);
+    }
+
+    #[test]
+    fn test_ring_consume() {
+        let mut ring = MockRing::new(64);
+        let mut stats = RingStats::default();
+        let mut got = vec![];
+
+        ring.push(b"hello");
+        ring.write(3 | BPF_RINGBUF_DISCARD_BIT, b"bad");
+        ring.push(b"kunai!!!");
+        assert_eq!(
+            ring.layout().consume(&mut stats, |d| got.push(d.to_vec())),
+            2
+        );
+        assert_eq!(got, vec![b"hello".to_vec(), b"kunai!!!".to_vec()]);
+        assert_eq!(ring.consumer.load(Ordering::Relaxed), 48);
+
+        // record wrapping around the end of the buffer
+        ring.push(b"wrapping record");
+        // not committed yet so consumption stops there
+        ring.write(1 | BPF_RINGBUF_BUSY_BIT, b"x");
+        got.clear();
+        assert_eq!(
+            ring.layout().consume(&mut stats, |d| got.push(d.to_vec())),
+            1
+        );
+        assert_eq!(got, vec![b"wrapping record".to_vec()]);
+        assert_eq!(ring.consumer.load(Ordering::Relaxed), 72);
+
+        assert_eq!(stats.records, 3);
+        assert_eq!(stats.bytes, 5 + 8 + 15);
+        assert_eq!(stats.discarded, 1);
+    }
+
+    #[test]
+    fn test_perf_consumer() {
+        let pc = std::sync::Arc::new(PerfConsumer::new());
+        assert_eq!(pc.stats(), PerfStats::default());
+        assert_eq!(pc.stats().loss_percent(), 0.0);
+
+        let readers = (0..4u32)
+            .map(|cpu| {
+                let pc = pc.clone();
+                std::thread::spawn(move || {
+                    for i in 0..100 {
+                        // only cpu 3 loses events, on one read out of ten
+                        let lost = if cpu == 3 && i % 10 == 0 { 5 } else { 0 };
+                        pc.on_events(cpu, &Events { read: 2, lost });
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        readers.into_iter().for_each(|t| t.join().unwrap());
+
+        let stats = pc.stats();
+        assert_eq!(stats.per_cpu.len(), 4);
+        assert_eq!(
+            stats.per_cpu[&0],
+            PerfCpuStats {
+                consumed: 200,
+                lost: 0
+            }
+        );
+        assert_eq!(
+            stats.per_cpu[&3],
+            PerfCpuStats {
+                consumed: 200,
+                lost: 50
+            }
+        );
+        assert_eq!(stats.consumed, 800);
+        assert_eq!(stats.lost, 50);
+        assert_eq!(stats.loss_percent(), 50.0 * 100.0 / 850.0);
+    }
+}
@@ -0,0 +1,335 @@
+//! This module contains helpers to inspect Linux capabilities, used to
+//! diagnose missing privileges before loading eBPF programs.
+use core::fmt;
+use std::io;
+use thiserror::Error;
+
+// see linux/capability.h
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+const LINUX_CAPABILITY_U32S_3: usize = 2;
+
+macro_rules! capabilities {
+    ($($variant:ident = $bit:literal => $name:literal),* $(,)?) => {
+        /// Linux capabilities, discriminants are the capability numbers
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        #[repr(u32)]
+        pub enum Capability {
+            $($variant = $bit),*
+        }
+
+        impl Capability {
+            pub const ALL: &'static [Capability] = &[$(Self::$variant),*];
+
+            #[inline]
+            pub fn from_bit(bit: u32) -> Option<Self> {
+                match bit {
+                    $($bit => Some(Self::$variant),)*
+                    _ => None,
+                }
+            }
+
+            #[inline]
+            pub const fn as_str(&self) -> &'static str {
+                match self {
+                    $(Self::$variant => $name),*
+                }
+            }
+        }
+    };
+}
+
+capabilities! {
+    Chown = 0 => "CAP_CHOWN",
+    DacOverride = 1 => "CAP_DAC_OVERRIDE",
+    DacReadSearch = 2 => "CAP_DAC_READ_SEARCH",
+    Fowner = 3 => "CAP_FOWNER",
+    Fsetid = 4 => "CAP_FSETID",
+    Kill = 5 => "CAP_KILL",
+    Setgid = 6 => "CAP_SETGID",
+    Setuid = 7 => "CAP_SETUID",
+    Setpcap = 8 => "CAP_SETPCAP",
+    LinuxImmutable = 9 => "CAP_LINUX_IMMUTABLE",
+    NetBindService = 10 => "CAP_NET_BIND_SERVICE",
+    NetBroadcast = 11 => "CAP_NET_BROADCAST",
+    NetAdmin = 12 => "CAP_NET_ADMIN",
+    NetRaw = 13 => "CAP_NET_RAW",
+    IpcLock = 14 => "CAP_IPC_LOCK",
+    IpcOwner = 15 => "CAP_IPC_OWNER",
+    SysModule = 16 => "CAP_SYS_MODULE",
+    SysRawio = 17 => "CAP_SYS_RAWIO",
+    SysChroot = 18 => "CAP_SYS_CHROOT",
+    SysPtrace = 19 => "CAP_SYS_PTRACE",
+    SysPacct = 20 => "CAP_SYS_PACCT",
+    SysAdmin = 21 => "CAP_SYS_ADMIN",
+    SysBoot = 22 => "CAP_SYS_BOOT",
+    SysNice = 23 => "CAP_SYS_NICE",
+    SysResource = 24 => "CAP_SYS_RESOURCE",
+    SysTime = 25 => "CAP_SYS_TIME",
+    SysTtyConfig = 26 => "CAP_SYS_TTY_CONFIG",
+    Mknod = 27 => "CAP_MKNOD",
+    Lease = 28 => "CAP_LEASE",
+    AuditWrite = 29 => "CAP_AUDIT_WRITE",
+    AuditControl = 30 => "CAP_AUDIT_CONTROL",
+    Setfcap = 31 => "CAP_SETFCAP",
+    MacOverride = 32 => "CAP_MAC_OVERRIDE",
+    MacAdmin = 33 => "CAP_MAC_ADMIN",
+    Syslog = 34 => "CAP_SYSLOG",
+    WakeAlarm = 35 => "CAP_WAKE_ALARM",
+    BlockSuspend = 36 => "CAP_BLOCK_SUSPEND",
+    AuditRead = 37 => "CAP_AUDIT_READ",
+    Perfmon = 38 => "CAP_PERFMON",
+    Bpf = 39 => "CAP_BPF",
+    CheckpointRestore = 40 => "CAP_CHECKPOINT_RESTORE",
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Decodes a capability mask (as found in /proc/<pid>/status or returned
+/// by capget) into the list of capabilities it holds. Bits unknown to us
+/// (capabilities added by newer kernels) are ignored.
+pub fn decode_caps(mask: u64) -> Vec<Capability> {
+    (0..64)
+        .filter(|bit| mask & (1 << bit) != 0)
+        .filter_map(Capability::from_bit)
+        .collect()
+}
+
+/// Capability sets of a thread
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CapSet {
+    pub effective: Vec<Capability>,
+    pub permitted: Vec<Capability>,
+    pub inheritable: Vec<Capability>,
+}
+
+impl CapSet {
+    #[inline]
+    pub fn from_masks(effective: u64, permitted: u64, inheritable: u64) -> Self {
+        Self {
+            effective: decode_caps(effective),
+            permitted: decode_caps(permitted),
+            inheritable: decode_caps(inheritable),
+        }
+    }
+
+    /// Returns true if `cap` is in the effective set
+    #[inline(always)]
+    pub fn has_effective(&self, cap: Capability) -> bool {
+        self.effective.contains(&cap)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CapError {
+    #[error("failed to get capabilities: {0}")]
+    Io(#[from] io::Error),
+    #[error("missing capabilities {}, {hint}", .missing.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(","))]
+    Missing {
+        missing: Vec<Capability>,
+        hint: String,
+    },
+}
+
+impl CapError {
+    fn missing(missing: Vec<Capability>) -> Self {
+        let exe = std::env::current_exe()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or("<path to kunai>".into());
+        let caps = missing
+            .iter()
+            .map(|c| c.as_str().to_lowercase())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        Self::Missing {
+            hint: format!("run as root or grant them with: setcap {caps}+ep {exe}"),
+            missing,
+        }
+    }
+}
+
+/// Returns the capabilities in `required` not in the effective set of `held`
+pub fn missing_capabilities(held: &CapSet, required: &[Capability]) -> Vec<Capability> {
+    required
+        .iter()
+        .filter(|c| !held.has_effective(**c))
+        .copied()
+        .collect()
+}
+
+/// Checks that `held` has all the `required` capabilities in its effective set
+pub fn check_capabilities(held: &CapSet, required: &[Capability]) -> Result<(), CapError> {
+    let missing = missing_capabilities(held, required);
+    if !missing.is_empty() {
+        return Err(CapError::missing(missing));
+    }
+    Ok(())
+}
+
+/// Checks that `held` has all the capabilities of at least one of the
+/// `alternatives` in its effective set (ex: CAP_BPF and CAP_PERFMON, or
+/// CAP_SYS_ADMIN which the kernel still accepts in their place). When none
+/// is held, the capabilities missing from the first alternative, assumed
+/// to be the preferred one, are reported.
+pub fn check_any_capabilities(
+    held: &CapSet,
+    alternatives: &[&[Capability]],
+) -> Result<(), CapError> {
+    let mut first_missing = None;
+    for required in alternatives {
+        let missing = missing_capabilities(held, required);
+        if missing.is_empty() {
+            return Ok(());
+        }
+        first_missing.get_or_insert(missing);
+    }
+
+    match first_missing {
+        Some(missing) => Err(CapError::missing(missing)),
+        None => Ok(()),
+    }
+}
+
+/// Same as [check_required_capabilities] but passes if the current thread
+/// holds any of the `alternatives` (see [check_any_capabilities])
+#[inline]
+pub fn check_required_any_capabilities(alternatives: &[&[Capability]]) -> Result<(), CapError> {
+    check_any_capabilities(&current_capabilities()?, alternatives)
+}
+
+/// Checks that the current thread has all the `required` capabilities. This
+/// must be called before loading eBPF programs so that the user gets an
+/// actionable error rather than an EPERM deep in the loading process.
+#[inline]
+pub fn check_required_capabilities(required: &[Capability]) -> Result<(), CapError> {
+    check_capabilities(&current_capabilities()?, required)
+}
+
+#[repr(C)]
+struct CapUserHeader {
+    version: u32,
+    pid: i32,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// Returns the capabilities of the calling thread, using capget(2)
+pub fn current_capabilities() -> io::Result<CapSet> {
+    let mut hdr = CapUserHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let mut data = [CapUserData::default(); LINUX_CAPABILITY_U32S_3];
+
+    if unsafe { libc::syscall(libc::SYS_capget, &mut hdr, data.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // v3 format splits 64 bits masks over two u32
+    let mask = |f: fn(&CapUserData) -> u32| (f(&data[1]) as u64) << 32 | f(&data[0]) as u64;
+
+    Ok(CapSet::from_masks(
+        mask(|d| d.effective),
+        mask(|d| d.permitted),
+        mask(|d| d.inheritable),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::util::proc::status_field;
+
+    use super::*;
+
+    #[test]
+    fn test_decode_caps() {
+        assert!(decode_caps(0).is_empty());
+        assert_eq!(
+            decode_caps(1 << 21 | 1 << 39 | 1 << 63),
+            vec![Capability::SysAdmin, Capability::Bpf]
+        );
+        assert_eq!(decode_caps(u64::MAX).len(), Capability::ALL.len());
+        assert_eq!(Capability::Perfmon.to_string(), "CAP_PERFMON");
+        assert_eq!(
+            Capability::from_bit(40),
+            Some(Capability::CheckpointRestore)
+        );
+    }
+
+    #[test]
+    fn test_current_capabilities() {
+        let caps = current_capabilities().unwrap();
+        // kernel guarantees effective set is a subset of the permitted set
+        assert!(caps.effective.iter().all(|c| caps.permitted.contains(c)));
+
+        // must be consistent with what procfs reports for this thread
+        let status = std::fs::read_to_string("/proc/thread-self/status").unwrap();
+        let mask = |name| u64::from_str_radix(status_field(&status, name).unwrap(), 16).unwrap();
+        assert_eq!(
+            caps,
+            CapSet::from_masks(mask("CapEff"), mask("CapPrm"), mask("CapInh"))
+        );
+    }
+
+    #[test]
+    fn test_check_capabilities() {
+        let held = CapSet::from_masks(1 << 21 | 1 << 38, 1 << 21 | 1 << 38, 0);
+
+        assert!(check_capabilities(&held, &[]).is_ok());
+        assert!(check_capabilities(&held, &[Capability::SysAdmin]).is_ok());
+        assert!(check_capabilities(&held, &[Capability::Perfmon, Capability::SysAdmin]).is_ok());
+
+        let err = check_capabilities(
+            &held,
+            &[Capability::Bpf, Capability::Perfmon, Capability::NetAdmin],
+        )
+        .unwrap_err();
+        let CapError::Missing { missing, hint } = &err else {
+            panic!("unexpected error {err}");
+        };
+        assert_eq!(missing, &vec![Capability::Bpf, Capability::NetAdmin]);
+        assert!(hint.contains("setcap cap_bpf,cap_net_admin+ep"));
+        assert!(err
+            .to_string()
+            .starts_with("missing capabilities CAP_BPF,CAP_NET_ADMIN"));
+
+        // only the effective set matters
+        let held = CapSet::from_masks(0, 1 << 39, 0);
+        assert!(check_capabilities(&held, &[Capability::Bpf]).is_err());
+    }
+
+    #[test]
+    fn test_check_any_capabilities() {
+        let alternatives: &[&[Capability]] = &[
+            &[Capability::Bpf, Capability::Perfmon],
+            &[Capability::SysAdmin],
+        ];
+
+        // container runtime not knowing about CAP_BPF
+        let sys_admin = CapSet::from_masks(1 << 21, 1 << 21, 0);
+        assert!(check_any_capabilities(&sys_admin, alternatives).is_ok());
+
+        let bpf_perfmon = CapSet::from_masks(1 << 38 | 1 << 39, 1 << 38 | 1 << 39, 0);
+        assert!(check_any_capabilities(&bpf_perfmon, alternatives).is_ok());
+
+        // partial sets are not enough
+        let bpf = CapSet::from_masks(1 << 39, 1 << 39, 0);
+        let err = check_any_capabilities(&bpf, alternatives).unwrap_err();
+        let CapError::Missing { missing, .. } = &err else {
+            panic!("unexpected error {err}");
+        };
+        assert_eq!(missing, &vec![Capability::Perfmon]);
+
+        assert!(check_any_capabilities(&bpf, &[]).is_ok());
+    }
+}
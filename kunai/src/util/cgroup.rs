@@ -0,0 +1,144 @@
+//! This module contains helpers to read cgroup v2 resource
+//! accounting information of processes.
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::proc::{invalid_data, proc_path};
+
+const CGROUP2_ROOT: &str = "/sys/fs/cgroup";
+
+/// Resource usage of the cgroup a process belongs to. Fields are
+/// `None` when the corresponding controller is not enabled for
+/// the cgroup.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CgroupStats {
+    /// cgroup path relative to the cgroup2 mount point
+    pub path: PathBuf,
+    /// memory.current in bytes
+    pub memory_current: Option<u64>,
+    /// memory.max in bytes, `None` if unlimited
+    pub memory_max: Option<u64>,
+    /// usage_usec field of cpu.stat
+    pub cpu_usage_usec: Option<u64>,
+}
+
+/// Extracts the unified hierarchy path from the content of /proc/<pid>/cgroup
+#[inline]
+fn parse_cgroup_v2_path(content: &str) -> Option<&str> {
+    content.lines().find_map(|l| l.strip_prefix("0::"))
+}
+
+/// Parses memory.max, which is either a number of bytes or "max"
+#[inline]
+fn parse_memory_max(s: &str) -> io::Result<Option<u64>> {
+    match s.trim() {
+        "max" => Ok(None),
+        v => v.parse().map(Some).map_err(invalid_data),
+    }
+}
+
+/// Parses cpu.stat content to extract usage_usec
+#[inline]
+fn parse_cpu_stat_usage(content: &str) -> io::Result<Option<u64>> {
+    content
+        .lines()
+        .find_map(|l| l.strip_prefix("usage_usec "))
+        .map(|v| v.trim().parse().map_err(invalid_data))
+        .transpose()
+}
+
+#[inline]
+fn read_optional(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(s) => Ok(Some(s)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Reads cgroup v2 resource statistics of process `pid`. An error of kind
+/// [io::ErrorKind::Unsupported] is returned if the host does not use the
+/// cgroup v2 unified hierarchy.
+pub fn read_cgroup_stats(pid: i32) -> io::Result<CgroupStats> {
+    let root = Path::new(CGROUP2_ROOT);
+
+    // cgroup.controllers only exists at the root of a cgroup2 mount
+    if !root.join("cgroup.controllers").exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "cgroup v2 is not mounted",
+        ));
+    }
+
+    let content = fs::read_to_string(proc_path(pid, "cgroup"))?;
+    let rel = parse_cgroup_v2_path(&content)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "no cgroup v2 entry"))?;
+
+    let dir = root.join(rel.trim_start_matches('/'));
+
+    Ok(CgroupStats {
+        path: PathBuf::from(rel),
+        memory_current: read_optional(&dir.join("memory.current"))?
+            .map(|s| s.trim().parse().map_err(invalid_data))
+            .transpose()?,
+        memory_max: read_optional(&dir.join("memory.max"))?
+            .map(|s| parse_memory_max(&s))
+            .transpose()?
+            .flatten(),
+        cpu_usage_usec: read_optional(&dir.join("cpu.stat"))?
+            .map(|s| parse_cpu_stat_usage(&s))
+            .transpose()?
+            .flatten(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_cgroup_path() {
+        assert_eq!(
+            parse_cgroup_v2_path("0::/system.slice/docker-42.scope\n"),
+            Some("/system.slice/docker-42.scope")
+        );
+        // cgroup v1 only host
+        assert_eq!(
+            parse_cgroup_v2_path("12:memory:/user.slice\n11:cpu,cpuacct:/user.slice\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_stat_files() {
+        assert_eq!(parse_memory_max("max\n").unwrap(), None);
+        assert_eq!(parse_memory_max("536870912\n").unwrap(), Some(536870912));
+        assert!(parse_memory_max("foo").is_err());
+
+        let cpu_stat = r#"usage_usec 8123456
+user_usec 6000000
+system_usec 2123456
+nr_periods 0
+nr_throttled 0
+throttled_usec 0"#;
+        assert_eq!(parse_cpu_stat_usage(cpu_stat).unwrap(), Some(8123456));
+        assert_eq!(parse_cpu_stat_usage("user_usec 42").unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_cgroup_stats() {
+        match read_cgroup_stats(std::process::id() as i32) {
+            Ok(s) => {
+                let content = fs::read_to_string("/proc/self/cgroup").unwrap();
+                assert_eq!(
+                    Some(s.path.as_path()),
+                    parse_cgroup_v2_path(&content).map(Path::new)
+                );
+                assert!(s.path.is_absolute());
+            }
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::Unsupported),
+        }
+    }
+}
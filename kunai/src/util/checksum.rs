@@ -0,0 +1,117 @@
+//! This module contains non cryptographic checksums and hashes, cheap to
+//! compute but not suitable where resistance to malicious collisions is
+//! required.
+use std::io::{self, Read};
+
+use super::read_chunks;
+
+/// Streaming CRC-32 (zlib polynomial, as used by .gnu_debuglink)
+#[derive(Debug, Default)]
+pub struct Crc32(flate2::Crc);
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline(always)]
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data)
+    }
+
+    /// Updates the checksum with all the data read from `r`
+    pub fn update_reader<R: Read>(&mut self, r: R) -> io::Result<()> {
+        read_chunks(r, |chunk| self.update(chunk))
+    }
+
+    #[inline(always)]
+    pub fn sum(&self) -> u32 {
+        self.0.sum()
+    }
+}
+
+/// Computes the CRC-32 of `data`
+#[inline]
+pub fn crc32_data<T: AsRef<[u8]>>(data: T) -> u32 {
+    let mut c = Crc32::new();
+    c.update(data.as_ref());
+    c.sum()
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Computes the 64 bits FNV-1a hash of `data`. This is meant to build in
+/// memory keys (ex: event deduplication) and must never be used where an
+/// attacker could benefit from forging collisions (integrity checks, IoC
+/// matching), a cryptographic hash must be used there.
+#[inline]
+pub fn fast_hash<T: AsRef<[u8]>>(data: T) -> u64 {
+    data.as_ref().iter().fold(FNV_OFFSET_BASIS, |h, &b| {
+        (h ^ b as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Combines hash `h` into `seed` to build composite keys out of several
+/// [fast_hash] values. The combination depends on the order of the values.
+#[inline]
+pub fn fast_hash_combine(seed: u64, h: u64) -> u64 {
+    seed ^ h
+        .wrapping_add(0x9e3779b97f4a7c15)
+        .wrapping_add(seed << 6)
+        .wrapping_add(seed >> 2)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32_data(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32_data(b""), 0);
+
+        let data = (0..10_000u32)
+            .flat_map(|i| i.to_le_bytes())
+            .collect::<Vec<u8>>();
+        let mut c = Crc32::new();
+        data.chunks(333).for_each(|d| c.update(d));
+        assert_eq!(c.sum(), crc32_data(&data));
+
+        let mut r = Crc32::new();
+        r.update_reader(io::Cursor::new(&data)).unwrap();
+        assert_eq!(r.sum(), c.sum());
+    }
+
+    #[test]
+    fn test_fast_hash() {
+        assert_eq!(fast_hash(b""), 0xcbf29ce484222325);
+        assert_eq!(fast_hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fast_hash(b"foobar"), 0x85944171f73967e8);
+
+        let (a, b) = (fast_hash(b"a"), fast_hash(b"b"));
+        assert_ne!(fast_hash_combine(a, b), fast_hash_combine(b, a));
+        assert_eq!(fast_hash_combine(a, b), fast_hash_combine(a, b));
+    }
+
+    #[test]
+    fn test_fast_hash_distribution() {
+        const N: usize = 100_000;
+        const BUCKETS: usize = 64;
+
+        let hashes = (0..N)
+            .map(|i| fast_hash(format!("/proc/{i}/exe")))
+            .collect::<std::collections::HashSet<u64>>();
+        // no collision expected on such a small set
+        assert_eq!(hashes.len(), N);
+
+        let mut buckets = [0usize; BUCKETS];
+        hashes
+            .iter()
+            .for_each(|h| buckets[(h % BUCKETS as u64) as usize] += 1);
+        let expected = N / BUCKETS;
+        assert!(buckets
+            .iter()
+            .all(|&b| b > expected * 8 / 10 && b < expected * 12 / 10));
+    }
+}
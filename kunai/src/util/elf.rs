@@ -1,7 +1,23 @@
-use object::{self, Object, ObjectSection, ObjectSymbol};
-use std::collections::HashMap;
+use object::{
+    self,
+    elf::{DT_NEEDED, DT_RPATH, DT_RUNPATH, ELFMAG, PT_LOAD, SHF_EXECINSTR, SHT_NOBITS},
+    read::elf::{Dyn, FileHeader, ProgramHeader, SectionHeader},
+    Endianness, FileKind, Object, ObjectSection, ObjectSymbol, SectionFlags, SymbolKind,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, Read},
+    os::unix::{ffi::OsStrExt, fs::PermissionsExt},
+    path::{Path, PathBuf},
+};
 use thiserror::Error;
 
+use super::{
+    checksum::Crc32,
+    entropy::{shannon_entropy, PACKED_THRESHOLD},
+};
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("object: {0}")]
@@ -46,13 +62,917 @@ impl ElfInfo {
     }
 }
 
+#[inline(always)]
+fn invalid_elf(e: object::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[inline(always)]
+fn is_executable(section: &object::Section<'_, '_>) -> bool {
+    matches!(section.flags(), SectionFlags::Elf { sh_flags } if sh_flags & SHF_EXECINSTR as u64 != 0)
+}
+
+/// Computes the entropy of named sections of raw ELF `data`
+fn section_entropies(data: &[u8]) -> io::Result<Vec<(String, f64, bool)>> {
+    let obj = object::read::File::parse(data).map_err(invalid_elf)?;
+    let mut out = vec![];
+
+    for section in obj.sections() {
+        let Ok(name) = section.name() else {
+            continue;
+        };
+        if name.is_empty() {
+            continue;
+        }
+        // SHT_NOBITS sections (.bss) have no data in file
+        let data = section.data().map_err(invalid_elf)?;
+        out.push((
+            name.to_string(),
+            shannon_entropy(data),
+            is_executable(&section),
+        ));
+    }
+
+    Ok(out)
+}
+
+/// Returns the Shannon entropy of every named section of ELF file at `path`
+pub fn elf_section_entropies(path: &Path) -> io::Result<BTreeMap<String, f64>> {
+    Ok(section_entropies(&fs::read(path)?)?
+        .into_iter()
+        .map(|(name, e, _)| (name, e))
+        .collect())
+}
+
+#[inline]
+fn suspicious_sections_of(data: &[u8], threshold: Option<f64>) -> io::Result<Vec<(String, f64)>> {
+    let threshold = threshold.unwrap_or(PACKED_THRESHOLD);
+    Ok(section_entropies(data)?
+        .into_iter()
+        .filter(|(_, e, exec)| *exec && *e > threshold)
+        .map(|(name, e, _)| (name, e))
+        .collect())
+}
+
+/// Returns the executable sections of ELF file at `path` having an entropy
+/// above `threshold` ([PACKED_THRESHOLD] if `None`), a sign of packing.
+pub fn suspicious_sections(path: &Path, threshold: Option<f64>) -> io::Result<Vec<(String, f64)>> {
+    suspicious_sections_of(&fs::read(path)?, threshold)
+}
+
+/// Structural anomaly found in an ELF file. Such anomalies are not
+/// produced by regular linkers and often denote tampering or hand
+/// crafted binaries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ElfAnomaly {
+    /// file does not have any section header
+    NoSectionHeaders,
+    /// section header table is not within the file
+    SectionHeadersPastEof,
+    /// entrypoint does not belong to any executable section
+    EntrypointOutsideExecSection(u64),
+    /// indexes of two loadable segments with overlapping address ranges
+    OverlappingSegments(usize, usize),
+    /// the number of program headers declared in the ELF
+    /// header does not match the program header table found
+    ProgramHeaderCountMismatch { declared: usize, available: usize },
+    /// section data located (partially) after the end of file
+    SectionPastEof { index: usize, offset: u64 },
+}
+
+fn anomalies<Elf: FileHeader<Endian = Endianness>>(data: &[u8]) -> io::Result<Vec<ElfAnomaly>> {
+    let mut out = vec![];
+    let hdr = Elf::parse(data).map_err(invalid_elf)?;
+    let endian = hdr.endian().map_err(invalid_elf)?;
+    let file_len = data.len() as u64;
+
+    // program headers
+    let declared = hdr
+        .phnum(endian, data)
+        .unwrap_or(hdr.e_phnum(endian) as usize);
+    match hdr.program_headers(endian, data) {
+        Ok(phdrs) => {
+            let loads = phdrs
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| p.p_type(endian) == PT_LOAD)
+                .map(|(i, p)| {
+                    let start: u64 = p.p_vaddr(endian).into();
+                    (i, start, start.saturating_add(p.p_memsz(endian).into()))
+                })
+                .collect::<Vec<(usize, u64, u64)>>();
+
+            for (k, (i, start, end)) in loads.iter().enumerate() {
+                for (j, ostart, oend) in loads.iter().skip(k + 1) {
+                    if start < oend && ostart < end {
+                        out.push(ElfAnomaly::OverlappingSegments(*i, *j));
+                    }
+                }
+            }
+        }
+        Err(_) => {
+            let phoff: u64 = hdr.e_phoff(endian).into();
+            let entsize = core::mem::size_of::<Elf::ProgramHeader>() as u64;
+            let available = if hdr.e_phentsize(endian) as u64 == entsize {
+                (file_len.saturating_sub(phoff) / entsize) as usize
+            } else {
+                0
+            };
+            out.push(ElfAnomaly::ProgramHeaderCountMismatch {
+                declared,
+                available,
+            });
+        }
+    }
+
+    // section headers
+    let sections = match hdr.section_headers(endian, data) {
+        Ok(s) => s,
+        Err(_) => {
+            out.push(ElfAnomaly::SectionHeadersPastEof);
+            return Ok(out);
+        }
+    };
+
+    if sections.is_empty() {
+        out.push(ElfAnomaly::NoSectionHeaders);
+        return Ok(out);
+    }
+
+    for (index, sh) in sections.iter().enumerate() {
+        if sh.sh_type(endian) == SHT_NOBITS {
+            continue;
+        }
+        let offset: u64 = sh.sh_offset(endian).into();
+        let size: u64 = sh.sh_size(endian).into();
+        if offset.saturating_add(size) > file_len {
+            out.push(ElfAnomaly::SectionPastEof { index, offset });
+        }
+    }
+
+    // relocatable objects and some shared objects have a null entrypoint
+    let entry: u64 = hdr.e_entry(endian).into();
+    if entry != 0
+        && !sections.iter().any(|sh| {
+            let flags: u64 = sh.sh_flags(endian).into();
+            let addr: u64 = sh.sh_addr(endian).into();
+            let size: u64 = sh.sh_size(endian).into();
+            flags & SHF_EXECINSTR as u64 != 0 && entry >= addr && entry < addr.saturating_add(size)
+        })
+    {
+        out.push(ElfAnomaly::EntrypointOutsideExecSection(entry));
+    }
+
+    Ok(out)
+}
+
+#[inline]
+fn elf_anomalies_of(data: &[u8]) -> io::Result<Vec<ElfAnomaly>> {
+    match FileKind::parse(data).map_err(invalid_elf)? {
+        FileKind::Elf32 => anomalies::<object::elf::FileHeader32<Endianness>>(data),
+        FileKind::Elf64 => anomalies::<object::elf::FileHeader64<Endianness>>(data),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an ELF file",
+        )),
+    }
+}
+
+/// Checks ELF file at `path` for structural anomalies. An empty
+/// list is returned if nothing unusual has been found.
+pub fn elf_anomalies(path: &Path) -> io::Result<Vec<ElfAnomaly>> {
+    elf_anomalies_of(&fs::read(path)?)
+}
+
+/// Returns the (tag, string) of all the string valued entries of
+/// the dynamic section. An ELF without dynamic section is not an error.
+fn dynamic_strings<Elf: FileHeader<Endian = Endianness>>(
+    data: &[u8],
+) -> io::Result<Vec<(u32, String)>> {
+    let hdr = Elf::parse(data).map_err(invalid_elf)?;
+    let endian = hdr.endian().map_err(invalid_elf)?;
+    let sections = hdr.sections(endian, data).map_err(invalid_elf)?;
+
+    let Some((dyns, link)) = sections.dynamic(endian, data).map_err(invalid_elf)? else {
+        return Ok(vec![]);
+    };
+    let strings = sections.strings(endian, data, link).map_err(invalid_elf)?;
+
+    let mut out = vec![];
+    for d in dyns {
+        if !d.is_string(endian) {
+            continue;
+        }
+        // is_string guarantees tag fits in u32
+        let tag = d.tag32(endian).unwrap_or_default();
+        let s = d.string(endian, strings).map_err(invalid_elf)?;
+        out.push((tag, String::from_utf8_lossy(s).into_owned()));
+    }
+    Ok(out)
+}
+
+#[inline]
+fn dynamic_strings_of(data: &[u8]) -> io::Result<Vec<(u32, String)>> {
+    match FileKind::parse(data).map_err(invalid_elf)? {
+        FileKind::Elf32 => dynamic_strings::<object::elf::FileHeader32<Endianness>>(data),
+        FileKind::Elf64 => dynamic_strings::<object::elf::FileHeader64<Endianness>>(data),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an ELF file",
+        )),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RPathIssue {
+    /// entry is relative to the current working directory
+    Relative,
+    /// entry is a world writable directory
+    WorldWritable,
+}
+
+/// Library search paths embedded in an ELF
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RPaths {
+    pub rpath: Option<String>,
+    pub runpath: Option<String>,
+    // directory containing the ELF, used to expand $ORIGIN
+    origin: Option<PathBuf>,
+}
+
+impl RPaths {
+    fn from_dynamic_strings(strings: Vec<(u32, String)>) -> Self {
+        let mut out = Self::default();
+        for (tag, s) in strings {
+            match tag {
+                DT_RPATH => out.rpath = Some(s),
+                DT_RUNPATH => out.runpath = Some(s),
+                _ => {}
+            }
+        }
+        out
+    }
+
+    /// Iterates over all the directories of DT_RPATH and DT_RUNPATH
+    pub fn entries(&self) -> impl Iterator<Item = &str> {
+        self.rpath
+            .iter()
+            .chain(self.runpath.iter())
+            .flat_map(|s| s.split(':'))
+    }
+
+    /// Returns the entries which can be abused to hijack library loading
+    pub fn insecure_entries(&self) -> Vec<(String, RPathIssue)> {
+        let mut out = vec![];
+        for e in self.entries() {
+            let dir = match (e.strip_prefix("$ORIGIN"), self.origin.as_ref()) {
+                (Some(rest), Some(origin)) => {
+                    PathBuf::from(format!("{}{rest}", origin.to_string_lossy()))
+                }
+                _ => PathBuf::from(e),
+            };
+
+            // an empty entry means the current directory
+            if !dir.is_absolute() {
+                out.push((e.to_string(), RPathIssue::Relative));
+                continue;
+            }
+
+            if let Ok(meta) = fs::metadata(&dir) {
+                if meta.is_dir() && meta.permissions().mode() & 0o002 != 0 {
+                    out.push((e.to_string(), RPathIssue::WorldWritable));
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Reads DT_RPATH and DT_RUNPATH of ELF file at `path`
+pub fn elf_rpaths(path: &Path) -> io::Result<RPaths> {
+    let mut rpaths = RPaths::from_dynamic_strings(dynamic_strings_of(&fs::read(path)?)?);
+    rpaths.origin = path.parent().map(|p| p.to_path_buf());
+    Ok(rpaths)
+}
+
+/// Returns the shared libraries (DT_NEEDED entries) needed by ELF file at
+/// `path`, in the order they appear. A statically linked ELF returns an
+/// empty list.
+pub fn elf_needed_libraries(path: &Path) -> io::Result<Vec<String>> {
+    Ok(dynamic_strings_of(&fs::read(path)?)?
+        .into_iter()
+        .filter(|(tag, _)| *tag == DT_NEEDED)
+        .map(|(_, s)| s)
+        .collect())
+}
+
+/// Returns true if file at `path` starts with the ELF magic. Only
+/// the first bytes of the file are read.
+pub fn is_elf(path: &Path) -> io::Result<bool> {
+    let mut magic = [0u8; 4];
+    match fs::File::open(path)?.read_exact(&mut magic) {
+        Ok(()) => Ok(magic == ELFMAG),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn symbolize_in(data: &[u8], vaddr: u64) -> io::Result<Option<(String, u64)>> {
+    let obj = object::File::parse(data).map_err(invalid_elf)?;
+
+    let lookup = |symbols: &mut dyn Iterator<Item = object::Symbol<'_, '_>>| {
+        symbols
+            .filter(|s| s.kind() == SymbolKind::Text && s.address() != 0)
+            .filter(|s| s.address() <= vaddr)
+            // symbols of unknown size are considered to extend up to vaddr
+            .filter(|s| s.size() == 0 || vaddr < s.address() + s.size())
+            .max_by_key(|s| (s.address(), s.size()))
+            .and_then(|s| Some((s.name().ok()?.to_string(), vaddr - s.address())))
+    };
+
+    // .dynsym only contains exported symbols, so it is used as a fallback
+    Ok(lookup(&mut obj.symbols()).or_else(|| lookup(&mut obj.dynamic_symbols())))
+}
+
+/// Symbolizes `addr`, an address within the mapping of ELF file at `path`
+/// loaded with `load_bias` (the difference between the address the ELF is
+/// mapped at and its link address, 0 for non PIE executables). The name of
+/// the function containing the address and the offset within it are
+/// returned. `None` is returned if no function is found, which is always
+/// the case for binaries stripped from their symbols.
+pub fn elf_symbolize(path: &Path, addr: u64, load_bias: u64) -> io::Result<Option<(String, u64)>> {
+    let Some(vaddr) = addr.checked_sub(load_bias) else {
+        return Ok(None);
+    };
+    symbolize_in(&fs::read(path)?, vaddr)
+}
+
+/// Standard directory holding separate debug information files
+pub const DEBUG_DIR: &str = "/usr/lib/debug";
+
+fn file_crc32(path: &Path) -> io::Result<u32> {
+    let mut crc = Crc32::new();
+    crc.update_reader(fs::File::open(path)?)?;
+    Ok(crc.sum())
+}
+
+/// Returns the locations where debug information of ELF file at `path` may
+/// be found, in the order they must be tried, along with the CRC expected
+/// for debuglink candidates.
+fn debug_info_candidates(
+    path: &Path,
+    data: &[u8],
+    debug_dir: &Path,
+) -> io::Result<Vec<(PathBuf, Option<u32>)>> {
+    let obj = object::File::parse(data).map_err(invalid_elf)?;
+    let mut out = vec![];
+
+    // .build-id/xx/yyyyyyyy.debug
+    if let Some(id) = obj
+        .build_id()
+        .map_err(invalid_elf)?
+        .filter(|id| id.len() > 1)
+    {
+        let id = hex::encode(id);
+        out.push((
+            debug_dir
+                .join(".build-id")
+                .join(&id[..2])
+                .join(format!("{}.debug", &id[2..])),
+            None,
+        ));
+    }
+
+    if let Some((name, crc)) = obj.gnu_debuglink().map_err(invalid_elf)? {
+        let name = Path::new(std::ffi::OsStr::from_bytes(name));
+        let dir = fs::canonicalize(path)?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        // same search order as gdb
+        out.push((dir.join(name), Some(crc)));
+        out.push((dir.join(".debug").join(name), Some(crc)));
+        out.push((
+            debug_dir
+                .join(dir.strip_prefix("/").unwrap_or(&dir))
+                .join(name),
+            Some(crc),
+        ));
+    }
+
+    Ok(out)
+}
+
+fn find_debug_info_in(path: &Path, debug_dir: &Path) -> io::Result<Option<PathBuf>> {
+    for (candidate, crc) in debug_info_candidates(path, &fs::read(path)?, debug_dir)? {
+        // debuglink pointing to the file itself
+        if !candidate.is_file() || fs::canonicalize(&candidate)? == fs::canonicalize(path)? {
+            continue;
+        }
+        match crc {
+            Some(crc) if file_crc32(&candidate)? != crc => continue,
+            _ => return Ok(Some(candidate)),
+        }
+    }
+    Ok(None)
+}
+
+/// Finds the file holding separate debug information of ELF file at `path`,
+/// looking for it by build-id under [DEBUG_DIR] and then by the name found
+/// in the .gnu_debuglink section (the CRC of the file has to match).
+#[inline]
+pub fn find_debug_info(path: &Path) -> io::Result<Option<PathBuf>> {
+    find_debug_info_in(path, Path::new(DEBUG_DIR))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[derive(Clone, Copy)]
+    struct TestSection<'a> {
+        name: &'a str,
+        sh_type: u32,
+        flags: u64,
+        link: u32,
+        data: &'a [u8],
+    }
+
+    const BASE: u64 = 0x400000;
+
+    /// Builds a minimal little endian x86_64 ELF64 executable with the
+    /// given entrypoint, loadable segments (vaddr, memsz) and sections.
+    /// Section addresses are BASE + file offset.
+    fn build_elf(entry: u64, segments: &[(u64, u64)], sections: &[TestSection<'_>]) -> Vec<u8> {
+        let mut data = vec![0u8; 64];
+
+        for (vaddr, memsz) in segments {
+            let mut p = [0u8; 56];
+            p[0..4].copy_from_slice(&PT_LOAD.to_le_bytes());
+            p[16..24].copy_from_slice(&vaddr.to_le_bytes());
+            p[24..32].copy_from_slice(&vaddr.to_le_bytes());
+            p[40..48].copy_from_slice(&memsz.to_le_bytes());
+            data.extend_from_slice(&p);
+        }
+
+        let mut shstrtab = vec![0u8];
+        let mut shdrs = vec![[0u8; 64]];
+        let mut section_header =
+            |name: u32, ty: u32, flags: u64, link: u32, off: u64, size: u64| {
+                let mut h = [0u8; 64];
+                h[0..4].copy_from_slice(&name.to_le_bytes());
+                h[4..8].copy_from_slice(&ty.to_le_bytes());
+                h[8..16].copy_from_slice(&flags.to_le_bytes());
+                h[16..24].copy_from_slice(&(BASE + off).to_le_bytes());
+                h[24..32].copy_from_slice(&off.to_le_bytes());
+                h[32..40].copy_from_slice(&size.to_le_bytes());
+                h[40..44].copy_from_slice(&link.to_le_bytes());
+                h[48..56].copy_from_slice(&1u64.to_le_bytes());
+                shdrs.push(h);
+            };
+
+        for s in sections {
+            let name = shstrtab.len() as u32;
+            shstrtab.extend_from_slice(s.name.as_bytes());
+            shstrtab.push(0);
+            section_header(
+                name,
+                s.sh_type,
+                s.flags,
+                s.link,
+                data.len() as u64,
+                s.data.len() as u64,
+            );
+            data.extend_from_slice(s.data);
+        }
+
+        let name = shstrtab.len() as u32;
+        shstrtab.extend_from_slice(b".shstrtab\0");
+        section_header(
+            name,
+            object::elf::SHT_STRTAB,
+            0,
+            0,
+            data.len() as u64,
+            shstrtab.len() as u64,
+        );
+        data.extend_from_slice(&shstrtab);
+
+        let shoff = data.len() as u64;
+        let shnum = shdrs.len() as u16;
+        shdrs.iter().for_each(|h| data.extend_from_slice(h));
+
+        // ELF header
+        data[0..4].copy_from_slice(b"\x7fELF");
+        data[4] = 2; // ELFCLASS64
+        data[5] = 1; // ELFDATA2LSB
+        data[6] = 1; // EV_CURRENT
+        data[16..18].copy_from_slice(&object::elf::ET_EXEC.to_le_bytes());
+        data[18..20].copy_from_slice(&object::elf::EM_X86_64.to_le_bytes());
+        data[20..24].copy_from_slice(&1u32.to_le_bytes());
+        data[24..32].copy_from_slice(&entry.to_le_bytes());
+        if !segments.is_empty() {
+            data[32..40].copy_from_slice(&64u64.to_le_bytes());
+        }
+        data[40..48].copy_from_slice(&shoff.to_le_bytes());
+        data[52..54].copy_from_slice(&64u16.to_le_bytes());
+        data[54..56].copy_from_slice(&56u16.to_le_bytes());
+        data[56..58].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+        data[58..60].copy_from_slice(&64u16.to_le_bytes());
+        data[60..62].copy_from_slice(&shnum.to_le_bytes());
+        data[62..64].copy_from_slice(&(shnum - 1).to_le_bytes());
+
+        data
+    }
+
+    #[test]
+    fn test_section_entropies() {
+        let exe = std::env::current_exe().unwrap();
+        let entropies = elf_section_entropies(&exe).unwrap();
+        let text = entropies.get(".text").unwrap();
+        assert!(*text > 0.0 && *text < PACKED_THRESHOLD);
+        assert!(suspicious_sections(&exe, None).unwrap().is_empty());
+
+        let random = crate::util::getrandom::<[u8; 8192]>().unwrap();
+        let code = [0x90u8; 512];
+        let elf = build_elf(
+            BASE + 64,
+            &[],
+            &[
+                TestSection {
+                    name: ".text",
+                    sh_type: object::elf::SHT_PROGBITS,
+                    flags: (object::elf::SHF_ALLOC | object::elf::SHF_EXECINSTR) as u64,
+                    link: 0,
+                    data: &code,
+                },
+                TestSection {
+                    name: ".upx0",
+                    sh_type: object::elf::SHT_PROGBITS,
+                    flags: (object::elf::SHF_ALLOC | object::elf::SHF_EXECINSTR) as u64,
+                    link: 0,
+                    data: &random,
+                },
+                TestSection {
+                    name: ".rodata",
+                    sh_type: object::elf::SHT_PROGBITS,
+                    flags: object::elf::SHF_ALLOC as u64,
+                    link: 0,
+                    data: &random,
+                },
+            ],
+        );
+
+        let entropies = section_entropies(&elf).unwrap();
+        assert_eq!(entropies.len(), 4);
+        let packed = entropies.iter().find(|(n, _, _)| n == ".upx0").unwrap().1;
+        assert!(packed > *text);
+
+        // non executable high entropy sections are not reported
+        let suspicious = suspicious_sections_of(&elf, None).unwrap();
+        assert_eq!(suspicious.len(), 1);
+        assert_eq!(suspicious[0].0, ".upx0");
+    }
+
     #[test]
     fn parse() {
         let data = include_bytes!("../../../target/bpfel-unknown-none/debug/kunai-ebpf");
         println!("{:#?}", ElfInfo::from_raw_elf(data.as_slice()).unwrap())
     }
+
+    #[test]
+    fn test_elf_anomalies() {
+        let exe = std::env::current_exe().unwrap();
+        assert!(elf_anomalies(&exe).unwrap().is_empty());
+
+        let code = [0x90u8; 64];
+        let text = || TestSection {
+            name: ".text",
+            sh_type: object::elf::SHT_PROGBITS,
+            flags: (object::elf::SHF_ALLOC | object::elf::SHF_EXECINSTR) as u64,
+            link: 0,
+            data: &code,
+        };
+        // .text is right after ELF header and the two program headers
+        let text_addr = BASE + 64 + 2 * 56;
+        let segments = [(BASE, 0x1000), (BASE + 0x1000, 0x1000)];
+
+        let good = build_elf(text_addr, &segments, &[text()]);
+        assert!(elf_anomalies_of(&good).unwrap().is_empty());
+
+        // entrypoint out of .text
+        let elf = build_elf(text_addr + 0x100, &segments, &[text()]);
+        assert_eq!(
+            elf_anomalies_of(&elf).unwrap(),
+            vec![ElfAnomaly::EntrypointOutsideExecSection(text_addr + 0x100)]
+        );
+
+        // overlapping segments
+        let elf = build_elf(
+            text_addr,
+            &[(BASE, 0x1000), (BASE + 0x800, 0x1000)],
+            &[text()],
+        );
+        assert_eq!(
+            elf_anomalies_of(&elf).unwrap(),
+            vec![ElfAnomaly::OverlappingSegments(0, 1)]
+        );
+
+        // no section headers
+        let mut elf = good.clone();
+        elf[40..48].copy_from_slice(&0u64.to_le_bytes());
+        elf[60..62].copy_from_slice(&0u16.to_le_bytes());
+        assert_eq!(
+            elf_anomalies_of(&elf).unwrap(),
+            vec![ElfAnomaly::NoSectionHeaders]
+        );
+
+        // more program headers declared than available in file
+        let mut elf = good.clone();
+        elf.truncate(64 + 2 * 56 + 64);
+        elf[56..58].copy_from_slice(&100u16.to_le_bytes());
+        let anomalies = elf_anomalies_of(&elf).unwrap();
+        assert!(anomalies.contains(&ElfAnomaly::ProgramHeaderCountMismatch {
+            declared: 100,
+            available: 3
+        }));
+        // section headers are gone too
+        assert!(anomalies.contains(&ElfAnomaly::SectionHeadersPastEof));
+
+        // section offset past end of file
+        let mut elf = good.clone();
+        let shoff = u64::from_le_bytes(elf[40..48].try_into().unwrap()) as usize;
+        // .text is the second section header, sh_offset at offset 24
+        let sh_offset = shoff + 64 + 24;
+        elf[sh_offset..sh_offset + 8].copy_from_slice(&0xffff_ffffu64.to_le_bytes());
+        assert_eq!(
+            elf_anomalies_of(&elf).unwrap(),
+            vec![ElfAnomaly::SectionPastEof {
+                index: 1,
+                offset: 0xffff_ffff
+            }]
+        );
+
+        assert!(elf_anomalies_of(b"not an elf").is_err());
+    }
+
+    /// Builds an ELF with a dynamic section made of string entries
+    fn build_dynamic_elf(entries: &[(u32, &str)]) -> Vec<u8> {
+        let mut dynstr = vec![0u8];
+        let mut dynamic = vec![];
+        for (tag, s) in entries {
+            dynamic.extend_from_slice(&(*tag as i64).to_le_bytes());
+            dynamic.extend_from_slice(&(dynstr.len() as u64).to_le_bytes());
+            dynstr.extend_from_slice(s.as_bytes());
+            dynstr.push(0);
+        }
+        // DT_NULL
+        dynamic.extend_from_slice(&[0u8; 16]);
+
+        build_elf(
+            0,
+            &[],
+            &[
+                TestSection {
+                    name: ".dynstr",
+                    sh_type: object::elf::SHT_STRTAB,
+                    flags: object::elf::SHF_ALLOC as u64,
+                    link: 0,
+                    data: &dynstr,
+                },
+                TestSection {
+                    name: ".dynamic",
+                    sh_type: object::elf::SHT_DYNAMIC,
+                    flags: object::elf::SHF_ALLOC as u64,
+                    // index of .dynstr
+                    link: 1,
+                    data: &dynamic,
+                },
+            ],
+        )
+    }
+
+    #[test]
+    fn test_elf_rpaths() {
+        let dir = tempfile::tempdir().unwrap();
+        let writable = dir.path().join("writable");
+        fs::create_dir(&writable).unwrap();
+        fs::set_permissions(&writable, fs::Permissions::from_mode(0o777)).unwrap();
+
+        let runpath = format!("/usr/lib:$ORIGIN/../lib:.:{}", writable.to_string_lossy());
+        let elf = build_dynamic_elf(&[
+            (object::elf::DT_NEEDED, "libc.so.6"),
+            (DT_RPATH, "/opt/app/lib"),
+            (DT_RUNPATH, &runpath),
+        ]);
+
+        let path = dir.path().join("bin");
+        fs::write(&path, elf).unwrap();
+
+        let rpaths = elf_rpaths(&path).unwrap();
+        assert_eq!(rpaths.rpath.as_deref(), Some("/opt/app/lib"));
+        assert_eq!(rpaths.runpath.as_deref(), Some(runpath.as_str()));
+        assert_eq!(rpaths.entries().count(), 5);
+
+        assert_eq!(
+            rpaths.insecure_entries(),
+            vec![
+                (".".into(), RPathIssue::Relative),
+                (writable.to_string_lossy().into(), RPathIssue::WorldWritable)
+            ]
+        );
+
+        // regular binary without any rpath
+        assert_eq!(
+            elf_rpaths(&std::env::current_exe().unwrap())
+                .unwrap()
+                .entries()
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_elf_needed_libraries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bin");
+
+        fs::write(
+            &path,
+            build_dynamic_elf(&[
+                (DT_NEEDED, "libz.so.1"),
+                (DT_RUNPATH, "/opt/lib"),
+                (DT_NEEDED, "libc.so.6"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(
+            elf_needed_libraries(&path).unwrap(),
+            vec!["libz.so.1".to_string(), "libc.so.6".to_string()]
+        );
+
+        // no dynamic section
+        fs::write(&path, build_elf(0, &[], &[])).unwrap();
+        assert!(elf_needed_libraries(&path).unwrap().is_empty());
+
+        // the test binary is dynamically linked against libc
+        let libs = elf_needed_libraries(&std::env::current_exe().unwrap()).unwrap();
+        assert!(libs.iter().any(|l| l.starts_with("libc.")));
+    }
+
+    fn symbol(name: u32, info: u8, shndx: u16, value: u64, size: u64) -> [u8; 24] {
+        let mut sym = [0u8; 24];
+        sym[0..4].copy_from_slice(&name.to_le_bytes());
+        sym[4] = info;
+        sym[6..8].copy_from_slice(&shndx.to_le_bytes());
+        sym[8..16].copy_from_slice(&value.to_le_bytes());
+        sym[16..24].copy_from_slice(&size.to_le_bytes());
+        sym
+    }
+
+    #[test]
+    fn test_elf_symbolize() {
+        use object::elf::{STB_GLOBAL, STT_FUNC, STT_OBJECT};
+        use std::io::Write;
+
+        let func = STB_GLOBAL << 4 | STT_FUNC;
+        let code = [0x90u8; 0x100];
+        // .text is the first section so it starts right after the header
+        let text = BASE + 64;
+
+        let strtab = b"\0main\0helper\0data\0";
+        let symtab = [
+            [0u8; 24],
+            symbol(1, func, 1, text, 0x40),
+            symbol(6, func, 1, text + 0x40, 0x20),
+            symbol(13, STB_GLOBAL << 4 | STT_OBJECT, 1, text + 0x80, 0x80),
+        ]
+        .concat();
+
+        let text_section = TestSection {
+            name: ".text",
+            sh_type: object::elf::SHT_PROGBITS,
+            flags: (object::elf::SHF_ALLOC | object::elf::SHF_EXECINSTR) as u64,
+            link: 0,
+            data: &code,
+        };
+        let stripped = build_elf(text, &[], &[text_section]);
+
+        let elf = build_elf(
+            text,
+            &[],
+            &[
+                text_section,
+                TestSection {
+                    name: ".strtab",
+                    sh_type: object::elf::SHT_STRTAB,
+                    flags: 0,
+                    link: 0,
+                    data: strtab,
+                },
+                TestSection {
+                    name: ".symtab",
+                    sh_type: object::elf::SHT_SYMTAB,
+                    flags: 0,
+                    link: 2,
+                    data: &symtab,
+                },
+            ],
+        );
+
+        assert_eq!(
+            symbolize_in(&elf, text + 0x10).unwrap(),
+            Some(("main".into(), 0x10))
+        );
+        assert_eq!(
+            symbolize_in(&elf, text + 0x40).unwrap(),
+            Some(("helper".into(), 0))
+        );
+        // data symbols and gaps between functions are not resolved
+        assert_eq!(symbolize_in(&elf, text + 0x90).unwrap(), None);
+        assert_eq!(symbolize_in(&elf, text + 0x60).unwrap(), None);
+        assert_eq!(symbolize_in(&stripped, text + 0x10).unwrap(), None);
+
+        // with a load bias as for PIE executables
+        let mut f = tempfile::NamedTempFile::new().unwrap();
+        f.write_all(&elf).unwrap();
+        let bias = 0x7f0000000000;
+        assert_eq!(
+            elf_symbolize(f.path(), bias + text + 0x44, bias).unwrap(),
+            Some(("helper".into(), 4))
+        );
+        assert_eq!(elf_symbolize(f.path(), 0x10, bias).unwrap(), None);
+    }
+
+    #[test]
+    fn test_find_debug_info() {
+        use crate::util::checksum::crc32_data;
+
+        let dir = tempfile::tempdir().unwrap();
+        let debug_dir = dir.path().join("debug");
+
+        let debug_content = b"separate debug info";
+        let crc = crc32_data(debug_content);
+
+        // filename is NUL terminated and padded to 4 bytes before the crc
+        let mut debuglink = b"prog.debug\0\0".to_vec();
+        debuglink.extend_from_slice(&crc.to_le_bytes());
+
+        let build_id = [0xab, 0xcd, 0xef, 0x01];
+        let mut note = vec![];
+        note.extend_from_slice(&4u32.to_le_bytes());
+        note.extend_from_slice(&(build_id.len() as u32).to_le_bytes());
+        note.extend_from_slice(&object::elf::NT_GNU_BUILD_ID.to_le_bytes());
+        note.extend_from_slice(b"GNU\0");
+        note.extend_from_slice(&build_id);
+
+        let section = |name, sh_type, data| TestSection {
+            name,
+            sh_type,
+            flags: 0,
+            link: 0,
+            data,
+        };
+        let elf = build_elf(
+            BASE,
+            &[],
+            &[
+                section(".gnu_debuglink", object::elf::SHT_PROGBITS, &debuglink),
+                section(".note.gnu.build-id", object::elf::SHT_NOTE, &note),
+            ],
+        );
+        let prog = dir.path().join("prog");
+        fs::write(&prog, &elf).unwrap();
+
+        let obj = object::File::parse(elf.as_slice()).unwrap();
+        assert_eq!(
+            obj.gnu_debuglink().unwrap(),
+            Some((&b"prog.debug"[..], crc))
+        );
+        let candidates = debug_info_candidates(&prog, &elf, &debug_dir).unwrap();
+        assert_eq!(candidates.len(), 4);
+        assert_eq!(
+            candidates[0],
+            (debug_dir.join(".build-id/ab/cdef01.debug"), None)
+        );
+
+        assert_eq!(find_debug_info_in(&prog, &debug_dir).unwrap(), None);
+
+        // crc mismatch
+        let debuglink_target = fs::canonicalize(dir.path()).unwrap().join("prog.debug");
+        fs::write(&debuglink_target, b"other content").unwrap();
+        assert_eq!(find_debug_info_in(&prog, &debug_dir).unwrap(), None);
+
+        fs::write(&debuglink_target, debug_content).unwrap();
+        assert_eq!(
+            find_debug_info_in(&prog, &debug_dir).unwrap(),
+            Some(debuglink_target)
+        );
+
+        // build-id takes precedence
+        let by_id = debug_dir.join(".build-id/ab/cdef01.debug");
+        fs::create_dir_all(by_id.parent().unwrap()).unwrap();
+        fs::write(&by_id, b"").unwrap();
+        assert_eq!(find_debug_info_in(&prog, &debug_dir).unwrap(), Some(by_id));
+    }
 }
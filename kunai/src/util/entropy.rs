@@ -0,0 +1,125 @@
+//! This module contains helpers to compute Shannon entropy of data,
+//! high entropy being a common trait of packed or encrypted content.
+use std::io::{self, Read};
+
+use super::read_chunks;
+
+/// Entropy (in bits per byte) above which data is likely packed or encrypted
+pub const PACKED_THRESHOLD: f64 = 7.2;
+
+/// Streaming entropy computation, data can be fed in several chunks
+#[derive(Debug, Clone)]
+pub struct Entropy {
+    counts: [u64; 256],
+    total: u64,
+}
+
+impl Default for Entropy {
+    fn default() -> Self {
+        Self {
+            counts: [0; 256],
+            total: 0,
+        }
+    }
+}
+
+impl Entropy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn update(&mut self, data: &[u8]) {
+        data.iter().for_each(|&b| self.counts[b as usize] += 1);
+        self.total += data.len() as u64;
+    }
+
+    /// Computes the entropy of all the data read from `r`
+    pub fn from_reader<R: Read>(r: R) -> io::Result<Self> {
+        let mut e = Self::new();
+        read_chunks(r, |chunk| e.update(chunk))?;
+        Ok(e)
+    }
+
+    /// Number of bytes processed so far
+    #[inline(always)]
+    pub fn len(&self) -> u64 {
+        self.total
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// Shannon entropy in bits per byte, in the range 0..=8
+    pub fn value(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+
+        let total = self.total as f64;
+        let e = self
+            .counts
+            .iter()
+            .filter(|&&c| c > 0)
+            .map(|&c| {
+                let p = c as f64 / total;
+                -p * p.log2()
+            })
+            .sum::<f64>();
+        // prevents returning -0.0
+        e.max(0.0)
+    }
+}
+
+/// Shannon entropy of `data` in bits per byte (0..=8)
+#[inline]
+pub fn shannon_entropy(data: &[u8]) -> f64 {
+    let mut e = Entropy::new();
+    e.update(data);
+    e.value()
+}
+
+/// Returns true if entropy of `data` is above `threshold`
+/// ([PACKED_THRESHOLD] if `None`).
+#[inline]
+pub fn is_likely_packed(data: &[u8], threshold: Option<f64>) -> bool {
+    shannon_entropy(data) > threshold.unwrap_or(PACKED_THRESHOLD)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::util::getrandom;
+
+    use super::*;
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(shannon_entropy(&[]), 0.0);
+        assert_eq!(shannon_entropy(&[0; 4096]), 0.0);
+        assert!(!is_likely_packed(&[0; 4096], None));
+
+        // two equiprobable symbols
+        assert_eq!(shannon_entropy(b"abababab"), 1.0);
+
+        let random = getrandom::<[u8; 16384]>().unwrap();
+        let e = shannon_entropy(&random);
+        assert!(e > 7.9 && e <= 8.0, "unexpected entropy {e}");
+        assert!(is_likely_packed(&random, None));
+        assert!(!is_likely_packed(&random, Some(8.0)));
+    }
+
+    #[test]
+    fn test_streaming() {
+        let data = (0..=255u8).cycle().take(10_000).collect::<Vec<u8>>();
+
+        let mut e = Entropy::new();
+        data.chunks(333).for_each(|c| e.update(c));
+        assert_eq!(e.len(), data.len() as u64);
+        assert_eq!(e.value(), shannon_entropy(&data));
+
+        let r = Entropy::from_reader(io::Cursor::new(&data)).unwrap();
+        assert_eq!(r.value(), e.value());
+    }
+}
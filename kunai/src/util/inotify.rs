@@ -0,0 +1,322 @@
+//! This module implements a lightweight inotify based watcher. It is
+//! meant to be used as a userland complement to eBPF probes, to monitor
+//! file drops in sensitive directories (ex: /tmp or /dev/shm).
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::{CString, OsStr},
+    io,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use libc::{
+    inotify_event, IN_CLOEXEC, IN_CLOSE_WRITE, IN_CREATE, IN_IGNORED, IN_ISDIR, IN_MODIFY,
+    IN_MOVED_FROM, IN_MOVED_TO, IN_NONBLOCK, IN_Q_OVERFLOW,
+};
+
+const EVENT_HEADER_SIZE: usize = core::mem::size_of::<inotify_event>();
+// enough to hold several events with names of NAME_MAX length
+const BUFFER_SIZE: usize = 16 * (EVENT_HEADER_SIZE + 256);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Created,
+    Modified,
+    MovedIn,
+    MovedOut,
+    /// the kernel event queue overflowed and events were lost
+    Overflow,
+    Other,
+}
+
+impl EventKind {
+    #[inline]
+    fn from_mask(mask: u32) -> Self {
+        if mask & IN_Q_OVERFLOW != 0 {
+            Self::Overflow
+        } else if mask & IN_CREATE != 0 {
+            Self::Created
+        } else if mask & (IN_MODIFY | IN_CLOSE_WRITE) != 0 {
+            Self::Modified
+        } else if mask & IN_MOVED_TO != 0 {
+            Self::MovedIn
+        } else if mask & IN_MOVED_FROM != 0 {
+            Self::MovedOut
+        } else {
+            Self::Other
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub kind: EventKind,
+    /// full path of the file the event relates to
+    pub path: PathBuf,
+    /// raw inotify mask of the event
+    pub mask: u32,
+    /// cookie linking IN_MOVED_FROM and IN_MOVED_TO events
+    pub cookie: u32,
+}
+
+impl Event {
+    #[inline(always)]
+    pub fn is_dir(&self) -> bool {
+        self.mask & IN_ISDIR != 0
+    }
+}
+
+/// Raw event parsed from the buffer returned by read(2)
+#[derive(Debug, PartialEq, Eq)]
+struct RawEvent<'a> {
+    wd: i32,
+    mask: u32,
+    cookie: u32,
+    name: &'a [u8],
+}
+
+/// Parses a buffer filled by a read on an inotify file descriptor. Events
+/// have a variable length as they are followed by a NUL padded name.
+fn parse_events(buf: &[u8]) -> io::Result<Vec<RawEvent<'_>>> {
+    let mut out = vec![];
+    let mut off = 0;
+
+    while off < buf.len() {
+        if buf.len() - off < EVENT_HEADER_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated inotify event",
+            ));
+        }
+
+        // buffer is not guaranteed to be aligned for inotify_event
+        let hdr: inotify_event =
+            unsafe { core::ptr::read_unaligned(buf[off..].as_ptr() as *const inotify_event) };
+
+        let name_start = off + EVENT_HEADER_SIZE;
+        let name_end = name_start + hdr.len as usize;
+        let name = buf.get(name_start..name_end).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "truncated inotify event name")
+        })?;
+
+        out.push(RawEvent {
+            wd: hdr.wd,
+            mask: hdr.mask,
+            cookie: hdr.cookie,
+            // name is NUL padded up to alignment boundary
+            name: name.split(|&b| b == 0).next().unwrap_or_default(),
+        });
+
+        off = name_end;
+    }
+
+    Ok(out)
+}
+
+pub struct InotifyWatcher {
+    fd: OwnedFd,
+    watches: HashMap<i32, PathBuf>,
+    pending: VecDeque<Event>,
+}
+
+impl InotifyWatcher {
+    /// Default mask to use to catch files being dropped in a directory
+    pub const DROP_MASK: u32 = IN_CREATE | IN_CLOSE_WRITE | IN_MOVED_TO;
+
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(IN_NONBLOCK | IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            watches: HashMap::new(),
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Adds a watch on `path` for events in `mask` and returns the
+    /// watch descriptor. Watching the same path again updates its mask.
+    pub fn watch<P: AsRef<Path>>(&mut self, path: P, mask: u32) -> io::Result<i32> {
+        let path = path.as_ref();
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), c_path.as_ptr(), mask) };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        self.watches.insert(wd, path.to_path_buf());
+        Ok(wd)
+    }
+
+    /// Removes the watch identified by `wd`
+    pub fn unwatch(&mut self, wd: i32) -> io::Result<()> {
+        if unsafe { libc::inotify_rm_watch(self.fd.as_raw_fd(), wd) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.watches.remove(&wd);
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub fn watched(&self) -> impl Iterator<Item = &PathBuf> {
+        self.watches.values()
+    }
+
+    /// Waits for at most `timeout` (forever if `None`) for an event to
+    /// happen. `Ok(None)` is returned if the timeout expired.
+    pub fn next_event(&mut self, timeout: Option<Duration>) -> io::Result<Option<Event>> {
+        loop {
+            if let Some(e) = self.pending.pop_front() {
+                return Ok(Some(e));
+            }
+
+            if !self.poll(timeout)? {
+                return Ok(None);
+            }
+
+            if !self.read_events()? && timeout.is_some() {
+                // spurious wake up
+                return Ok(None);
+            }
+        }
+    }
+
+    fn poll(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        let mut pfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        let timeout = timeout
+            .map(|t| t.as_millis().min(i32::MAX as u128) as i32)
+            .unwrap_or(-1);
+
+        loop {
+            let rc = unsafe { libc::poll(&mut pfd, 1, timeout) };
+            if rc < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+            return Ok(rc > 0);
+        }
+    }
+
+    /// Reads available events and queue them. Returns false if
+    /// nothing could be read.
+    fn read_events(&mut self) -> io::Result<bool> {
+        let mut buf = [0u8; BUFFER_SIZE];
+
+        let n = unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+            )
+        };
+
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            return match err.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => Ok(false),
+                _ => Err(err),
+            };
+        }
+
+        for raw in parse_events(&buf[..n as usize])? {
+            // watch got removed (explicitly or because the watched
+            // path got deleted), the descriptor may be reused later.
+            if raw.mask & IN_IGNORED != 0 {
+                self.watches.remove(&raw.wd);
+                continue;
+            }
+
+            let path = match self.watches.get(&raw.wd) {
+                Some(dir) if !raw.name.is_empty() => dir.join(OsStr::from_bytes(raw.name)),
+                Some(dir) => dir.clone(),
+                // overflow events have wd == -1
+                None => PathBuf::new(),
+            };
+
+            self.pending.push_back(Event {
+                kind: EventKind::from_mask(raw.mask),
+                path,
+                mask: raw.mask,
+                cookie: raw.cookie,
+            });
+        }
+
+        Ok(n > 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+
+    fn raw_event(wd: i32, mask: u32, name: &str, padded_len: u32) -> Vec<u8> {
+        let mut v = vec![];
+        v.extend_from_slice(&wd.to_ne_bytes());
+        v.extend_from_slice(&mask.to_ne_bytes());
+        v.extend_from_slice(&0u32.to_ne_bytes());
+        v.extend_from_slice(&padded_len.to_ne_bytes());
+        let mut n = name.as_bytes().to_vec();
+        n.resize(padded_len as usize, 0);
+        v.extend(n);
+        v
+    }
+
+    #[test]
+    fn test_parse_events() {
+        let mut buf = raw_event(1, IN_CREATE, "dropped", 16);
+        buf.extend(raw_event(1, IN_IGNORED, "", 0));
+
+        let events = parse_events(&buf).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].wd, 1);
+        assert_eq!(events[0].name, b"dropped");
+        assert_eq!(events[1].mask, IN_IGNORED);
+        assert!(events[1].name.is_empty());
+
+        // truncated name
+        assert!(parse_events(&buf[..EVENT_HEADER_SIZE + 4]).is_err());
+    }
+
+    #[test]
+    fn test_watch_create() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut w = InotifyWatcher::new().unwrap();
+        w.watch(dir.path(), InotifyWatcher::DROP_MASK).unwrap();
+
+        // nothing happened yet
+        assert!(w
+            .next_event(Some(Duration::from_millis(10)))
+            .unwrap()
+            .is_none());
+
+        let path = dir.path().join("dropped");
+        fs::write(&path, b"#!/bin/sh").unwrap();
+
+        let e = w.next_event(Some(Duration::from_secs(1))).unwrap().unwrap();
+        assert_eq!(e.kind, EventKind::Created);
+        assert_eq!(e.path, path);
+        assert!(!e.is_dir());
+
+        let e = w.next_event(Some(Duration::from_secs(1))).unwrap().unwrap();
+        assert_eq!(e.kind, EventKind::Modified);
+        assert_eq!(e.path, path);
+    }
+}
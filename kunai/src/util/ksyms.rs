@@ -0,0 +1,242 @@
+//! This module implements kernel address symbolization
+//! based on the content of /proc/kallsyms.
+use std::{
+    collections::HashMap,
+    fs, io,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
+use thiserror::Error;
+
+const KALLSYMS_PATH: &str = "/proc/kallsyms";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("kallsyms io: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid kallsyms line: {0}")]
+    Parse(String),
+    /// addresses are zeroed when reader lacks privileges (kptr_restrict)
+    #[error("kernel symbol addresses are unavailable")]
+    Unavailable,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Symbol {
+    addr: u64,
+    name: String,
+    /// index of the owning module in [KallsymsResolver::modules],
+    /// `None` for core kernel symbols
+    module: Option<usize>,
+}
+
+/// Kernel symbol table sorted by address
+#[derive(Debug, Default, Clone)]
+pub struct KallsymsResolver {
+    symbols: Vec<Symbol>,
+    modules: Vec<String>,
+}
+
+static SHARED: OnceLock<Arc<KallsymsResolver>> = OnceLock::new();
+
+impl FromStr for KallsymsResolver {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut symbols = vec![];
+        let mut modules: Vec<String> = vec![];
+        let mut module_idx = HashMap::new();
+        let mut zeroed = true;
+
+        for line in s.lines().filter(|l| !l.trim().is_empty()) {
+            let mut it = line.split_whitespace();
+            let (Some(addr), Some(ty), Some(name)) = (it.next(), it.next(), it.next()) else {
+                return Err(Error::Parse(line.into()));
+            };
+
+            let addr = u64::from_str_radix(addr, 16).map_err(|_| Error::Parse(line.into()))?;
+            if addr != 0 {
+                zeroed = false;
+            }
+
+            // absolute symbols are not located in kernel text
+            if ty.eq_ignore_ascii_case("a") {
+                continue;
+            }
+
+            // module symbols are suffixed with [module]
+            let module = it
+                .next()
+                .and_then(|m| m.strip_prefix('[')?.strip_suffix(']'))
+                .map(|m| {
+                    *module_idx.entry(m.to_string()).or_insert_with(|| {
+                        modules.push(m.into());
+                        modules.len() - 1
+                    })
+                });
+
+            symbols.push(Symbol {
+                addr,
+                name: name.into(),
+                module,
+            });
+        }
+
+        if zeroed {
+            return Err(Error::Unavailable);
+        }
+
+        symbols.sort_by_key(|s| s.addr);
+        Ok(Self { symbols, modules })
+    }
+}
+
+impl KallsymsResolver {
+    /// Loads symbols from /proc/kallsyms
+    #[inline]
+    pub fn load() -> Result<Self, Error> {
+        fs::read_to_string(KALLSYMS_PATH)?.parse()
+    }
+
+    /// Returns a resolver shared across the application. As kallsyms is
+    /// big, it is loaded only once, at first successful call.
+    pub fn shared() -> Result<Arc<Self>, Error> {
+        if let Some(r) = SHARED.get() {
+            return Ok(Arc::clone(r));
+        }
+        let r = Arc::new(Self::load()?);
+        Ok(Arc::clone(SHARED.get_or_init(|| r)))
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Names of the modules owning symbols
+    #[inline(always)]
+    pub fn modules(&self) -> impl Iterator<Item = &str> {
+        self.modules.iter().map(String::as_str)
+    }
+
+    /// Returns the names of the symbols owned by `module`
+    pub fn module_symbols<'a>(&'a self, module: &str) -> impl Iterator<Item = &'a str> {
+        let idx = self.modules.iter().position(|m| m == module);
+        self.symbols
+            .iter()
+            .filter(move |s| idx.is_some() && s.module == idx)
+            .map(|s| s.name.as_str())
+    }
+
+    /// Resolves `addr` to the closest symbol located at or below it,
+    /// returning the module owning the symbol (`None` for the core
+    /// kernel), the symbol name and the offset of `addr` within it.
+    pub fn resolve(&self, addr: u64) -> Option<(Option<String>, String, u64)> {
+        let i = self.symbols.partition_point(|s| s.addr <= addr);
+        let sym = self.symbols.get(i.checked_sub(1)?)?;
+        Some((
+            sym.module.map(|m| self.modules[m].clone()),
+            sym.name.clone(),
+            addr - sym.addr,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KALLSYMS: &str = r#"0000000000000000 A fixed_percpu_data
+ffffffff81000000 T _stext
+ffffffff81001000 T do_one_initcall
+ffffffff81000100 t early_idt_handler
+ffffffff81002000 T security_bprm_check
+"#;
+
+    #[test]
+    fn test_resolve() {
+        let r = KALLSYMS.parse::<KallsymsResolver>().unwrap();
+        assert_eq!(r.len(), 4);
+
+        assert_eq!(
+            r.resolve(0xffffffff81000000),
+            Some((None, "_stext".into(), 0))
+        );
+        assert_eq!(
+            r.resolve(0xffffffff81000142),
+            Some((None, "early_idt_handler".into(), 0x42))
+        );
+        assert_eq!(
+            r.resolve(0xffffffff81002010),
+            Some((None, "security_bprm_check".into(), 0x10))
+        );
+        // below first symbol
+        assert_eq!(r.resolve(0x1000), None);
+    }
+
+    #[test]
+    fn test_resolve_modules() {
+        let s = format!(
+            "{KALLSYMS}ffffffffc0a01000 t nf_conntrack_init\t[nf_conntrack]
+ffffffffc0a01200 T nf_ct_get_tuple\t[nf_conntrack]
+ffffffffc0b00000 t ext4_fill_super\t[ext4]
+"
+        );
+        let r = s.parse::<KallsymsResolver>().unwrap();
+        assert_eq!(
+            r.modules().collect::<Vec<_>>(),
+            vec!["nf_conntrack", "ext4"]
+        );
+        assert_eq!(
+            r.module_symbols("nf_conntrack").collect::<Vec<_>>(),
+            vec!["nf_conntrack_init", "nf_ct_get_tuple"]
+        );
+        assert_eq!(r.module_symbols("unknown").count(), 0);
+
+        assert_eq!(
+            r.resolve(0xffffffffc0a01234),
+            Some((Some("nf_conntrack".into()), "nf_ct_get_tuple".into(), 0x34))
+        );
+        assert_eq!(
+            r.resolve(0xffffffffc0b00010),
+            Some((Some("ext4".into()), "ext4_fill_super".into(), 0x10))
+        );
+        assert_eq!(
+            r.resolve(0xffffffff81002000),
+            Some((None, "security_bprm_check".into(), 0))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let zeroed = "0000000000000000 T _stext\n0000000000000000 T do_one_initcall\n";
+        assert!(matches!(
+            zeroed.parse::<KallsymsResolver>(),
+            Err(Error::Unavailable)
+        ));
+        assert!(matches!(
+            "ffffffff81000000 T".parse::<KallsymsResolver>(),
+            Err(Error::Parse(_))
+        ));
+        assert!(matches!(
+            "zzzz T _stext".parse::<KallsymsResolver>(),
+            Err(Error::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_shared() {
+        match KallsymsResolver::shared() {
+            Ok(r) => {
+                assert!(!r.is_empty());
+                assert!(Arc::ptr_eq(&r, &KallsymsResolver::shared().unwrap()));
+            }
+            Err(e) => assert!(matches!(e, Error::Unavailable), "{e}"),
+        }
+    }
+}
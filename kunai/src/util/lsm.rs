@@ -0,0 +1,428 @@
+//! This module contains helpers to get the state of Linux Security
+//! Modules (SELinux, AppArmor, lockdown, IMA) on the host.
+use std::{fs, io, path::Path};
+
+use serde::Serialize;
+
+use super::{get_xattr, is_regular_file, open_and_stat, proc::proc_path, HashAlgo};
+
+const SELINUX_ENFORCE: &str = "/sys/fs/selinux/enforce";
+const APPARMOR_ENABLED: &str = "/sys/module/apparmor/parameters/enabled";
+const LOCKDOWN: &str = "/sys/kernel/security/lockdown";
+const LSM: &str = "/sys/kernel/security/lsm";
+const IMA_XATTR: &str = "security.ima";
+
+// see security/integrity/integrity.h
+const IMA_XATTR_DIGEST: u8 = 0x04;
+const IMA_XATTR_DIGEST_NG: u8 = 0x05;
+const EVM_IMA_XATTR_DIGSIG: u8 = 0x03;
+const DIGSIG_VERSION_2: u8 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SelinuxMode {
+    Disabled,
+    Permissive,
+    Enforcing,
+}
+
+impl SelinuxMode {
+    fn from_enforce(s: &str) -> io::Result<Self> {
+        match s.trim() {
+            "0" => Ok(Self::Permissive),
+            "1" => Ok(Self::Enforcing),
+            v => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected selinux enforce value: {v}"),
+            )),
+        }
+    }
+}
+
+fn selinux_enforce_mode_from(path: &Path) -> io::Result<SelinuxMode> {
+    match fs::read_to_string(path) {
+        Ok(s) => SelinuxMode::from_enforce(&s),
+        // selinuxfs is not mounted when SELinux is disabled
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(SelinuxMode::Disabled),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the current SELinux mode
+#[inline]
+pub fn selinux_enforce_mode() -> io::Result<SelinuxMode> {
+    selinux_enforce_mode_from(Path::new(SELINUX_ENFORCE))
+}
+
+/// Parses an AppArmor label of the form `profile (mode)`
+fn parse_apparmor_label(label: &str) -> Option<(String, String)> {
+    let label = label.trim_end_matches(['\n', '\0']).trim();
+    if label.is_empty() || label == "unconfined" {
+        return None;
+    }
+
+    match label.strip_suffix(')').and_then(|l| l.rsplit_once(" (")) {
+        Some((profile, mode)) => Some((profile.into(), mode.into())),
+        // mode is not always present
+        None => Some((label.into(), String::new())),
+    }
+}
+
+#[inline]
+pub(crate) fn is_apparmor_enabled() -> bool {
+    fs::read_to_string(APPARMOR_ENABLED)
+        .map(|s| s.trim() == "Y")
+        .unwrap_or_default()
+}
+
+/// Returns the AppArmor profile confining `pid` along with its mode (ex:
+/// enforce, complain). `None` is returned if the process is unconfined or
+/// if AppArmor is not enabled.
+pub fn apparmor_profile(pid: i32) -> io::Result<Option<(String, String)>> {
+    if !is_apparmor_enabled() {
+        return Ok(None);
+    }
+
+    // with LSM stacking, attr/current may belong to another LSM
+    let label = match fs::read_to_string(proc_path(pid, "attr/apparmor/current")) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            fs::read_to_string(proc_path(pid, "attr/current"))?
+        }
+        r => r?,
+    };
+
+    Ok(parse_apparmor_label(&label))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockdownMode {
+    None,
+    /// features allowing to modify the running kernel are disabled
+    Integrity,
+    /// features allowing to extract confidential data from the
+    /// kernel (ex: some BPF helpers) are disabled as well
+    Confidentiality,
+    /// lockdown LSM is not available, or mode is not known
+    Unknown,
+}
+
+impl LockdownMode {
+    /// Parses lockdown file content where the active mode is
+    /// bracketed (ex: none [integrity] confidentiality)
+    fn from_lockdown(s: &str) -> Self {
+        let active = s
+            .split_whitespace()
+            .find_map(|m| m.strip_prefix('[').and_then(|m| m.strip_suffix(']')));
+
+        match active {
+            Some("none") => Self::None,
+            Some("integrity") => Self::Integrity,
+            Some("confidentiality") => Self::Confidentiality,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Returns the kernel lockdown mode, [LockdownMode::Unknown] is returned
+/// if lockdown is not supported or securityfs is not mounted.
+pub fn lockdown_mode() -> io::Result<LockdownMode> {
+    match fs::read_to_string(LOCKDOWN) {
+        Ok(s) => Ok(LockdownMode::from_lockdown(&s)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(LockdownMode::Unknown),
+        Err(e) => Err(e),
+    }
+}
+
+#[inline]
+fn parse_lsm_list(s: &str) -> Vec<String> {
+    s.trim()
+        .split(',')
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Returns the LSMs active on the host in the order they are invoked,
+/// `None` is returned if securityfs is not mounted.
+pub fn active_lsms() -> io::Result<Option<Vec<String>>> {
+    match fs::read_to_string(LSM) {
+        Ok(s) => Ok(Some(parse_lsm_list(&s))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Hash algorithm identifiers, see include/uapi/linux/hash_info.h
+#[inline]
+fn ima_hash_algo_name(id: u8) -> Option<&'static str> {
+    Some(match id {
+        0 => "md4",
+        1 => "md5",
+        2 => "sha1",
+        3 => "rmd160",
+        4 => "sha256",
+        5 => "sha384",
+        6 => "sha512",
+        7 => "sha224",
+        17 => "sm3-256",
+        18 => "streebog256",
+        19 => "streebog512",
+        _ => return None,
+    })
+}
+
+/// Content of the security.ima extended attribute of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImaMeasurement {
+    /// hash of the file content
+    Digest { algo: u8, digest: Vec<u8> },
+    /// signature of the file hash, only the key can tell if it is valid
+    Signature {
+        algo: u8,
+        key_id: u32,
+        signature: Vec<u8>,
+    },
+}
+
+impl ImaMeasurement {
+    fn from_xattr(x: &[u8]) -> io::Result<Self> {
+        let err =
+            |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("security.ima: {msg}"));
+
+        match x {
+            // legacy format only supporting sha1
+            [IMA_XATTR_DIGEST, digest @ ..] if digest.len() == 20 => Ok(Self::Digest {
+                algo: 2,
+                digest: digest.to_vec(),
+            }),
+            [IMA_XATTR_DIGEST_NG, algo, digest @ ..] if !digest.is_empty() => Ok(Self::Digest {
+                algo: *algo,
+                digest: digest.to_vec(),
+            }),
+            [EVM_IMA_XATTR_DIGSIG, DIGSIG_VERSION_2, algo, k0, k1, k2, k3, s0, s1, sig @ ..] => {
+                // key id and size are big endian
+                let size = u16::from_be_bytes([*s0, *s1]) as usize;
+                if sig.len() != size {
+                    return Err(err("signature size mismatch"));
+                }
+                Ok(Self::Signature {
+                    algo: *algo,
+                    key_id: u32::from_be_bytes([*k0, *k1, *k2, *k3]),
+                    signature: sig.to_vec(),
+                })
+            }
+            [] => Err(err("empty attribute")),
+            [t, ..] => Err(err(&format!("unsupported type {t:#x}"))),
+        }
+    }
+
+    /// Name of the hash algorithm, `None` if unknown
+    #[inline]
+    pub fn algo_name(&self) -> Option<&'static str> {
+        match self {
+            Self::Digest { algo, .. } | Self::Signature { algo, .. } => ima_hash_algo_name(*algo),
+        }
+    }
+
+    /// Checks the measured digest against a freshly computed hash of `path`.
+    /// `None` is returned for signatures or digest algorithms we cannot
+    /// compute, as nothing can be verified. IMA measures regular files only,
+    /// other file types are reported with an error of kind
+    /// [io::ErrorKind::InvalidInput].
+    pub fn verify(&self, path: &Path) -> io::Result<Option<bool>> {
+        let Self::Digest { algo, digest } = self else {
+            return Ok(None);
+        };
+
+        let algo = match algo {
+            1 => HashAlgo::Md5,
+            2 => HashAlgo::Sha1,
+            4 => HashAlgo::Sha256,
+            6 => HashAlgo::Sha512,
+            _ => return Ok(None),
+        };
+
+        let (f, st) = open_and_stat(path)?;
+        if !is_regular_file(&st) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("not a regular file: {}", path.display()),
+            ));
+        }
+
+        Ok(Some(algo.hash_reader(f)? == hex::encode(digest)))
+    }
+}
+
+/// Reads the IMA measurement of `path`, `None` being returned
+/// if the file does not have any security.ima attribute.
+pub fn ima_measurement(path: &Path) -> io::Result<Option<ImaMeasurement>> {
+    get_xattr(path, IMA_XATTR)?
+        .map(|x| ImaMeasurement::from_xattr(&x))
+        .transpose()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_selinux_mode() {
+        assert_eq!(
+            SelinuxMode::from_enforce("1\n").unwrap(),
+            SelinuxMode::Enforcing
+        );
+        assert_eq!(
+            SelinuxMode::from_enforce("0").unwrap(),
+            SelinuxMode::Permissive
+        );
+        assert!(SelinuxMode::from_enforce("2").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            selinux_enforce_mode_from(&dir.path().join("enforce")).unwrap(),
+            SelinuxMode::Disabled
+        );
+        fs::write(dir.path().join("enforce"), "1").unwrap();
+        assert_eq!(
+            selinux_enforce_mode_from(&dir.path().join("enforce")).unwrap(),
+            SelinuxMode::Enforcing
+        );
+
+        // must never fail on a host without SELinux
+        selinux_enforce_mode().unwrap();
+    }
+
+    #[test]
+    fn test_apparmor_label() {
+        assert_eq!(
+            parse_apparmor_label("docker-default (enforce)\n"),
+            Some(("docker-default".into(), "enforce".into()))
+        );
+        assert_eq!(
+            parse_apparmor_label("/usr/sbin/cupsd (complain)"),
+            Some(("/usr/sbin/cupsd".into(), "complain".into()))
+        );
+        assert_eq!(
+            parse_apparmor_label("snap.firefox (x) (enforce)"),
+            Some(("snap.firefox (x)".into(), "enforce".into()))
+        );
+        assert_eq!(parse_apparmor_label("unconfined\n"), None);
+        assert_eq!(parse_apparmor_label(""), None);
+
+        let own = apparmor_profile(std::process::id() as i32);
+        if !is_apparmor_enabled() {
+            assert_eq!(own.unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_active_lsms() {
+        assert_eq!(
+            parse_lsm_list("lockdown,capability,landlock,yama,apparmor,bpf\n"),
+            vec![
+                "lockdown",
+                "capability",
+                "landlock",
+                "yama",
+                "apparmor",
+                "bpf"
+            ]
+        );
+        assert!(parse_lsm_list("").is_empty());
+
+        active_lsms().unwrap();
+    }
+
+    #[test]
+    fn test_lockdown_mode() {
+        assert_eq!(
+            LockdownMode::from_lockdown("[none] integrity confidentiality\n"),
+            LockdownMode::None
+        );
+        assert_eq!(
+            LockdownMode::from_lockdown("none [integrity] confidentiality"),
+            LockdownMode::Integrity
+        );
+        assert_eq!(
+            LockdownMode::from_lockdown("none integrity [confidentiality]"),
+            LockdownMode::Confidentiality
+        );
+        assert_eq!(
+            LockdownMode::from_lockdown("none integrity confidentiality"),
+            LockdownMode::Unknown
+        );
+
+        if !Path::new(LOCKDOWN).exists() {
+            assert_eq!(lockdown_mode().unwrap(), LockdownMode::Unknown);
+        }
+    }
+
+    #[test]
+    fn test_ima_measurement() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, b"kunai").unwrap();
+
+        let sha256 = hex::decode(HashAlgo::Sha256.hash_data(b"kunai")).unwrap();
+        let mut x = vec![IMA_XATTR_DIGEST_NG, 4];
+        x.extend_from_slice(&sha256);
+
+        let m = ImaMeasurement::from_xattr(&x).unwrap();
+        assert_eq!(
+            m,
+            ImaMeasurement::Digest {
+                algo: 4,
+                digest: sha256
+            }
+        );
+        assert_eq!(m.algo_name(), Some("sha256"));
+        assert_eq!(m.verify(&path).unwrap(), Some(true));
+        fs::write(&path, b"tampered").unwrap();
+        assert_eq!(m.verify(&path).unwrap(), Some(false));
+        assert_eq!(
+            m.verify(Path::new("/dev/null")).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+
+        let mut x = vec![IMA_XATTR_DIGEST];
+        x.extend_from_slice(&[0x42; 20]);
+        assert_eq!(
+            ImaMeasurement::from_xattr(&x).unwrap().algo_name(),
+            Some("sha1")
+        );
+
+        let sig = [
+            EVM_IMA_XATTR_DIGSIG,
+            2,
+            4,
+            0xde,
+            0xad,
+            0xbe,
+            0xef,
+            0,
+            3,
+            1,
+            2,
+            3,
+        ];
+        let m = ImaMeasurement::from_xattr(&sig).unwrap();
+        assert_eq!(
+            m,
+            ImaMeasurement::Signature {
+                algo: 4,
+                key_id: 0xdeadbeef,
+                signature: vec![1, 2, 3]
+            }
+        );
+        assert_eq!(m.verify(&path).unwrap(), None);
+
+        assert!(ImaMeasurement::from_xattr(&sig[..sig.len() - 1]).is_err());
+        assert!(ImaMeasurement::from_xattr(&[]).is_err());
+        assert!(ImaMeasurement::from_xattr(&[0x42, 1]).is_err());
+
+        // a freshly written file is not measured
+        assert_eq!(ima_measurement(&path).unwrap(), None);
+    }
+}
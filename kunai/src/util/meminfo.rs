@@ -0,0 +1,137 @@
+//! This module contains helpers to read system memory information from
+//! /proc/meminfo, used to size buffers and caches according to the host.
+use std::{collections::HashMap, fs, io, str::FromStr};
+
+use super::proc::invalid_data;
+
+const MEMINFO_PATH: &str = "/proc/meminfo";
+
+/// Parsed content of /proc/meminfo, all values are in bytes
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MemInfo {
+    fields: HashMap<String, u64>,
+}
+
+impl FromStr for MemInfo {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = HashMap::new();
+
+        for line in s.lines() {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let mut value = value.split_whitespace();
+            let n = value
+                .next()
+                .ok_or_else(|| invalid_data(format!("missing value for {name}")))?
+                .parse::<u64>()
+                .map_err(invalid_data)?;
+            // some fields (ex: HugePages_Total) are not sizes
+            let n = match value.next() {
+                Some("kB") => n.saturating_mul(1024),
+                _ => n,
+            };
+            fields.insert(name.to_string(), n);
+        }
+
+        Ok(Self { fields })
+    }
+}
+
+impl MemInfo {
+    #[inline]
+    pub fn from_proc() -> io::Result<Self> {
+        fs::read_to_string(MEMINFO_PATH)?.parse()
+    }
+
+    #[inline(always)]
+    pub fn get(&self, name: &str) -> Option<u64> {
+        self.fields.get(name).copied()
+    }
+
+    #[inline]
+    pub fn total(&self) -> io::Result<u64> {
+        self.get("MemTotal")
+            .ok_or_else(|| invalid_data("MemTotal not found"))
+    }
+
+    /// Memory available for new allocations without swapping. Kernels
+    /// older than 3.14 do not expose MemAvailable so we fall back to an
+    /// estimation made of MemFree + Cached.
+    pub fn available(&self) -> io::Result<u64> {
+        if let Some(a) = self.get("MemAvailable") {
+            return Ok(a);
+        }
+
+        match (self.get("MemFree"), self.get("Cached")) {
+            (Some(free), Some(cached)) => Ok(free.saturating_add(cached)),
+            _ => Err(invalid_data("cannot compute available memory")),
+        }
+    }
+}
+
+/// Returns the memory available on the system in bytes
+#[inline]
+pub fn mem_available_bytes() -> io::Result<u64> {
+    MemInfo::from_proc()?.available()
+}
+
+/// Returns the total memory of the system in bytes
+#[inline]
+pub fn mem_total_bytes() -> io::Result<u64> {
+    MemInfo::from_proc()?.total()
+}
+
+/// Returns a size corresponding to `fraction` of the available memory,
+/// clamped to `min..=max`. This is meant to size buffers and caches.
+#[inline]
+pub fn fraction_of_available(fraction: f64, min: u64, max: u64) -> io::Result<u64> {
+    let avail = mem_available_bytes()? as f64;
+    Ok(((avail * fraction.clamp(0.0, 1.0)) as u64).clamp(min, max))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MEMINFO: &str = r#"MemTotal:       16283532 kB
+MemFree:         1794164 kB
+MemAvailable:    9712032 kB
+Buffers:          568084 kB
+Cached:          7262692 kB
+SwapCached:            0 kB
+HugePages_Total:       0
+Hugepagesize:       2048 kB"#;
+
+    #[test]
+    fn test_parse_meminfo() {
+        let mi = MEMINFO.parse::<MemInfo>().unwrap();
+        assert_eq!(mi.total().unwrap(), 16283532 * 1024);
+        assert_eq!(mi.available().unwrap(), 9712032 * 1024);
+        assert_eq!(mi.get("HugePages_Total"), Some(0));
+        assert_eq!(mi.get("Hugepagesize"), Some(2048 * 1024));
+
+        // old kernels do not have MemAvailable
+        let old = MEMINFO
+            .lines()
+            .filter(|l| !l.starts_with("MemAvailable"))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .parse::<MemInfo>()
+            .unwrap();
+        assert_eq!(old.available().unwrap(), (1794164 + 7262692) * 1024);
+
+        assert!("MemTotal: nan kB".parse::<MemInfo>().is_err());
+        assert!(MemInfo::default().available().is_err());
+    }
+
+    #[test]
+    fn test_fraction_of_available() {
+        let avail = mem_available_bytes().unwrap();
+        assert!(avail > 0 && avail <= mem_total_bytes().unwrap());
+        assert_eq!(fraction_of_available(0.5, 0, 4096).unwrap(), 4096);
+        assert_eq!(fraction_of_available(0.0, 42, u64::MAX).unwrap(), 42);
+    }
+}
@@ -0,0 +1,449 @@
+//! This module contains helpers related to mount points and
+//! filesystems (mountinfo parsing, overlayfs resolution).
+use std::{
+    ffi::CString,
+    fs, io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use super::{
+    get_xattr,
+    proc::{invalid_data, proc_path},
+};
+
+const OVERLAYFS_SUPER_MAGIC: i64 = 0x794c7630;
+const TMPFS_MAGIC: i64 = 0x01021994;
+const RAMFS_MAGIC: i64 = 0x858458f6;
+
+/// Returns the filesystem type (magic number) of the filesystem `path` is on
+pub fn statfs_type(path: &Path) -> io::Result<i64> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut st: libc::statfs = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut st) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    Ok(st.f_type as i64)
+}
+
+/// Returns true if `path` is on a filesystem backed by memory only
+/// (tmpfs, including /dev/shm, or ramfs)
+#[inline]
+pub fn is_memory_fs(path: &Path) -> io::Result<bool> {
+    Ok(matches!(statfs_type(path)?, TMPFS_MAGIC | RAMFS_MAGIC))
+}
+
+/// Decodes octal escapes (ex: \040 for space) used in mountinfo
+pub(crate) fn unescape_octal(s: &str) -> String {
+    let b = s.as_bytes();
+    let mut out = Vec::with_capacity(b.len());
+    let mut i = 0;
+
+    while i < b.len() {
+        if b[i] == b'\\' && i + 4 <= b.len() {
+            let oct = &b[i + 1..i + 4];
+            if oct.iter().all(|c| (b'0'..=b'7').contains(c)) {
+                let c = oct.iter().fold(0u32, |acc, c| acc * 8 + (c - b'0') as u32);
+                out.push(c as u8);
+                i += 4;
+                continue;
+            }
+        }
+        out.push(b[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Entry of a mountinfo file (see proc_pid_mountinfo(5))
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MountEntry {
+    pub mount_id: u32,
+    pub parent_id: u32,
+    pub major: u32,
+    pub minor: u32,
+    /// path of the mounted directory within the source filesystem,
+    /// different from / for bind mounts of a sub-directory or a file
+    pub root: PathBuf,
+    pub target: PathBuf,
+    /// per mount options
+    pub options: Vec<String>,
+    /// propagation optional fields (ex: shared:1, master:2)
+    pub propagation: Vec<String>,
+    pub fstype: String,
+    pub source: String,
+    /// per superblock options
+    pub super_options: Vec<String>,
+}
+
+impl FromStr for MountEntry {
+    type Err = io::Error;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let err = || invalid_data(format!("invalid mountinfo line: {line}"));
+        let opts = |o: &str| o.split(',').map(String::from).collect::<Vec<String>>();
+
+        // optional fields are variable in number and terminated by -
+        let (pre, post) = line.split_once(" - ").ok_or_else(err)?;
+        let mut pre = pre.split_whitespace();
+        let mut post = post.split_whitespace();
+
+        let mut next = || pre.next().ok_or_else(err);
+        let mount_id = next()?.parse().map_err(|_| err())?;
+        let parent_id = next()?.parse().map_err(|_| err())?;
+        let (major, minor) = next()?.split_once(':').ok_or_else(err)?;
+        let (major, minor) = (
+            major.parse().map_err(|_| err())?,
+            minor.parse().map_err(|_| err())?,
+        );
+        let root = PathBuf::from(unescape_octal(next()?));
+        let target = PathBuf::from(unescape_octal(next()?));
+        let options = opts(next()?);
+
+        Ok(Self {
+            mount_id,
+            parent_id,
+            major,
+            minor,
+            root,
+            target,
+            options,
+            propagation: pre.map(String::from).collect(),
+            fstype: post.next().ok_or_else(err)?.into(),
+            source: unescape_octal(post.next().ok_or_else(err)?),
+            super_options: post.next().map(opts).unwrap_or_default(),
+        })
+    }
+}
+
+/// Parses the content of a mountinfo file
+pub fn parse_mountinfo(mountinfo: &str) -> io::Result<Vec<MountEntry>> {
+    mountinfo
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(MountEntry::from_str)
+        .collect()
+}
+
+/// Reads the mounts of the mount namespace `pid` is in,
+/// from the point of view of its root directory.
+#[inline]
+pub fn read_mounts(pid: i32) -> io::Result<Vec<MountEntry>> {
+    parse_mountinfo(&fs::read_to_string(proc_path(pid, "mountinfo"))?)
+}
+
+/// Layers of an overlayfs mount
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct OverlayMount {
+    target: PathBuf,
+    upper: Option<PathBuf>,
+    lowers: Vec<PathBuf>,
+}
+
+impl OverlayMount {
+    fn from_entry(e: &MountEntry) -> Option<Self> {
+        if e.fstype != "overlay" {
+            return None;
+        }
+
+        let mut m = OverlayMount {
+            target: e.target.clone(),
+            ..Default::default()
+        };
+
+        for opt in e.super_options.iter() {
+            if let Some(lower) = opt.strip_prefix("lowerdir=") {
+                m.lowers
+                    .extend(lower.split(':').map(|l| PathBuf::from(unescape_octal(l))));
+            } else if let Some(lower) = opt.strip_prefix("lowerdir+=") {
+                m.lowers.push(PathBuf::from(unescape_octal(lower)));
+            } else if let Some(upper) = opt.strip_prefix("upperdir=") {
+                m.upper = Some(PathBuf::from(unescape_octal(upper)));
+            }
+        }
+
+        Some(m)
+    }
+}
+
+/// Finds the layer file backing `rel` (relative to the overlay root).
+/// Upper layer takes precedence over lower layers, except for metacopy
+/// files which only hold metadata, their data being in a lower layer
+/// (at the location pointed by the redirect attribute if any).
+fn resolve_in_layers(rel: &Path, upper: Option<&Path>, lowers: &[PathBuf]) -> Option<PathBuf> {
+    let mut rel = rel.to_path_buf();
+
+    if let Some(upper) = upper {
+        let candidate = upper.join(&rel);
+        if candidate.symlink_metadata().is_ok() {
+            // trusted xattrs may not be readable if we lack privileges
+            let metacopy = get_xattr(&candidate, "trusted.overlay.metacopy")
+                .ok()
+                .flatten()
+                .is_some();
+            if !metacopy {
+                return Some(candidate);
+            }
+
+            if let Ok(Some(redirect)) = get_xattr(&candidate, "trusted.overlay.redirect") {
+                let redirect = PathBuf::from(String::from_utf8_lossy(&redirect).into_owned());
+                // absolute redirects are relative to the layer root
+                rel = match redirect.strip_prefix("/") {
+                    Ok(r) => r.to_path_buf(),
+                    Err(_) => rel.with_file_name(redirect),
+                };
+            }
+        }
+    }
+
+    lowers
+        .iter()
+        .map(|l| l.join(&rel))
+        .find(|p| p.symlink_metadata().is_ok())
+}
+
+/// Resolves the real path of a file located on an overlayfs mount, by
+/// looking for it in the layers of the mount. `None` is returned if the
+/// file is not on overlayfs or if resolution is not possible.
+pub fn overlay_backing_path(path: &Path) -> io::Result<Option<PathBuf>> {
+    if statfs_type(path)? != OVERLAYFS_SUPER_MAGIC {
+        return Ok(None);
+    }
+
+    let path = fs::canonicalize(path)?;
+    let mounts = read_mounts(std::process::id() as i32)?
+        .iter()
+        .filter_map(OverlayMount::from_entry)
+        .collect::<Vec<_>>();
+
+    // the deepest mount point containing the path is the one it is on
+    let Some(m) = mounts
+        .iter()
+        .filter(|m| path.starts_with(&m.target))
+        .max_by_key(|m| m.target.components().count())
+    else {
+        return Ok(None);
+    };
+
+    let Ok(rel) = path.strip_prefix(&m.target) else {
+        return Ok(None);
+    };
+
+    Ok(resolve_in_layers(rel, m.upper.as_deref(), &m.lowers))
+}
+
+/// Paths commonly shadowed to hide files or hijack binaries
+pub const DEFAULT_SENSITIVE_PATHS: &[&str] = &[
+    "/etc/passwd",
+    "/etc/shadow",
+    "/etc/sudoers",
+    "/etc/ld.so.preload",
+    "/bin",
+    "/sbin",
+    "/usr/bin",
+    "/usr/sbin",
+    "/lib",
+    "/usr/lib",
+];
+
+impl MountEntry {
+    /// Mounts of a sub-tree (or a file) of a filesystem are bind mounts.
+    /// Bind mounts of a filesystem root cannot be distinguished from the
+    /// original mount and are not detected.
+    #[inline(always)]
+    pub fn is_bind(&self) -> bool {
+        self.root != Path::new("/")
+    }
+}
+
+fn find_suspicious_bind_mounts<P, F>(
+    mounts: &[MountEntry],
+    sensitive: &[P],
+    is_file: F,
+) -> Vec<MountEntry>
+where
+    P: AsRef<Path>,
+    F: Fn(&Path) -> bool,
+{
+    mounts
+        .iter()
+        .filter(|m| m.is_bind())
+        .filter(|m| {
+            sensitive.iter().any(|p| m.target.starts_with(p))
+                // only a file can be bind mounted over a file
+                || is_file(&m.target)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Same as [suspicious_bind_mounts] with a custom list of sensitive paths.
+/// Any bind mount at or below one of the `sensitive` paths is flagged.
+pub fn suspicious_bind_mounts_with<P: AsRef<Path>>(
+    pid: i32,
+    sensitive: &[P],
+) -> io::Result<Vec<MountEntry>> {
+    // targets must be checked from the point of view of the process
+    let root = proc_path(pid, "root");
+    let is_file = |target: &Path| {
+        target
+            .strip_prefix("/")
+            .map(|t| root.join(t).symlink_metadata().is_ok_and(|m| m.is_file()))
+            .unwrap_or_default()
+    };
+
+    Ok(find_suspicious_bind_mounts(
+        &read_mounts(pid)?,
+        sensitive,
+        is_file,
+    ))
+}
+
+/// Returns the bind mounts, in the mount namespace of `pid`, shadowing one
+/// of the [DEFAULT_SENSITIVE_PATHS] or a file. Some container runtimes bind
+/// mount files on purpose (ex: /etc/hosts) so results need triage.
+#[inline]
+pub fn suspicious_bind_mounts(pid: i32) -> io::Result<Vec<MountEntry>> {
+    suspicious_bind_mounts_with(pid, DEFAULT_SENSITIVE_PATHS)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unescape_octal() {
+        assert_eq!(unescape_octal(r"/mnt/with\040space"), "/mnt/with space");
+        assert_eq!(unescape_octal(r"/no/escape"), "/no/escape");
+        assert_eq!(unescape_octal(r"/trailing\04"), r"/trailing\04");
+    }
+
+    #[test]
+    fn test_overlay_mounts() {
+        let mi = r#"23 28 0:22 / /proc rw,relatime - proc proc rw
+812 700 0:97 / /var/lib/docker/overlay2/abc/merged rw,relatime - overlay overlay rw,lowerdir=/var/lib/docker/overlay2/l/L1:/var/lib/docker/overlay2/l/L2,upperdir=/var/lib/docker/overlay2/abc/diff,workdir=/var/lib/docker/overlay2/abc/work"#;
+
+        let mounts = parse_mountinfo(mi)
+            .unwrap()
+            .iter()
+            .filter_map(OverlayMount::from_entry)
+            .collect::<Vec<_>>();
+        assert_eq!(mounts.len(), 1);
+        assert_eq!(
+            mounts[0].target,
+            PathBuf::from("/var/lib/docker/overlay2/abc/merged")
+        );
+        assert_eq!(
+            mounts[0].upper,
+            Some(PathBuf::from("/var/lib/docker/overlay2/abc/diff"))
+        );
+        assert_eq!(
+            mounts[0].lowers,
+            vec![
+                PathBuf::from("/var/lib/docker/overlay2/l/L1"),
+                PathBuf::from("/var/lib/docker/overlay2/l/L2")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_mountinfo() {
+        let mi = r#"36 35 98:0 /mnt1 /mnt/with\040space rw,noatime master:1 shared:2 - ext3 /dev/root rw,errors=continue
+24 1 0:21 / /proc rw,nosuid,nodev,noexec,relatime - proc proc rw
+"#;
+        let mounts = parse_mountinfo(mi).unwrap();
+        assert_eq!(mounts.len(), 2);
+
+        let m = &mounts[0];
+        assert_eq!(m.mount_id, 36);
+        assert_eq!(m.parent_id, 35);
+        assert_eq!((m.major, m.minor), (98, 0));
+        assert_eq!(m.root, PathBuf::from("/mnt1"));
+        assert_eq!(m.target, PathBuf::from("/mnt/with space"));
+        assert_eq!(m.options, vec!["rw", "noatime"]);
+        assert_eq!(m.propagation, vec!["master:1", "shared:2"]);
+        assert_eq!(m.fstype, "ext3");
+        assert_eq!(m.source, "/dev/root");
+        assert_eq!(m.super_options, vec!["rw", "errors=continue"]);
+
+        assert!(mounts[1].propagation.is_empty());
+        assert_eq!(mounts[1].fstype, "proc");
+
+        assert!(parse_mountinfo("36 35 98:0 /mnt1 /mnt2 rw").is_err());
+        assert!(parse_mountinfo("x 35 98:0 /mnt1 /mnt2 rw - ext3 /dev/root rw").is_err());
+
+        let own = read_mounts(std::process::id() as i32).unwrap();
+        assert!(own.iter().any(|m| m.target == Path::new("/")));
+    }
+
+    #[test]
+    fn test_resolve_in_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        let upper = dir.path().join("upper");
+        let lower1 = dir.path().join("lower1");
+        let lower2 = dir.path().join("lower2");
+        for d in [&upper, &lower1, &lower2] {
+            fs::create_dir_all(d.join("bin")).unwrap();
+        }
+        fs::write(lower2.join("bin/sh"), b"lower").unwrap();
+        fs::write(lower1.join("bin/ls"), b"lower").unwrap();
+        fs::write(upper.join("bin/ls"), b"upper").unwrap();
+
+        let lowers = [lower1.clone(), lower2.clone()];
+        assert_eq!(
+            resolve_in_layers(Path::new("bin/ls"), Some(&upper), &lowers),
+            Some(upper.join("bin/ls"))
+        );
+        assert_eq!(
+            resolve_in_layers(Path::new("bin/sh"), Some(&upper), &lowers),
+            Some(lower2.join("bin/sh"))
+        );
+        assert_eq!(
+            resolve_in_layers(Path::new("bin/missing"), Some(&upper), &lowers),
+            None
+        );
+    }
+
+    #[test]
+    fn test_not_overlay() {
+        let f = tempfile::NamedTempFile::new().unwrap();
+        if statfs_type(f.path()).unwrap() != OVERLAYFS_SUPER_MAGIC {
+            assert_eq!(overlay_backing_path(f.path()).unwrap(), None);
+        }
+        assert!(overlay_backing_path(Path::new("/proc/self/status"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_suspicious_bind_mounts() {
+        let mi = r#"22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw
+23 22 8:1 /home/user/bin /usr/bin rw,relatime shared:1 - ext4 /dev/sda1 rw
+24 22 8:1 /tmp/sudo /usr/local/bin/sudo rw,relatime shared:1 - ext4 /dev/sda1 rw
+25 22 8:1 /srv/data /mnt/data rw,relatime shared:1 - ext4 /dev/sda1 rw
+26 22 0:5 / /usr/lib/modules rw - tmpfs tmpfs rw
+27 22 8:1 /tmp/passwd /etc/passwd rw,relatime shared:1 - ext4 /dev/sda1 rw
+"#;
+        let mounts = parse_mountinfo(mi).unwrap();
+        let is_file = |p: &Path| p == Path::new("/usr/local/bin/sudo");
+
+        let sus = find_suspicious_bind_mounts(&mounts, DEFAULT_SENSITIVE_PATHS, is_file);
+        assert_eq!(
+            sus.iter().map(|m| m.mount_id).collect::<Vec<_>>(),
+            vec![23, 24, 27]
+        );
+
+        // custom sensitive paths
+        let sus = find_suspicious_bind_mounts(&mounts, &["/mnt"], |_| false);
+        assert_eq!(sus.len(), 1);
+        assert_eq!(sus[0].target, PathBuf::from("/mnt/data"));
+
+        suspicious_bind_mounts(std::process::id() as i32).unwrap();
+    }
+}
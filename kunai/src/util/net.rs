@@ -0,0 +1,240 @@
+//! This module contains helpers to inventory the sockets of the host,
+//! from the tables exposed by the kernel under /proc/net.
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    fs, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::PathBuf,
+};
+
+use super::proc::{all_pids, exe_path, invalid_data, proc_path};
+
+/// TCP_LISTEN socket state
+const TCP_LISTEN: u8 = 0x0a;
+/// TCP_CLOSE socket state, the one of unconnected UDP sockets
+const TCP_CLOSE: u8 = 0x07;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Proto {
+    Tcp,
+    Udp,
+}
+
+impl Proto {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Tcp => "tcp",
+            Self::Udp => "udp",
+        }
+    }
+}
+
+impl Display for Proto {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Entry of a /proc/net/{tcp,tcp6,udp,udp6} table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetSocket {
+    pub proto: Proto,
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+    pub state: u8,
+    pub uid: u32,
+    pub inode: u64,
+}
+
+impl NetSocket {
+    /// Returns true for listening TCP sockets and unconnected UDP ones
+    #[inline]
+    pub fn is_listening(&self) -> bool {
+        match self.proto {
+            Proto::Tcp => self.state == TCP_LISTEN,
+            Proto::Udp => self.state == TCP_CLOSE && self.remote.port() == 0,
+        }
+    }
+}
+
+/// Parses an address formatted as hex words in host byte order
+fn parse_addr(s: &str) -> io::Result<SocketAddr> {
+    let err = || invalid_data(format!("invalid socket address: {s}"));
+    let (ip, port) = s.split_once(':').ok_or_else(err)?;
+    let port = u16::from_str_radix(port, 16).map_err(|_| err())?;
+
+    let mut bytes = Vec::with_capacity(16);
+    for i in (0..ip.len()).step_by(8) {
+        let word = ip.get(i..i + 8).ok_or_else(err)?;
+        let word = u32::from_str_radix(word, 16).map_err(|_| err())?;
+        bytes.extend_from_slice(&word.to_ne_bytes());
+    }
+
+    let ip = match bytes.len() {
+        4 => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(bytes).unwrap())),
+        16 => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(bytes).unwrap())),
+        _ => return Err(err()),
+    };
+
+    Ok(SocketAddr::new(ip, port))
+}
+
+fn parse_net_sockets(proto: Proto, table: &str) -> io::Result<Vec<NetSocket>> {
+    // first line is the header
+    table
+        .lines()
+        .skip(1)
+        .filter(|l| !l.trim().is_empty())
+        .map(|line| {
+            let err = || invalid_data(format!("invalid socket line: {line}"));
+            let f = line.split_whitespace().collect::<Vec<&str>>();
+            let field = |i: usize| f.get(i).copied().ok_or_else(err);
+
+            Ok(NetSocket {
+                proto,
+                local: parse_addr(field(1)?)?,
+                remote: parse_addr(field(2)?)?,
+                state: u8::from_str_radix(field(3)?, 16).map_err(|_| err())?,
+                uid: field(7)?.parse().map_err(|_| err())?,
+                inode: field(9)?.parse().map_err(|_| err())?,
+            })
+        })
+        .collect()
+}
+
+/// Reads the TCP and UDP sockets of the network namespace of the current
+/// process. IPv6 tables are skipped when IPv6 is disabled.
+pub fn net_sockets() -> io::Result<Vec<NetSocket>> {
+    let mut out = vec![];
+    for (file, proto) in [
+        ("tcp", Proto::Tcp),
+        ("tcp6", Proto::Tcp),
+        ("udp", Proto::Udp),
+        ("udp6", Proto::Udp),
+    ] {
+        match fs::read_to_string(format!("/proc/net/{file}")) {
+            Ok(t) => out.extend(parse_net_sockets(proto, &t)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(out)
+}
+
+/// Resolves socket inodes to the PID of a process holding them, scanning
+/// the file descriptors of all processes once. Only the `inodes` requested
+/// are kept. Processes which cannot be inspected are skipped.
+pub fn socket_inode_pids(inodes: &HashSet<u64>) -> io::Result<HashMap<u64, i32>> {
+    let mut out = HashMap::new();
+
+    for pid in all_pids()? {
+        let Ok(fds) = fs::read_dir(proc_path(pid, "fd")) else {
+            continue;
+        };
+
+        for fd in fds.flatten() {
+            let Ok(target) = fs::read_link(fd.path()) else {
+                continue;
+            };
+
+            let inode = target
+                .to_str()
+                .and_then(|t| t.strip_prefix("socket:[")?.strip_suffix(']')?.parse().ok());
+
+            if let Some(inode) = inode.filter(|i| inodes.contains(i)) {
+                // a socket shared across processes is attributed to the first one
+                out.entry(inode).or_insert(pid);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Socket waiting for connections, along with its owner
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListeningSocket {
+    pub proto: Proto,
+    pub local: SocketAddr,
+    pub uid: u32,
+    /// `None` if the owning process cannot be found (ex: insufficient
+    /// privileges or socket held by the kernel)
+    pub pid: Option<i32>,
+    pub exe: Option<PathBuf>,
+}
+
+/// Returns the listening sockets of the network namespace of the current
+/// process along with the processes owning them
+pub fn listening_sockets() -> io::Result<Vec<ListeningSocket>> {
+    let sockets = net_sockets()?
+        .into_iter()
+        .filter(NetSocket::is_listening)
+        .collect::<Vec<_>>();
+
+    let pids = socket_inode_pids(&sockets.iter().map(|s| s.inode).collect())?;
+
+    Ok(sockets
+        .into_iter()
+        .map(|s| {
+            let pid = pids.get(&s.inode).copied();
+            ListeningSocket {
+                proto: s.proto,
+                local: s.local,
+                uid: s.uid,
+                pid,
+                exe: pid.and_then(|p| exe_path(p).ok()).map(|e| e.path),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_net_sockets() {
+        let tcp = r#"  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:0CEA 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 31337 1 0000000000000000 100 0 0 10 0
+   1: 0F02000A:0016 0A01A8C0:D431 01 00000000:00000000 02:000A7A0C 00000000     0        0 4242 2 0000000000000000 20 4 29 10 -1
+"#;
+        let s = parse_net_sockets(Proto::Tcp, tcp).unwrap();
+        assert_eq!(s.len(), 2);
+        assert_eq!(s[0].local, "127.0.0.1:3306".parse().unwrap());
+        assert_eq!(s[0].uid, 1000);
+        assert_eq!(s[0].inode, 31337);
+        assert!(s[0].is_listening());
+        assert_eq!(s[1].local, "10.0.2.15:22".parse().unwrap());
+        assert_eq!(s[1].remote, "192.168.1.10:54321".parse().unwrap());
+        assert!(!s[1].is_listening());
+
+        let udp6 = r#"  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops
+  0: 00000000000000000000000001000000:0035 00000000000000000000000000000000:0000 07 00000000:00000000 00:00000000 00000000   101        0 2121 2 0000000000000000 0
+"#;
+        let s = parse_net_sockets(Proto::Udp, udp6).unwrap();
+        assert_eq!(s[0].local, "[::1]:53".parse().unwrap());
+        assert!(s[0].is_listening());
+
+        assert!(parse_net_sockets(Proto::Tcp, "header\n 0: 0100007F 00000000:0000 0A").is_err());
+    }
+
+    #[test]
+    fn test_listening_sockets() {
+        let l = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = l.local_addr().unwrap();
+
+        let sockets = listening_sockets().unwrap();
+        let s = sockets
+            .iter()
+            .find(|s| s.proto == Proto::Tcp && s.local == addr)
+            .unwrap();
+        assert_eq!(s.pid, Some(std::process::id() as i32));
+        assert_eq!(
+            s.exe,
+            exe_path(std::process::id() as i32).ok().map(|e| e.path)
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! This module contains helpers to audit file permissions, looking for
+//! security relevant mode bits (setuid, setgid, writable by others).
+use std::{
+    io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    elf::is_elf,
+    walk::{TreeWalker, DEFAULT_MAX_DEPTH},
+};
+
+/// Security relevant bits of a file mode
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FileSecurityFlags {
+    pub setuid: bool,
+    pub setgid: bool,
+    pub sticky: bool,
+    pub group_writable: bool,
+    pub world_writable: bool,
+}
+
+impl FileSecurityFlags {
+    #[inline]
+    pub fn from_mode(mode: u32) -> Self {
+        Self {
+            setuid: mode & libc::S_ISUID != 0,
+            setgid: mode & libc::S_ISGID != 0,
+            sticky: mode & libc::S_ISVTX != 0,
+            group_writable: mode & libc::S_IWGRP != 0,
+            world_writable: mode & libc::S_IWOTH != 0,
+        }
+    }
+
+    /// Returns true if the file runs with privileges of its owner or group
+    #[inline(always)]
+    pub fn is_privileged(&self) -> bool {
+        self.setuid || self.setgid
+    }
+
+    /// Returns true if the file can be modified by users other than its owner
+    #[inline(always)]
+    pub fn is_writable_by_others(&self) -> bool {
+        self.group_writable || self.world_writable
+    }
+}
+
+/// Returns the security flags of file at `path` (symlinks are not followed)
+#[inline]
+pub fn file_security_flags(path: &Path) -> io::Result<FileSecurityFlags> {
+    Ok(FileSecurityFlags::from_mode(
+        path.symlink_metadata()?.mode(),
+    ))
+}
+
+/// Walks `root` (up to [DEFAULT_MAX_DEPTH]) looking for setuid/setgid ELF
+/// files which are group or world writable, anyone able to modify such a
+/// file can escalate privileges. Only regular files are considered and
+/// entries which cannot be inspected are skipped.
+pub fn writable_suid_binaries(root: &Path) -> io::Result<Vec<PathBuf>> {
+    // we want to report an error if the root itself is not accessible
+    root.symlink_metadata()?;
+
+    Ok(TreeWalker::new([root.to_path_buf()], DEFAULT_MAX_DEPTH)
+        .filter_map(|(p, m)| m.ok().filter(|m| m.is_file()).map(|m| (p, m)))
+        .filter(|(_, m)| {
+            let flags = FileSecurityFlags::from_mode(m.mode());
+            flags.is_privileged() && flags.is_writable_by_others()
+        })
+        .filter(|(p, _)| is_elf(p).unwrap_or_default())
+        .map(|(p, _)| p)
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs::{self, Permissions},
+        os::unix::fs::PermissionsExt,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_file_security_flags() {
+        let f = FileSecurityFlags::from_mode(0o104755);
+        assert!(f.setuid && f.is_privileged());
+        assert!(!f.is_writable_by_others());
+
+        let f = FileSecurityFlags::from_mode(0o1777);
+        assert!(f.sticky && f.world_writable && f.group_writable);
+        assert!(!f.is_privileged());
+    }
+
+    #[test]
+    fn test_writable_suid_binaries() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("bin")).unwrap();
+
+        let elf = [&b"\x7fELF"[..], &[0; 60]].concat();
+        let files = [
+            ("bin/vuln", &elf[..], 0o4777),
+            ("bin/sgid", &elf[..], 0o2775),
+            ("bin/safe", &elf[..], 0o4755),
+            ("bin/script", &b"#!/bin/sh"[..], 0o4777),
+        ];
+        for (name, content, mode) in files {
+            let p = root.join(name);
+            fs::write(&p, content).unwrap();
+            fs::set_permissions(&p, Permissions::from_mode(mode)).unwrap();
+        }
+
+        assert_eq!(
+            file_security_flags(&root.join("bin/vuln")).unwrap(),
+            FileSecurityFlags::from_mode(0o4777)
+        );
+
+        let mut found = writable_suid_binaries(root).unwrap();
+        found.sort();
+        assert_eq!(found, vec![root.join("bin/sgid"), root.join("bin/vuln")]);
+
+        assert!(writable_suid_binaries(&root.join("missing")).is_err());
+    }
+}
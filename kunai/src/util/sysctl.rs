@@ -0,0 +1,344 @@
+//! This module contains helpers to read kernel parameters exposed
+//! under /proc/sys and relevant to the trust we can put in the host.
+use core::fmt;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::proc::invalid_data;
+
+const PROC_SYS: &str = "/proc/sys";
+
+/// Translates a dotted sysctl name (ex: kernel.kptr_restrict) into its path
+/// under `root`. Names using / as separator are accepted as well. Components
+/// containing dots (ex: some interface names) must use the / separator.
+fn sysctl_path(root: &Path, name: &str) -> PathBuf {
+    let rel = if name.contains('/') {
+        name.trim_start_matches('/').to_string()
+    } else {
+        name.replace('.', "/")
+    };
+    root.join(rel)
+}
+
+#[inline]
+fn read_sysctl_in(root: &Path, name: &str) -> io::Result<String> {
+    Ok(fs::read_to_string(sysctl_path(root, name))?.trim().into())
+}
+
+/// Reads the value of sysctl `name` (ex: kernel.kptr_restrict),
+/// trailing whitespaces are stripped from the value.
+#[inline]
+pub fn read_sysctl(name: &str) -> io::Result<String> {
+    read_sysctl_in(Path::new(PROC_SYS), name)
+}
+
+/// Change of a sysctl value, values are `None` when the
+/// sysctl cannot be read (ex: absent or not allowed)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SysctlChange {
+    pub name: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// Watches a set of sysctls for runtime changes, by comparing
+/// their values against a baseline taken at creation.
+#[derive(Debug, Clone)]
+pub struct SysctlMonitor {
+    root: PathBuf,
+    baseline: BTreeMap<String, Option<String>>,
+}
+
+impl SysctlMonitor {
+    /// Creates a monitor recording the current values of `names`
+    #[inline]
+    pub fn new<S: AsRef<str>>(names: &[S]) -> Self {
+        Self::with_root(PROC_SYS, names)
+    }
+
+    fn with_root<P: AsRef<Path>, S: AsRef<str>>(root: P, names: &[S]) -> Self {
+        let root = root.as_ref().to_path_buf();
+        let baseline = names
+            .iter()
+            .map(|n| {
+                let n = n.as_ref();
+                (n.to_string(), read_sysctl_in(&root, n).ok())
+            })
+            .collect();
+        Self { root, baseline }
+    }
+
+    /// Reads monitored sysctls and returns the ones which changed since the
+    /// previous poll. The baseline is updated so that a change is reported
+    /// only once.
+    pub fn poll(&mut self) -> Vec<SysctlChange> {
+        let mut changes = vec![];
+        for (name, old) in self.baseline.iter_mut() {
+            let new = read_sysctl_in(&self.root, name).ok();
+            if new != *old {
+                changes.push(SysctlChange {
+                    name: name.clone(),
+                    old: old.clone(),
+                    new: new.clone(),
+                });
+                *old = new;
+            }
+        }
+        changes
+    }
+
+    /// Returns the baseline value of sysctl `name`
+    #[inline]
+    pub fn baseline(&self, name: &str) -> Option<&str> {
+        self.baseline.get(name)?.as_deref()
+    }
+}
+
+macro_rules! taint_flags {
+    ($($variant:ident = $bit:literal => ($letter:literal, $desc:literal)),* $(,)?) => {
+        /// Kernel taint flags (see Documentation/admin-guide/tainted-kernels.rst),
+        /// discriminants are the bit numbers in the taint mask
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        #[repr(u32)]
+        pub enum TaintFlag {
+            $($variant = $bit),*
+        }
+
+        impl TaintFlag {
+            pub const ALL: &'static [TaintFlag] = &[$(Self::$variant),*];
+
+            #[inline]
+            pub fn from_bit(bit: u32) -> Option<Self> {
+                match bit {
+                    $($bit => Some(Self::$variant),)*
+                    _ => None,
+                }
+            }
+
+            /// Letter used for the flag in oops reports
+            #[inline]
+            pub const fn letter(&self) -> char {
+                match self {
+                    $(Self::$variant => $letter),*
+                }
+            }
+
+            #[inline]
+            pub const fn as_str(&self) -> &'static str {
+                match self {
+                    $(Self::$variant => $desc),*
+                }
+            }
+        }
+    };
+}
+
+taint_flags! {
+    ProprietaryModule = 0 => ('P', "proprietary module loaded"),
+    ForcedModule = 1 => ('F', "module force loaded"),
+    CpuOutOfSpec = 2 => ('S', "kernel running on an out of specification system"),
+    ForcedRmmod = 3 => ('R', "module force unloaded"),
+    MachineCheck = 4 => ('M', "machine check exception occurred"),
+    BadPage = 5 => ('B', "bad page referenced or unexpected page flags"),
+    User = 6 => ('U', "taint requested by userspace"),
+    Die = 7 => ('D', "kernel died recently (oops or bug)"),
+    OverriddenAcpiTable = 8 => ('A', "ACPI table overridden by user"),
+    Warn = 9 => ('W', "kernel issued a warning"),
+    Crap = 10 => ('C', "staging driver loaded"),
+    FirmwareWorkaround = 11 => ('I', "workaround for a platform firmware bug applied"),
+    OotModule = 12 => ('O', "out of tree module loaded"),
+    UnsignedModule = 13 => ('E', "unsigned module loaded"),
+    SoftLockup = 14 => ('L', "soft lockup occurred"),
+    Livepatch = 15 => ('K', "kernel live patched"),
+    Aux = 16 => ('X', "auxiliary taint, defined for distributions"),
+    RandStruct = 17 => ('T', "kernel built with the struct randomization plugin"),
+    Test = 18 => ('N', "in-kernel test run"),
+}
+
+impl fmt::Display for TaintFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Kernel taint state
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct KernelTaint {
+    /// raw taint mask
+    pub raw: u64,
+    /// flags decoded from the mask, bits unknown to us are ignored
+    pub flags: Vec<TaintFlag>,
+}
+
+impl KernelTaint {
+    pub fn from_mask(raw: u64) -> Self {
+        Self {
+            raw,
+            flags: (0..64)
+                .filter(|bit| raw & (1 << bit) != 0)
+                .filter_map(TaintFlag::from_bit)
+                .collect(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_tainted(&self) -> bool {
+        self.raw != 0
+    }
+
+    #[inline]
+    pub fn has(&self, flag: TaintFlag) -> bool {
+        self.raw & (1 << flag as u32) != 0
+    }
+}
+
+/// Reads the taint state of the running kernel
+pub fn kernel_taint() -> io::Result<KernelTaint> {
+    Ok(KernelTaint::from_mask(
+        read_sysctl("kernel.tainted")?
+            .parse()
+            .map_err(invalid_data)?,
+    ))
+}
+
+/// Highest Yama ptrace scope (see Documentation/admin-guide/LSM/Yama.rst)
+const PTRACE_SCOPE_MAX: u8 = 3;
+
+fn parse_ptrace_scope(s: &str) -> io::Result<u8> {
+    match s.trim().parse::<u8>() {
+        Ok(v) if v <= PTRACE_SCOPE_MAX => Ok(v),
+        _ => Err(invalid_data(format!("unexpected ptrace_scope value: {s}"))),
+    }
+}
+
+fn ptrace_scope_in(root: &Path) -> io::Result<Option<u8>> {
+    match read_sysctl_in(root, "kernel.yama.ptrace_scope") {
+        Ok(s) => parse_ptrace_scope(&s).map(Some),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the Yama ptrace scope, `None` if Yama is not enabled. Scope 0 lets
+/// any process ptrace the processes of the same user, 1 restricts it to
+/// descendants, 2 to CAP_SYS_PTRACE holders and 3 disables ptrace.
+#[inline]
+pub fn ptrace_scope() -> io::Result<Option<u8>> {
+    ptrace_scope_in(Path::new(PROC_SYS))
+}
+
+/// Returns true if Yama restricts ptrace further than the classic
+/// same user check, false if permissive or Yama is absent.
+#[inline]
+pub fn ptrace_restricted() -> io::Result<bool> {
+    Ok(ptrace_scope()?.is_some_and(|s| s > 0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_kernel_taint() {
+        // out of tree and unsigned module
+        let t = KernelTaint::from_mask(0x3000);
+        assert_eq!(
+            t.flags,
+            vec![TaintFlag::OotModule, TaintFlag::UnsignedModule]
+        );
+        assert!(t.is_tainted());
+        assert!(t.has(TaintFlag::OotModule));
+        assert!(!t.has(TaintFlag::ProprietaryModule));
+        assert_eq!(TaintFlag::UnsignedModule.letter(), 'E');
+
+        // unknown bits are kept in raw value only
+        let t = KernelTaint::from_mask(1 | 1 << 63);
+        assert_eq!(t.flags, vec![TaintFlag::ProprietaryModule]);
+        assert_eq!(t.raw, 1 | 1 << 63);
+
+        assert!(!KernelTaint::from_mask(0).is_tainted());
+        assert!(TaintFlag::ALL
+            .iter()
+            .all(|f| TaintFlag::from_bit(*f as u32) == Some(*f)));
+
+        kernel_taint().unwrap();
+    }
+
+    #[test]
+    fn test_sysctl_path() {
+        let root = Path::new("/proc/sys");
+        assert_eq!(
+            sysctl_path(root, "kernel.yama.ptrace_scope"),
+            PathBuf::from("/proc/sys/kernel/yama/ptrace_scope")
+        );
+        assert_eq!(
+            sysctl_path(root, "net/ipv4/conf/eth0.100/forwarding"),
+            PathBuf::from("/proc/sys/net/ipv4/conf/eth0.100/forwarding")
+        );
+
+        assert!(!read_sysctl("kernel.ostype").unwrap().ends_with('\n'));
+    }
+
+    #[test]
+    fn test_sysctl_monitor() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("kernel")).unwrap();
+        let kptr = dir.path().join("kernel/kptr_restrict");
+        fs::write(&kptr, "2\n").unwrap();
+
+        let mut m = SysctlMonitor::with_root(
+            dir.path(),
+            &["kernel.kptr_restrict", "kernel.dmesg_restrict"],
+        );
+        assert_eq!(m.baseline("kernel.kptr_restrict"), Some("2"));
+        assert_eq!(m.baseline("kernel.dmesg_restrict"), None);
+        assert!(m.poll().is_empty());
+
+        fs::write(&kptr, "0\n").unwrap();
+        fs::write(dir.path().join("kernel/dmesg_restrict"), "0").unwrap();
+        assert_eq!(
+            m.poll(),
+            vec![
+                SysctlChange {
+                    name: "kernel.dmesg_restrict".into(),
+                    old: None,
+                    new: Some("0".into()),
+                },
+                SysctlChange {
+                    name: "kernel.kptr_restrict".into(),
+                    old: Some("2".into()),
+                    new: Some("0".into()),
+                },
+            ]
+        );
+        // changes are reported only once
+        assert!(m.poll().is_empty());
+
+        SysctlMonitor::new(&["kernel.kptr_restrict"]).poll();
+    }
+
+    #[test]
+    fn test_ptrace_scope() {
+        for v in 0..=3 {
+            assert_eq!(parse_ptrace_scope(&format!("{v}\n")).unwrap(), v);
+        }
+        assert!(parse_ptrace_scope("4").is_err());
+        assert!(parse_ptrace_scope("").is_err());
+
+        // Yama absent
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(ptrace_scope_in(dir.path()).unwrap(), None);
+
+        fs::create_dir_all(dir.path().join("kernel/yama")).unwrap();
+        fs::write(dir.path().join("kernel/yama/ptrace_scope"), "1\n").unwrap();
+        assert_eq!(ptrace_scope_in(dir.path()).unwrap(), Some(1));
+
+        assert_eq!(
+            ptrace_restricted().unwrap(),
+            ptrace_scope().unwrap().unwrap_or_default() > 0
+        );
+    }
+}
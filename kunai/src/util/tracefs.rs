@@ -0,0 +1,88 @@
+//! This module contains helpers to inspect the kernel tracing filesystem,
+//! to list probes and tracepoints configured on the system.
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Locations where the tracing fs can be found, tracefs mount point being
+/// preferred over the legacy location within debugfs.
+const TRACEFS_LOCATIONS: &[&str] = &["/sys/kernel/tracing", "/sys/kernel/debug/tracing"];
+
+/// Returns the path where the tracing fs is mounted. An error of kind
+/// [io::ErrorKind::Unsupported] is returned if it is not mounted.
+pub fn tracefs_path() -> io::Result<PathBuf> {
+    TRACEFS_LOCATIONS
+        .iter()
+        .map(Path::new)
+        // an unmounted tracefs location is an empty directory
+        .find(|p| p.join("trace").exists())
+        .map(Path::to_path_buf)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "tracing fs is not mounted"))
+}
+
+/// Parses a tracefs file listing one definition per line
+fn parse_lines(s: &str) -> Vec<String> {
+    s.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+#[inline]
+fn read_tracefs_list(file: &str) -> io::Result<Vec<String>> {
+    Ok(parse_lines(&fs::read_to_string(
+        tracefs_path()?.join(file),
+    )?))
+}
+
+/// Lists kprobes and kretprobes defined through the tracing fs (ex:
+/// p:kprobes/my_probe do_sys_open). Probes created through perf_event_open
+/// (like most BPF kprobes) do not show up there.
+#[inline]
+pub fn list_kprobe_events() -> io::Result<Vec<String>> {
+    read_tracefs_list("kprobe_events")
+}
+
+/// Lists the tracepoints enabled for tracing, formatted as group:event
+#[inline]
+pub fn list_enabled_tracepoints() -> io::Result<Vec<String>> {
+    read_tracefs_list("set_event")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_kprobe_events() {
+        let s = r#"p:kprobes/p_do_sys_open_0 do_sys_open dfd=%di:s32
+r4:kprobes/r_do_sys_open_0 do_sys_open ret=$retval
+
+p:kprobes/myprobe security_bprm_check+0x10
+"#;
+        let kp = parse_lines(s);
+        assert_eq!(kp.len(), 3);
+        assert_eq!(kp[0], "p:kprobes/p_do_sys_open_0 do_sys_open dfd=%di:s32");
+        assert_eq!(kp[2], "p:kprobes/myprobe security_bprm_check+0x10");
+
+        assert_eq!(
+            parse_lines("sched:sched_process_exec\nsyscalls:sys_enter_execve\n"),
+            vec!["sched:sched_process_exec", "syscalls:sys_enter_execve"]
+        );
+    }
+
+    #[test]
+    fn test_list_kprobe_events() {
+        match list_kprobe_events() {
+            Ok(_) => assert!(tracefs_path().is_ok()),
+            Err(e) => assert!(matches!(
+                e.kind(),
+                io::ErrorKind::Unsupported
+                    | io::ErrorKind::PermissionDenied
+                    | io::ErrorKind::NotFound
+            )),
+        }
+    }
+}
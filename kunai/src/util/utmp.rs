@@ -0,0 +1,278 @@
+//! This module contains the necessary structures to parse utmp
+//! formatted files (utmp, wtmp and btmp) holding login records.
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use chrono::{DateTime, Utc};
+use libc::utmpx;
+
+pub const UTMP_PATH: &str = "/var/run/utmp";
+pub const WTMP_PATH: &str = "/var/log/wtmp";
+pub const BTMP_PATH: &str = "/var/log/btmp";
+
+/// Size of a record, layout depends on the target libc
+pub const RECORD_SIZE: usize = core::mem::size_of::<utmpx>();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UtmpType {
+    Empty,
+    RunLevel,
+    BootTime,
+    NewTime,
+    OldTime,
+    InitProcess,
+    LoginProcess,
+    UserProcess,
+    DeadProcess,
+    Accounting,
+    Unknown(i16),
+}
+
+impl From<i16> for UtmpType {
+    fn from(value: i16) -> Self {
+        match value {
+            0 => Self::Empty,
+            1 => Self::RunLevel,
+            2 => Self::BootTime,
+            3 => Self::NewTime,
+            4 => Self::OldTime,
+            5 => Self::InitProcess,
+            6 => Self::LoginProcess,
+            7 => Self::UserProcess,
+            8 => Self::DeadProcess,
+            9 => Self::Accounting,
+            v => Self::Unknown(v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UtmpEntry {
+    pub ty: UtmpType,
+    pub pid: i32,
+    /// device name of the tty (ex: pts/0)
+    pub line: String,
+    pub user: String,
+    /// remote host for remote logins
+    pub host: String,
+    pub tv_sec: i64,
+    pub tv_usec: i64,
+}
+
+/// Decodes a fixed size NUL padded char field. Fields
+/// are not NUL terminated when they use their full size.
+#[inline]
+fn decode_field(field: &[libc::c_char]) -> String {
+    let bytes = field
+        .iter()
+        .map(|&c| c as u8)
+        .take_while(|&b| b != 0)
+        .collect::<Vec<u8>>();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+impl UtmpEntry {
+    /// Parses a single record, `None` is returned if `data` is
+    /// not exactly [RECORD_SIZE] bytes long.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() != RECORD_SIZE {
+            return None;
+        }
+
+        // data is not guaranteed to be aligned
+        let rec: utmpx = unsafe { core::ptr::read_unaligned(data.as_ptr() as *const utmpx) };
+
+        Some(Self {
+            ty: rec.ut_type.into(),
+            pid: rec.ut_pid,
+            line: decode_field(&rec.ut_line),
+            user: decode_field(&rec.ut_user),
+            host: decode_field(&rec.ut_host),
+            #[allow(clippy::unnecessary_cast)]
+            tv_sec: rec.ut_tv.tv_sec as i64,
+            #[allow(clippy::unnecessary_cast)]
+            tv_usec: rec.ut_tv.tv_usec as i64,
+        })
+    }
+
+    #[inline]
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp(self.tv_sec, (self.tv_usec as u32).saturating_mul(1000))
+    }
+}
+
+/// Parses all the complete records found in `data`
+pub fn parse_records(data: &[u8]) -> Vec<UtmpEntry> {
+    data.chunks_exact(RECORD_SIZE)
+        .filter_map(UtmpEntry::from_bytes)
+        .collect()
+}
+
+/// Streaming reader of utmp formatted data yielding records one by
+/// one, so that large files (wtmp, btmp) do not have to be loaded in
+/// memory. A trailing partial record ends the iteration.
+pub struct UtmpReader<R: Read> {
+    r: R,
+    done: bool,
+}
+
+impl<R: Read> UtmpReader<R> {
+    pub fn new(r: R) -> Self {
+        Self { r, done: false }
+    }
+
+    /// Fills `buf` and returns the number of bytes read, which is
+    /// smaller than the buffer size only at end of input.
+    fn read_full(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+        while n < buf.len() {
+            match self.r.read(&mut buf[n..]) {
+                Ok(0) => break,
+                Ok(m) => n += m,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl<R: Read> Iterator for UtmpReader<R> {
+    type Item = io::Result<UtmpEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut buf = [0u8; RECORD_SIZE];
+        match self.read_full(&mut buf) {
+            Ok(RECORD_SIZE) => UtmpEntry::from_bytes(&buf).map(Ok),
+            Ok(_) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Opens a utmp formatted file for streaming
+pub fn stream_records<P: AsRef<Path>>(path: P) -> io::Result<UtmpReader<BufReader<File>>> {
+    Ok(UtmpReader::new(BufReader::new(File::open(path)?)))
+}
+
+#[inline(always)]
+fn read_records<P: AsRef<Path>>(path: P) -> io::Result<Vec<UtmpEntry>> {
+    stream_records(path)?.collect()
+}
+
+/// Reads the current login records from [UTMP_PATH]
+pub fn read_utmp() -> io::Result<Vec<UtmpEntry>> {
+    read_records(UTMP_PATH)
+}
+
+/// Reads login history from [WTMP_PATH]. As the file may be
+/// large, consider using [stream_wtmp] instead.
+pub fn read_wtmp() -> io::Result<Vec<UtmpEntry>> {
+    read_records(WTMP_PATH)
+}
+
+/// Reads failed login history from [BTMP_PATH]. As the file may be
+/// large, consider using [stream_btmp] instead.
+pub fn read_btmp() -> io::Result<Vec<UtmpEntry>> {
+    read_records(BTMP_PATH)
+}
+
+#[inline]
+pub fn stream_wtmp() -> io::Result<UtmpReader<BufReader<File>>> {
+    stream_records(WTMP_PATH)
+}
+
+#[inline]
+pub fn stream_btmp() -> io::Result<UtmpReader<BufReader<File>>> {
+    stream_records(BTMP_PATH)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::*;
+
+    fn record(ty: i16, pid: i32, line: &str, user: &str, host: &str) -> Vec<u8> {
+        let mut rec: utmpx = unsafe { core::mem::zeroed() };
+        rec.ut_type = ty;
+        rec.ut_pid = pid;
+        let fill = |dst: &mut [libc::c_char], src: &str| {
+            dst.iter_mut()
+                .zip(src.bytes())
+                .for_each(|(d, s)| *d = s as libc::c_char)
+        };
+        fill(&mut rec.ut_line, line);
+        fill(&mut rec.ut_user, user);
+        fill(&mut rec.ut_host, host);
+        rec.ut_tv.tv_sec = 1_700_000_000;
+        rec.ut_tv.tv_usec = 42;
+
+        unsafe { core::slice::from_raw_parts(&rec as *const utmpx as *const u8, RECORD_SIZE) }
+            .to_vec()
+    }
+
+    #[test]
+    fn test_parse_record() {
+        let mut data = record(7, 4242, "pts/0", "alice", "192.168.1.42");
+        // a user name using the whole field is not NUL terminated
+        data.extend(record(2, 0, "~", &"a".repeat(64), ""));
+        // partial records are ignored
+        data.extend_from_slice(&[0; 12]);
+
+        let entries = parse_records(&data);
+        assert_eq!(entries.len(), 2);
+
+        let e = &entries[0];
+        assert_eq!(e.ty, UtmpType::UserProcess);
+        assert_eq!(e.pid, 4242);
+        assert_eq!(e.line, "pts/0");
+        assert_eq!(e.user, "alice");
+        assert_eq!(e.host, "192.168.1.42");
+        assert_eq!(e.time().unwrap().timestamp(), 1_700_000_000);
+
+        assert_eq!(entries[1].ty, UtmpType::BootTime);
+        assert_eq!(entries[1].user, "a".repeat(32));
+        assert!(UtmpEntry::from_bytes(&data[..RECORD_SIZE - 1]).is_none());
+
+        let streamed = UtmpReader::new(io::Cursor::new(&data))
+            .collect::<io::Result<Vec<UtmpEntry>>>()
+            .unwrap();
+        assert_eq!(streamed, entries);
+    }
+
+    #[test]
+    fn test_stream_bounded() {
+        let mut f = tempfile::NamedTempFile::new().unwrap();
+        for pid in 0..100 {
+            f.write_all(&record(6, pid, "ssh:notty", "admin", "10.0.0.1"))
+                .unwrap();
+        }
+        f.write_all(&[0; 8]).unwrap();
+        f.flush().unwrap();
+
+        let first = stream_records(f.path())
+            .unwrap()
+            .take(3)
+            .collect::<io::Result<Vec<UtmpEntry>>>()
+            .unwrap();
+        assert_eq!(first.len(), 3);
+        assert_eq!(first[2].pid, 2);
+        assert_eq!(first[2].ty, UtmpType::LoginProcess);
+
+        assert_eq!(read_records(f.path()).unwrap().len(), 100);
+    }
+}
@@ -0,0 +1,250 @@
+//! This module implements a bounded file-system walker used for
+//! baseline scans (bulk hashing, file permission audits).
+use std::{
+    fs::{self, Metadata},
+    io,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{FileTypeExt, MetadataExt},
+    },
+    path::{Path, PathBuf},
+};
+
+use super::{is_regular_file, open_and_stat, HashAlgo};
+
+/// Default maximum depth of a walk
+pub const DEFAULT_MAX_DEPTH: usize = 32;
+
+/// Depth first walker yielding all the non directory entries found under
+/// a set of roots, along with their metadata (symlinks are not followed).
+/// Symlinked directories are not descended into, which prevents cycles.
+/// Errors are yielded along with the path they relate to, so that an
+/// unreadable directory does not abort the whole walk. A directory too
+/// deep to be descended into is yielded as an error, so that the caller
+/// knows the walk is incomplete.
+pub struct TreeWalker {
+    stack: Vec<(PathBuf, usize)>,
+    max_depth: usize,
+}
+
+impl TreeWalker {
+    /// Creates a walker descending at most `max_depth` directories
+    /// below the roots.
+    pub fn new<I: IntoIterator<Item = PathBuf>>(roots: I, max_depth: usize) -> Self {
+        let mut stack = roots.into_iter().map(|r| (r, 0)).collect::<Vec<_>>();
+        // we pop from the back so reverse to walk roots in order
+        stack.reverse();
+        Self { stack, max_depth }
+    }
+}
+
+impl Iterator for TreeWalker {
+    type Item = (PathBuf, io::Result<Metadata>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((path, depth)) = self.stack.pop() {
+            let meta = match path.symlink_metadata() {
+                Ok(m) => m,
+                Err(e) => return Some((path, Err(e))),
+            };
+
+            if !meta.is_dir() {
+                return Some((path, Ok(meta)));
+            }
+
+            if depth > self.max_depth {
+                let e = io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("maximum walk depth {} exceeded", self.max_depth),
+                );
+                return Some((path, Err(e)));
+            }
+
+            let rd = match fs::read_dir(&path) {
+                Ok(rd) => rd,
+                Err(e) => return Some((path, Err(e))),
+            };
+
+            let mut entries = rd.flatten().map(|e| e.path()).collect::<Vec<_>>();
+            // sorted in reverse so that entries are yielded in order
+            entries.sort_unstable_by(|a, b| b.cmp(a));
+            self.stack
+                .extend(entries.into_iter().map(|e| (e, depth + 1)));
+        }
+
+        None
+    }
+}
+
+/// Computes a digest summarizing the whole tree under `root`. Entries are
+/// walked in sorted order and, for each of them, the digest is chained with
+/// the relative path, the mode (file type included) and the content digest
+/// of the entry. Only regular files are read, symlinks are accounted by
+/// their target and devices by their device number, other special files
+/// (ex: FIFOs, sockets) by their path and mode only so that they cannot
+/// block the walk. Any change of name, permission or content under root thus
+/// changes the result. Directories are not accounted on their own, so an
+/// empty directory or a directory permission change goes unnoticed. Any
+/// error (ex: unreadable file, directory deeper than [DEFAULT_MAX_DEPTH])
+/// aborts the computation.
+pub fn tree_hash(root: &Path, algo: HashAlgo) -> io::Result<String> {
+    let mut acc = algo.hash_data(b"");
+
+    for (path, meta) in TreeWalker::new([root.to_path_buf()], DEFAULT_MAX_DEPTH) {
+        let meta = meta?;
+        let ft = meta.file_type();
+        let content = if ft.is_symlink() {
+            algo.hash_data(fs::read_link(&path)?.as_os_str().as_bytes())
+        } else if ft.is_file() {
+            let (f, st) = open_and_stat(&path)?;
+            // the entry got replaced since it has been walked
+            if !is_regular_file(&st) || st.st_ino != meta.ino() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("file changed while hashing tree: {}", path.display()),
+                ));
+            }
+            algo.hash_reader(f)?
+        } else if ft.is_block_device() || ft.is_char_device() {
+            algo.hash_data(meta.rdev().to_le_bytes())
+        } else {
+            String::new()
+        };
+
+        let rel = path.strip_prefix(root).unwrap_or(&path);
+        let mut record = acc.into_bytes();
+        record.push(0);
+        record.extend_from_slice(rel.as_os_str().as_bytes());
+        record.extend_from_slice(format!("\0{:o}\0{content}", meta.mode()).as_bytes());
+        acc = algo.hash_data(record);
+    }
+
+    Ok(acc)
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+
+    #[test]
+    fn test_walk() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("a/b/c")).unwrap();
+        fs::write(root.join("top"), b"").unwrap();
+        fs::write(root.join("a/b/mid"), b"").unwrap();
+        fs::write(root.join("a/b/c/deep"), b"").unwrap();
+        // a cycle which must not be followed
+        symlink(root, root.join("a/loop")).unwrap();
+
+        let all = TreeWalker::new([root.to_path_buf()], DEFAULT_MAX_DEPTH)
+            .map(|(p, m)| (p, m.unwrap()))
+            .collect::<Vec<_>>();
+        let paths = all.iter().map(|(p, _)| p.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                root.join("a/b/c/deep"),
+                root.join("a/b/mid"),
+                root.join("a/loop"),
+                root.join("top")
+            ]
+        );
+        assert!(all[2].1.file_type().is_symlink());
+
+        let shallow = TreeWalker::new([root.to_path_buf()], 1).collect::<Vec<_>>();
+        let paths = shallow.iter().map(|(p, _)| p.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![root.join("a/b"), root.join("a/loop"), root.join("top")]
+        );
+        // the directory not descended into is reported
+        assert!(shallow[0].1.is_err());
+
+        let missing = TreeWalker::new([root.join("missing")], 1).collect::<Vec<_>>();
+        assert_eq!(missing.len(), 1);
+        assert!(missing[0].1.is_err());
+    }
+
+    #[test]
+    fn test_tree_hash() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("cron.d");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("job"), b"* * * * * root true").unwrap();
+        fs::write(root.join("sub/other"), b"").unwrap();
+
+        let h = |r: &Path| tree_hash(r, HashAlgo::Sha256).unwrap();
+        let base = h(&root);
+        assert_eq!(base, h(&root));
+
+        // same content created in a different order at another
+        // location must give the same hash
+        let copy = dir.path().join("copy");
+        fs::create_dir_all(copy.join("sub")).unwrap();
+        fs::write(copy.join("sub/other"), b"").unwrap();
+        fs::write(copy.join("job"), b"* * * * * root true").unwrap();
+        for p in ["job", "sub/other"] {
+            let mode = fs::metadata(root.join(p)).unwrap().permissions();
+            fs::set_permissions(copy.join(p), mode).unwrap();
+        }
+        assert_eq!(base, h(&copy));
+
+        fs::write(root.join("new"), b"").unwrap();
+        let added = h(&root);
+        assert_ne!(base, added);
+
+        fs::write(root.join("job"), b"* * * * * root false").unwrap();
+        let modified = h(&root);
+        assert_ne!(added, modified);
+
+        fs::set_permissions(root.join("job"), fs::Permissions::from_mode(0o600)).unwrap();
+        let chmoded = h(&root);
+        assert_ne!(modified, chmoded);
+
+        fs::rename(root.join("new"), root.join("renamed")).unwrap();
+        assert_ne!(chmoded, h(&root));
+
+        symlink("/etc/passwd", root.join("link")).unwrap();
+        let linked = h(&root);
+        fs::remove_file(root.join("link")).unwrap();
+        symlink("/etc/shadow", root.join("link")).unwrap();
+        assert_ne!(linked, h(&root));
+
+        // changes too deep to be walked must not go unnoticed
+        let deep = (0..=DEFAULT_MAX_DEPTH).fold(root.clone(), |p, i| p.join(i.to_string()));
+        fs::create_dir_all(&deep).unwrap();
+        assert!(tree_hash(&root, HashAlgo::Sha256).is_err());
+    }
+
+    #[test]
+    fn test_tree_hash_special_files() {
+        use std::ffi::CString;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("file"), b"content").unwrap();
+        let base = tree_hash(root, HashAlgo::Sha256).unwrap();
+
+        // opening a FIFO without writer would block forever
+        let fifo = CString::new(root.join("fifo").as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+        let with_fifo = tree_hash(root, HashAlgo::Sha256).unwrap();
+        assert_ne!(base, with_fifo);
+        assert_eq!(with_fifo, tree_hash(root, HashAlgo::Sha256).unwrap());
+
+        // a regular file with the same name and mode is told apart
+        fs::remove_file(root.join("fifo")).unwrap();
+        fs::write(root.join("fifo"), b"").unwrap();
+        fs::set_permissions(
+            root.join("fifo"),
+            std::os::unix::fs::PermissionsExt::from_mode(0o644),
+        )
+        .unwrap();
+        assert_ne!(with_fifo, tree_hash(root, HashAlgo::Sha256).unwrap());
+    }
+}
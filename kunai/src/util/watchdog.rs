@@ -0,0 +1,185 @@
+//! This module implements a watchdog used to detect worker threads
+//! which stopped making progress (ex: stuck on a pathological file).
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use log::error;
+
+use super::ktime_get_ns;
+
+/// Action taken when the watched worker stalls. The duration
+/// passed to custom actions is the time since the last heartbeat.
+pub enum Action {
+    /// logs an error
+    Log,
+    /// aborts the process, letting the service manager restart it
+    Abort,
+    Custom(Box<dyn FnMut(Duration) + Send>),
+}
+
+impl Action {
+    fn fire(&mut self, name: &str, stalled: Duration) {
+        match self {
+            Self::Log => error!("watchdog: {name} made no progress for {stalled:?}"),
+            Self::Abort => {
+                error!("watchdog: {name} made no progress for {stalled:?}, aborting");
+                std::process::abort()
+            }
+            Self::Custom(f) => f(stalled),
+        }
+    }
+}
+
+#[inline]
+fn now_ns() -> u64 {
+    // CLOCK_MONOTONIC cannot fail with a valid timespec
+    ktime_get_ns().unwrap_or_default()
+}
+
+/// Handle used by the watched worker to signal it is alive
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    last: Arc<AtomicU64>,
+}
+
+impl Heartbeat {
+    /// Must be called at every iteration of the worker loop
+    #[inline(always)]
+    pub fn beat(&self) {
+        self.last.store(now_ns(), Ordering::Relaxed);
+    }
+}
+
+/// Watchdog firing an [Action] when no heartbeat has been received within
+/// a timeout. The action fires once per stall, the watchdog re-arming when
+/// heartbeats resume. The watchdog thread stops when the watchdog is dropped.
+pub struct Watchdog {
+    heartbeat: Heartbeat,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    pub fn new<S: Into<String>>(name: S, timeout: Duration, mut action: Action) -> Self {
+        let heartbeat = Heartbeat {
+            last: Arc::new(AtomicU64::new(now_ns())),
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let name = name.into();
+
+        let hb = heartbeat.clone();
+        let st = stop.clone();
+        let timeout_ns = timeout.as_nanos() as u64;
+        // checking more often than timeout bounds detection latency
+        let tick = (timeout / 4).max(Duration::from_millis(1));
+
+        let handle = thread::spawn(move || {
+            let mut fired = false;
+            loop {
+                // unparked on drop so that we do not wait for a full tick
+                thread::park_timeout(tick);
+                if st.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                let elapsed = now_ns().saturating_sub(hb.last.load(Ordering::Relaxed));
+                if elapsed < timeout_ns {
+                    fired = false;
+                } else if !fired {
+                    action.fire(&name, Duration::from_nanos(elapsed));
+                    fired = true;
+                }
+            }
+        });
+
+        Self {
+            heartbeat,
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// Returns a heartbeat handle to pass to the worker
+    #[inline(always)]
+    pub fn heartbeat(&self) -> Heartbeat {
+        self.heartbeat.clone()
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(h) = self.handle.take() {
+            h.thread().unpark();
+            let _ = h.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn counting_watchdog(timeout: Duration) -> (Watchdog, Arc<AtomicU64>) {
+        let fired = Arc::new(AtomicU64::new(0));
+        let f = fired.clone();
+        let wd = Watchdog::new(
+            "test",
+            timeout,
+            Action::Custom(Box::new(move |_| {
+                f.fetch_add(1, Ordering::Relaxed);
+            })),
+        );
+        (wd, fired)
+    }
+
+    #[test]
+    fn test_healthy_worker() {
+        let (wd, fired) = counting_watchdog(Duration::from_millis(200));
+        let hb = wd.heartbeat();
+
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_millis(600) {
+            hb.beat();
+            thread::sleep(Duration::from_millis(10));
+        }
+        drop(wd);
+        assert_eq!(fired.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_stalled_worker() {
+        let (wd, fired) = counting_watchdog(Duration::from_millis(50));
+        let hb = wd.heartbeat();
+        hb.beat();
+
+        // worker stalls, action must fire only once for this stall
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(fired.load(Ordering::Relaxed), 1);
+
+        // worker recovers then stalls again
+        hb.beat();
+        thread::sleep(Duration::from_millis(300));
+        drop(wd);
+        assert_eq!(fired.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_prompt_drop() {
+        let (wd, _) = counting_watchdog(Duration::from_secs(60));
+        // let the watchdog thread start waiting for its tick
+        thread::sleep(Duration::from_millis(50));
+
+        let start = Instant::now();
+        drop(wd);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}